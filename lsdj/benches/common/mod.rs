@@ -0,0 +1,12 @@
+use lsdj::song::SongMemory;
+
+/// Generate a song that compresses badly, by filling the first `len` bytes with noise
+pub fn noisy_song(len: usize) -> SongMemory {
+    let mut song = SongMemory::new();
+    let mut state = 1u32;
+    for byte in &mut song.as_mut_slice()[..len] {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        *byte = (state >> 16) as u8;
+    }
+    song
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::noisy_song;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lsdj::{
+    fs::{Filesystem, Index},
+    lsdsng::LsdSng,
+    name::Name,
+    song::SongMemory,
+};
+
+fn compress(c: &mut Criterion) {
+    let name = Name::<8>::from_bytes(b"BENCH").unwrap();
+
+    let empty = SongMemory::new();
+    c.bench_function("compress empty song", |b| {
+        b.iter(|| LsdSng::from_song(name.clone(), 0, black_box(&empty)).unwrap())
+    });
+
+    let noisy = noisy_song(0x2000);
+    c.bench_function("compress noisy song", |b| {
+        b.iter(|| LsdSng::from_song(name.clone(), 0, black_box(&noisy)).unwrap())
+    });
+
+    // Fill all 32 slots, each song taking up a few blocks
+    let songs: Vec<_> = (0..Filesystem::FILES_CAPACITY)
+        .map(|index| noisy_song(0x200 + index * 0x10))
+        .collect();
+    c.bench_function("insert full filesystem", |b| {
+        b.iter(|| {
+            let mut filesystem = Filesystem::new();
+            for (index, song) in songs.iter().enumerate() {
+                filesystem
+                    .insert_file(Index::new(index as u8), &name, 0, black_box(song))
+                    .unwrap();
+            }
+            filesystem
+        })
+    });
+}
+
+criterion_group!(benches, compress);
+criterion_main!(benches);
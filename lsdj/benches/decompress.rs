@@ -0,0 +1,60 @@
+mod common;
+
+use common::noisy_song;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lsdj::{
+    fs::{File, Filesystem, Index},
+    lsdsng::LsdSng,
+    name::Name,
+    serde::decompress_block,
+    song::SongMemory,
+};
+use std::io::Cursor;
+
+fn decompress(c: &mut Criterion) {
+    let name = Name::<8>::from_bytes(b"BENCH").unwrap();
+
+    let empty = LsdSng::from_song(name.clone(), 0, &SongMemory::new()).unwrap();
+    c.bench_function("decompress empty block", |b| {
+        let mut memory = vec![0; SongMemory::LEN];
+        b.iter(|| {
+            decompress_block(
+                black_box(empty.blocks()),
+                Cursor::new(memory.as_mut_slice()),
+            )
+            .unwrap()
+        })
+    });
+
+    c.bench_function("decompress empty song", |b| {
+        b.iter(|| black_box(&empty).decompress().unwrap())
+    });
+
+    let noisy = LsdSng::from_song(name.clone(), 0, &noisy_song(0x2000)).unwrap();
+    c.bench_function("decompress noisy song", |b| {
+        b.iter(|| black_box(&noisy).decompress().unwrap())
+    });
+
+    // A filesystem with every file slot filled, as far as the blocks allow
+    let mut filesystem = Filesystem::new();
+    let song = noisy_song(0x400);
+    for index in 0..Filesystem::FILES_CAPACITY as u8 {
+        if filesystem
+            .insert_file(Index::new(index), &name, 0, &song)
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    c.bench_function("decompress full filesystem", |b| {
+        b.iter(|| {
+            for file in black_box(&filesystem).files().flatten() {
+                file.decompress().unwrap();
+            }
+        })
+    });
+}
+
+criterion_group!(benches, decompress);
+criterion_main!(benches);
@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lsdj::{
+    fs::Filesystem,
+    serde::{decompress_block, BlockJumps, Decompressor},
+    song::SongMemory,
+};
+use std::io::{Cursor, Read};
+
+fuzz_target!(|data: &[u8]| {
+    // Decompressing a single block into song memory can fail, but never panic
+    let mut memory = [0; SongMemory::LEN];
+    let _ = decompress_block(Cursor::new(data), Cursor::new(memory.as_mut_slice()));
+
+    // The streaming decompressor never produces more than fits in song memory
+    for jumps in [
+        BlockJumps::Linear,
+        BlockJumps::FollowJumps {
+            block_len: Filesystem::BLOCK_LEN,
+        },
+    ] {
+        let mut plain = Vec::new();
+        let _ = Decompressor::new(Cursor::new(data), jumps).read_to_end(&mut plain);
+        assert!(plain.len() <= SongMemory::LEN);
+    }
+});
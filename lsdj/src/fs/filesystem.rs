@@ -1,12 +1,14 @@
 use super::{File, FileToLsdSngError};
 use crate::{
     lsdsng::LsdSng,
-    name::{FromBytesError, Name},
-    serde::{compress_block, decompress_block, CompressBlockError, End},
-    song::{self, SongMemory},
+    name::{self, Name},
+    serde::{
+        compress_from_slice, decompress_block, BlockJumps, CompressBlockError, Decompressor, End,
+    },
+    song::{self, SongMemory, Validation},
 };
 use std::{
-    collections::HashMap,
+    fmt,
     io::{self, Cursor, Read, Seek, SeekFrom, Write},
     mem::replace,
     ops::Range,
@@ -16,12 +18,13 @@ use thiserror::Error;
 /// A 5-bit (0 - 32) index into the [`Filesystem`]
 pub type Index = ux::u5;
 
-const FILE_VERSIONS_RANGE: Range<usize> = 0x0100..0x0120;
-const CHECK_RANGE: Range<usize> = 0x013E..0x0140;
+pub(crate) const FILE_NAMES_RANGE: Range<usize> = 0x0000..0x0100;
+pub(crate) const FILE_VERSIONS_RANGE: Range<usize> = 0x0100..0x0120;
+pub(crate) const CHECK_RANGE: Range<usize> = 0x013E..0x0140;
 const CHECK_VALUE: [u8; 2] = [0x6A, 0x6B];
-const ACTIVE_FILE_INDEX: usize = 0x0140;
+pub(crate) const ACTIVE_FILE_INDEX: usize = 0x0140;
 const NO_ACTIVE_FILE: u8 = 0xFF;
-const ALLOC_TABLE_RANGE: Range<usize> = 0x0141..0x0200;
+pub(crate) const ALLOC_TABLE_RANGE: Range<usize> = 0x0141..0x0200;
 const UNUSED_BLOCK: u8 = 0xFF;
 
 /// A filesystem for storing compressed [`File`]'s
@@ -49,11 +52,17 @@ impl Filesystem {
     /// The amount of blocks available in the filesystem
     pub const BLOCKS_CAPACITY: usize = 0xC0;
 
+    /// The amount of blocks available for storing files
+    ///
+    /// This is one less than [`BLOCKS_CAPACITY`](Self::BLOCKS_CAPACITY), because the first block
+    /// holds the file names, versions and allocation table.
+    pub const FILE_BLOCKS_CAPACITY: usize = Self::BLOCKS_CAPACITY - 1;
+
     /// The length in bytes of a compression block
-    pub(crate) const BLOCK_LEN: usize = 0x200;
+    pub const BLOCK_LEN: usize = 0x200;
 
     /// The length in bytes of the entire filesystem
-    const LEN: usize = Self::BLOCK_LEN * Self::BLOCKS_CAPACITY;
+    pub const LEN: usize = Self::BLOCK_LEN * Self::BLOCKS_CAPACITY;
 
     /// Construct a valid, but empty filesystem
     ///
@@ -86,6 +95,33 @@ impl Filesystem {
         Ok(Self { bytes })
     }
 
+    /// Deserialize a [`Filesystem`] from a slice of bytes
+    ///
+    /// The slice needs to be exactly as long as the filesystem itself.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromBytesError> {
+        let bytes: [u8; Self::LEN] = match bytes.len() {
+            Self::LEN => bytes.try_into().unwrap(),
+            got if got < Self::LEN => {
+                return Err(FromBytesError::TooShort {
+                    expected: Self::LEN,
+                    got,
+                })
+            }
+            got => {
+                return Err(FromBytesError::TooLong {
+                    expected: Self::LEN,
+                    got,
+                })
+            }
+        };
+
+        if bytes[CHECK_RANGE] != CHECK_VALUE {
+            return Err(FromBytesError::InitializationCheckIncorrect);
+        }
+
+        Ok(Self { bytes })
+    }
+
     // Serialize the [`Filesystem`] to an arbitrary I/O writer
     pub fn to_writer<W>(&self, mut writer: W) -> Result<(), io::Error>
     where
@@ -94,10 +130,23 @@ impl Filesystem {
         writer.write_all(&self.bytes)
     }
 
+    /// Access the raw bytes that make up the filesystem
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Copy the raw bytes that make up the filesystem into a [`Vec`]
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.bytes.to_vec()
+    }
+
     /// Is any compessed song data stored for the file slot at this index?
-    fn is_file_in_use(&self, index: Index) -> bool {
+    ///
+    /// This is the same check [`Filesystem::file()`] does, and can be used to find a free
+    /// slot before calling [`Filesystem::insert_file()`].
+    pub fn is_file_in_use(&self, index: Index) -> bool {
         let index = index.into();
-        self.alloc_table().iter().any(|block| *block == index)
+        self.alloc_table().contains(&index)
     }
 
     /// Retrieve a [`File`] [`Entry`] from the filesystem
@@ -107,7 +156,7 @@ impl Filesystem {
     /// is empty.
     ///
     /// The resulting [`Entry`] can be queried for [`Name`], version and [`SongMemory`].
-    pub fn file(&self, index: Index) -> Option<Entry> {
+    pub fn file(&self, index: Index) -> Option<Entry<'_>> {
         if self.is_file_in_use(index) {
             Some(Entry { fs: self, index })
         } else {
@@ -115,11 +164,79 @@ impl Filesystem {
         }
     }
 
+    /// Retrieve a mutable [`File`] [`EntryMut`] from the filesystem
+    ///
+    /// Like [`Filesystem::file()`], this returns [`None`] if the file slot is empty.
+    ///
+    /// The resulting [`EntryMut`] can be used to change the [`Name`] and version of
+    /// the file in place, without touching (or recompressing) the song data.
+    pub fn file_mut(&mut self, index: Index) -> Option<EntryMut<'_>> {
+        if self.is_file_in_use(index) {
+            Some(EntryMut { fs: self, index })
+        } else {
+            None
+        }
+    }
+
+    /// Change the [`Name`] of a file in place
+    ///
+    /// This is a shorthand for [`EntryMut::set_name()`], returning `false` (without changing
+    /// anything) if the file slot is empty.
+    pub fn rename_file(&mut self, index: Index, name: &Name<8>) -> bool {
+        match self.file_mut(index) {
+            Some(mut file) => {
+                file.set_name(name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Change the version of a file in place
+    ///
+    /// This is a shorthand for [`EntryMut::set_version()`], returning `false` (without changing
+    /// anything) if the file slot is empty.
+    pub fn set_file_version(&mut self, index: Index, version: u8) -> bool {
+        match self.file_mut(index) {
+            Some(mut file) => {
+                file.set_version(version);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Iterate over all the [`File`]'s in the filesystem
-    pub fn files(&self) -> Entries {
+    pub fn files(&self) -> Entries<'_> {
         Entries { fs: self, index: 0 }
     }
 
+    /// Iterate over only the file slots that are in use, together with their indices
+    ///
+    /// Unlike [`Filesystem::files()`], empty slots are skipped entirely.
+    pub fn occupied_files(&self) -> OccupiedEntries<'_> {
+        let mut in_use = [false; Self::FILES_CAPACITY];
+        for file in self.alloc_table() {
+            if let Some(in_use) = in_use.get_mut(*file as usize) {
+                *in_use = true;
+            }
+        }
+
+        OccupiedEntries {
+            fs: self,
+            in_use,
+            front: 0,
+            back: Self::FILES_CAPACITY as u8,
+        }
+    }
+
+    /// Iterate over the indices of the file slots that aren't in use, in ascending order
+    pub fn free_slots(&self) -> impl Iterator<Item = Index> + '_ {
+        (0..Self::FILES_CAPACITY as u8)
+            .map(Index::new)
+            .filter(|index| !self.is_file_in_use(*index))
+    }
+
     /// Insert a new file into the filesystem
     ///
     /// This function tries to compress the provided song memory into the filesystem. It can
@@ -127,71 +244,179 @@ impl Filesystem {
     /// it won't insert anything at all.
     ///
     /// If a file already existed at this index, the old file is returned as an [`LsdSng`].
+    ///
+    /// Free blocks are used in ascending order, see [`Filesystem::insert_file_with_options()`]
+    /// for other allocation strategies.
     pub fn insert_file(
         &mut self,
         file: Index,
         name: &Name<8>,
         version: u8,
         song: &SongMemory,
-    ) -> Result<Option<LsdSng>, CompressBlockError> {
-        // First, compress the song into temporary blocks to figure out how many we need
-        let blocks = {
-            // Figure out which blocks we *can* use
-            let mut free_blocks = self
-                .alloc_table()
-                .iter()
-                .enumerate()
-                .filter_map(|(index, f)| {
-                    if *f == UNUSED_BLOCK || *f == file.into() {
-                        Some(index as u8 + 1)
-                    } else {
-                        None
-                    }
-                })
-                .peekable();
-
-            // Create a reader over the song memory and a hashmap to store the blocks
-            let mut reader = Cursor::new(song.as_slice());
-            let mut blocks = HashMap::new();
+    ) -> Result<Option<LsdSng>, InsertFileError> {
+        self.insert_file_with_options(file, name, version, song, InsertOptions::default())
+    }
 
-            // Loop until we've reached end-of-file
-            // If we run out of space, compress_block() will return an error and this will propagate upward
-            loop {
-                let mut block = [0; Self::BLOCK_LEN];
-                let index = free_blocks.next().ok_or(CompressBlockError::NoBlockLeft)?;
-                let end = compress_block(&mut reader, Cursor::new(block.as_mut_slice()), || {
-                    free_blocks.peek().copied()
-                })?;
+    /// Insert a new file into the filesystem, choosing how its blocks are allocated
+    ///
+    /// This works like [`Filesystem::insert_file()`]. Blocks of a file already existing at this
+    /// index count as free, since they're being replaced.
+    pub fn insert_file_with_options(
+        &mut self,
+        file: Index,
+        name: &Name<8>,
+        version: u8,
+        song: &SongMemory,
+        options: InsertOptions,
+    ) -> Result<Option<LsdSng>, InsertFileError> {
+        // Make sure we don't touch anything if the filesystem is already corrupt
+        self.check_alloc_table()?;
+
+        // First, figure out which blocks we *can* use: the free ones and those of the file
+        // being replaced, whose data is kept aside in case compression fails
+        let usable: Vec<u8> = self
+            .alloc_table()
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| **f == UNUSED_BLOCK || **f == u8::from(file))
+            .map(|(index, _)| index as u8 + 1)
+            .collect();
+
+        // Do a dry run of the compression, so a song that doesn't fit never touches any block
+        let needed = song.compressed_block_count()?;
+        if needed > usable.len() {
+            return Err(InsertFileError::NotEnoughBlocks {
+                needed,
+                free: usable.len(),
+            });
+        }
+        let blocks = allocate(usable, options.allocation, needed)?;
 
-                blocks.insert(index, block);
+        let old_blocks = self.file_blocks(file);
+        let old_bytes: Vec<u8> = old_blocks
+            .iter()
+            .flat_map(|block| self.block(*block))
+            .copied()
+            .collect();
 
-                if end == End::EndOfFile {
-                    break;
+        // Free blocks can still hold stale bytes, so keep aside everything that might be written
+        let snapshot: Vec<u8> = blocks
+            .iter()
+            .flat_map(|block| self.block(*block))
+            .copied()
+            .collect();
+
+        // Second, compress the song straight into those blocks, in ascending order
+        let mut handed_out = 0;
+        let result = compress_from_slice(
+            song.as_slice(),
+            Cursor::new(self.bytes.as_mut_slice()),
+            || {
+                let block = blocks.get(handed_out).copied();
+                handed_out += block.is_some() as usize;
+                block
+            },
+        );
+
+        let count = match result {
+            Ok(count) => count,
+            Err(error) => {
+                // Undo everything by restoring the blocks written to, the old file's included
+                for (block, bytes) in blocks[..handed_out]
+                    .iter()
+                    .zip(snapshot.chunks(Self::BLOCK_LEN))
+                {
+                    self.block_mut(*block).copy_from_slice(bytes);
                 }
-            }
 
-            blocks
+                return Err(error.into());
+            }
         };
 
-        // Second, remove the old file if necessary
-        let old = self.remove_file(file);
+        // Third, replace the old file (but keep it active, it's being replaced)
+        let old = (!old_blocks.is_empty()).then(|| {
+            let name = Name::from_bytes(self.file_name(file)).unwrap_or_default();
+            let version = self.bytes[FILE_VERSIONS_RANGE.start + u8::from(file) as usize];
+            LsdSng::from_blocks(name, version, old_bytes)
+        });
+
+        for block in &old_blocks {
+            if !blocks[..count].contains(block) {
+                self.block_mut(*block).fill(0);
+                self.alloc_table_mut()[*block as usize - 1] = UNUSED_BLOCK;
+            }
+        }
+
+        for block in &blocks[..count] {
+            self.alloc_table_mut()[*block as usize - 1] = file.into();
+        }
 
-        // Third, do the actual import
         self.file_name_mut(file).copy_from_slice(name.bytes());
         *self.file_version_mut(file) = version;
 
-        for (index, block) in blocks {
-            self.alloc_table_mut()[index as usize - 1] = file.into();
-            self.block_mut(index).copy_from_slice(&block);
-        }
-
         Ok(old)
     }
 
     /// Remove a file from the filesystem
     ///
-    /// Returns either the file, or [`None`] if no file at that index existed
+    /// Returns either the file, or [`None`] if no file at that index existed.
+    ///
+    /// If the removed file was the [active file](Filesystem::active_file), the active file is
+    /// cleared, since LSDJ would otherwise show a song that no longer exists as the current one.
+    /// Call [`Filesystem::set_active_file()`] afterwards to point it somewhere else.
     pub fn remove_file(&mut self, index: Index) -> Option<LsdSng> {
+        let file = self.take_file(index)?;
+
+        if self.active_file() == Some(index) {
+            self.set_active_file(None);
+        }
+
+        Some(file)
+    }
+
+    /// Move a file to another slot, without recompressing it
+    ///
+    /// The name, version and blocks of the file are handed over to the new slot, and the
+    /// [active file](Filesystem::active_file) follows the file if it pointed at `from`.
+    ///
+    /// Returns `false` (without changing anything) if there is no file at `from`, or if
+    /// `to` is already in use by another file.
+    pub fn move_file(&mut self, from: Index, to: Index) -> bool {
+        if !self.is_file_in_use(from) {
+            return false;
+        }
+
+        if from == to {
+            return true;
+        }
+
+        if self.is_file_in_use(to) {
+            return false;
+        }
+
+        let mut name = [0; 8];
+        name.copy_from_slice(self.file_name(from));
+        self.file_name_mut(from).fill(0);
+        self.file_name_mut(to).copy_from_slice(&name);
+
+        let version = replace(self.file_version_mut(from), 0);
+        *self.file_version_mut(to) = version;
+
+        for file in self.alloc_table_mut() {
+            if *file == u8::from(from) {
+                *file = u8::from(to);
+            }
+        }
+
+        if self.active_file() == Some(from) {
+            self.set_active_file(Some(to));
+        }
+
+        true
+    }
+
+    /// Remove a file from the filesystem, without touching the active file
+    fn take_file(&mut self, index: Index) -> Option<LsdSng> {
         if self.is_file_in_use(index) {
             let name = {
                 let bytes = self.file_name_mut(index);
@@ -211,7 +436,7 @@ impl Filesystem {
                 self.alloc_table_mut()[(block - 1) as usize] = UNUSED_BLOCK;
             }
 
-            Some(LsdSng::new(name, version, blocks))
+            Some(LsdSng::from_blocks(name, version, blocks))
         } else {
             None
         }
@@ -223,34 +448,266 @@ impl Filesystem {
     /// an actively edited verson of one of the files in the filesystem.
     pub fn active_file(&self) -> Option<Index> {
         match self.bytes[ACTIVE_FILE_INDEX] {
-            NO_ACTIVE_FILE => None,
-            index => Some(Index::new(index)),
+            index if (index as usize) < Self::FILES_CAPACITY => Some(Index::new(index)),
+            _ => None,
         }
     }
 
-    /// Return the number of blocks in use
+    /// Set (or clear) the index of the file currently loaded into working memory
+    pub fn set_active_file(&mut self, index: Option<Index>) {
+        self.bytes[ACTIVE_FILE_INDEX] = match index {
+            Some(index) => index.into(),
+            None => NO_ACTIVE_FILE,
+        };
+    }
+
+    /// Return the number of blocks in use by files
+    ///
+    /// Orphaned blocks aren't included, see [`Filesystem::blocks_orphaned_count()`].
     pub fn blocks_used_count(&self) -> usize {
         self.alloc_table()
             .iter()
-            .filter(|block| **block != UNUSED_BLOCK)
+            .filter(|file| (**file as usize) < Self::FILES_CAPACITY)
+            .count()
+    }
+
+    /// Return the number of blocks that belong to a file slot that doesn't exist
+    ///
+    /// These blocks are the result of a corrupted allocation table. They're invisible to every
+    /// file, but aren't free either, so they take up space until they're
+    /// [reclaimed](Filesystem::reclaim_orphan_blocks).
+    pub fn blocks_orphaned_count(&self) -> usize {
+        self.alloc_table()
+            .iter()
+            .filter(|file| is_orphan(**file))
+            .count()
+    }
+
+    /// Free every block that belongs to a file slot that doesn't exist
+    ///
+    /// The blocks are zeroed out and marked unused, like those of a removed file. Every file
+    /// that was readable before stays readable. Returns the number of blocks that were freed.
+    pub fn reclaim_orphan_blocks(&mut self) -> usize {
+        let orphans: Vec<_> = self
+            .alloc_table()
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| is_orphan(**file))
+            .map(|(index, _)| index as u8 + 1)
+            .collect();
+
+        for block in &orphans {
+            self.block_mut(*block).fill(0);
+            self.alloc_table_mut()[*block as usize - 1] = UNUSED_BLOCK;
+        }
+
+        orphans.len()
+    }
+
+    /// Return the number of blocks taken up by a specific file, or [`None`] if the slot is empty
+    pub fn file_block_count(&self, index: Index) -> Option<usize> {
+        self.file(index).map(|file| file.block_count())
+    }
+
+    /// Return the number of blocks still available for storing files
+    pub fn blocks_free_count(&self) -> usize {
+        self.alloc_table()
+            .iter()
+            .filter(|block| **block == UNUSED_BLOCK)
             .count()
     }
 
+    /// Rearrange the blocks so that every file is stored contiguously
+    ///
+    /// Repeatedly inserting and removing files can leave the blocks of a single file scattered
+    /// across the filesystem, interleaved with those of other files. LSDJ doesn't mind, but it
+    /// does make for noisy diffs between save files. This function moves all blocks so that every
+    /// file occupies an ascending run of blocks, with the files themselves ordered by index. The
+    /// block jump commands inside the compressed data are updated accordingly.
+    ///
+    /// Names, versions and the active file are left untouched, as is the decompressed song data.
+    /// Blocks that end up unused are zeroed out. Returns the number of blocks that were moved.
+    ///
+    /// Only the blocks reached by following a file's block chain are kept, so orphaned blocks and
+    /// blocks a file owns but never jumps to would be lost. That's why this refuses to touch a
+    /// filesystem for which [`check()`](Self::check) reports any problems, leaving it unchanged.
+    pub fn defragment(&mut self) -> Result<usize, DefragmentError> {
+        let problems = self.check();
+        if !problems.is_empty() {
+            return Err(DefragmentError::Inconsistent(problems));
+        }
+
+        let mut bytes = [0; Self::LEN];
+        bytes[..Self::BLOCK_LEN].copy_from_slice(self.block(0));
+        bytes[ALLOC_TABLE_RANGE].fill(UNUSED_BLOCK);
+
+        let mut destination = 1;
+        let mut moved = 0;
+
+        for file in 0..Self::FILES_CAPACITY as u8 {
+            let file = Index::new(file);
+            if !self.is_file_in_use(file) {
+                continue;
+            }
+
+            for (block, jump) in self.block_chain(file)? {
+                let range = Self::block_range(destination);
+                bytes[range.clone()].copy_from_slice(self.block(block));
+
+                // The jump command needs to point to the block that directly follows
+                if let Some(offset) = jump {
+                    bytes[range.start + offset] = destination + 1;
+                }
+
+                bytes[ALLOC_TABLE_RANGE][destination as usize - 1] = file.into();
+
+                if block != destination {
+                    moved += 1;
+                }
+                destination += 1;
+            }
+        }
+
+        self.bytes = bytes;
+
+        Ok(moved)
+    }
+
+    /// Check the filesystem for inconsistencies
+    ///
+    /// LSDJ itself (and this crate) assume the filesystem is well-formed, but save files that were
+    /// corrupted (for example during a failed transfer) can contain all kinds of inconsistencies.
+    /// This function walks the block allocation table and the block chain of every file, and
+    /// reports every problem it finds. It never modifies the filesystem, and never panics, no
+    /// matter what bytes the filesystem consists of.
+    ///
+    /// An empty list means the filesystem is consistent.
+    pub fn check(&self) -> Vec<Integrity> {
+        let mut problems = Vec::new();
+
+        for (index, file) in self.alloc_table().iter().enumerate() {
+            if is_orphan(*file) {
+                problems.push(Integrity::InvalidAllocEntry {
+                    block: index as u8 + 1,
+                    file: *file,
+                });
+            }
+        }
+
+        match self.bytes[ACTIVE_FILE_INDEX] {
+            NO_ACTIVE_FILE => (),
+            index if index as usize >= Self::FILES_CAPACITY => {
+                problems.push(Integrity::InvalidActiveFile(index))
+            }
+            _ => (),
+        }
+
+        for file in (0..Self::FILES_CAPACITY as u8).map(Index::new) {
+            if self.is_file_in_use(file) {
+                if let Err(error) = Name::<8>::from_bytes(self.file_name(file)) {
+                    problems.push(Integrity::InvalidName { file, error });
+                }
+
+                self.check_block_chain(file, &mut problems);
+            } else {
+                let version = self.bytes[FILE_VERSIONS_RANGE.start + u8::from(file) as usize];
+                if version != 0 {
+                    problems.push(Integrity::OrphanedVersion { file, version });
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Follow the block chain of a single file, and report any problems along the way
+    fn check_block_chain(&self, file: Index, problems: &mut Vec<Integrity>) {
+        let owned = self.file_blocks(file);
+        let mut visited = Vec::with_capacity(owned.len());
+        let mut writer = Cursor::new(Vec::with_capacity(SongMemory::LEN));
+
+        let mut block = owned[0];
+        loop {
+            visited.push(block);
+
+            let mut reader = Cursor::new(self.block(block));
+            let end = match decompress_block(&mut reader, &mut writer) {
+                Ok(end) => end,
+                Err(_) => {
+                    problems.push(Integrity::UnterminatedBlock { file, block });
+                    return;
+                }
+            };
+
+            let actual = writer.get_ref().len();
+            if actual > SongMemory::LEN {
+                problems.push(Integrity::DecompressedSizeMismatch {
+                    file,
+                    expected: SongMemory::LEN,
+                    actual,
+                });
+                return;
+            }
+
+            match end {
+                End::EndOfFile => {
+                    if actual != SongMemory::LEN {
+                        problems.push(Integrity::DecompressedSizeMismatch {
+                            file,
+                            expected: SongMemory::LEN,
+                            actual,
+                        });
+                    }
+                    break;
+                }
+                End::JumpToBlock(to) => {
+                    if to == 0 || to as usize >= Self::BLOCKS_CAPACITY || !owned.contains(&to) {
+                        problems.push(Integrity::JumpToForeignBlock {
+                            file,
+                            from: block,
+                            to,
+                        });
+                        return;
+                    }
+
+                    if visited.contains(&to) {
+                        problems.push(Integrity::BlockVisitedTwice { file, block: to });
+                        return;
+                    }
+
+                    block = to;
+                }
+            }
+        }
+
+        for block in owned {
+            if !visited.contains(&block) {
+                problems.push(Integrity::UnreachableBlock { file, block });
+            }
+        }
+    }
+
     /// Decompress a file starting at a specific block
-    fn decompress(&self, block: u8) -> Result<SongMemory, song::FromReaderError> {
+    fn decompress(
+        &self,
+        block: u8,
+        validation: Validation,
+    ) -> Result<SongMemory, song::FromReaderError> {
         let mut reader = Cursor::new(&self.bytes);
         reader.seek(SeekFrom::Start(Self::block_range(block).start as u64))?;
 
-        let mut memory = [0; SongMemory::LEN];
-        let mut writer = Cursor::new(memory.as_mut_slice());
+        let jumps = BlockJumps::FollowJumps {
+            block_len: Self::BLOCK_LEN,
+        };
 
-        while let End::JumpToBlock(block) = decompress_block(&mut reader, &mut writer)? {
-            reader.seek(SeekFrom::Start(Self::block_range(block).start as u64))?;
-        }
+        let mut memory = Vec::with_capacity(SongMemory::LEN);
+        Decompressor::new(reader, jumps)
+            .read_to_end(&mut memory)
+            .map_err(song::FromReaderError::from_decompression)?;
 
-        assert_eq!(writer.stream_position()?, SongMemory::LEN as u64);
+        song::FromReaderError::check_decompressed_size(memory.len() as u64)?;
 
-        SongMemory::from_reader(Cursor::new(memory))
+        Ok(SongMemory::from_bytes_with_validation(&memory, validation)?)
     }
 
     /// What's the byte range for a given block in the filesystem?
@@ -274,6 +731,17 @@ impl Filesystem {
         &self.bytes[ALLOC_TABLE_RANGE]
     }
 
+    /// Check that every block in the allocation table is either unused or belongs to a valid file slot
+    fn check_alloc_table(&self) -> Result<(), InsertFileError> {
+        match self.alloc_table().iter().position(|file| is_orphan(*file)) {
+            Some(index) => Err(InsertFileError::CorruptAllocTable {
+                block: index as u8 + 1,
+                file: self.alloc_table()[index],
+            }),
+            None => Ok(()),
+        }
+    }
+
     /// Access the part of block 0 that represents the block allocation table
     fn alloc_table_mut(&mut self) -> &mut [u8] {
         &mut self.bytes[ALLOC_TABLE_RANGE]
@@ -281,13 +749,13 @@ impl Filesystem {
 
     /// Retrieve the bytes for a given file
     fn file_name(&self, file: Index) -> &[u8] {
-        let offset = u8::from(file) as usize * 8;
+        let offset = FILE_NAMES_RANGE.start + u8::from(file) as usize * 8;
         &self.bytes[offset..offset + 8]
     }
 
     /// Retrieve the bytes for a given file
     fn file_name_mut(&mut self, file: Index) -> &mut [u8] {
-        let offset = u8::from(file) as usize * 8;
+        let offset = FILE_NAMES_RANGE.start + u8::from(file) as usize * 8;
         &mut self.bytes[offset..offset + 8]
     }
 
@@ -299,7 +767,7 @@ impl Filesystem {
 
     /// Retrieve the indices of the blocks for a specific file
     fn file_blocks(&self, file: Index) -> Vec<u8> {
-        let file = file.into();
+        let file = u8::from(file);
         self.alloc_table()
             .iter()
             .enumerate()
@@ -312,6 +780,196 @@ impl Filesystem {
             })
             .collect()
     }
+
+    /// Follow the block jumps for a specific file
+    ///
+    /// This returns the blocks in the order in which they are decompressed, together with the
+    /// offset (within the block) of the byte containing the jump destination, if there is one.
+    fn block_chain(&self, file: Index) -> io::Result<Vec<(u8, Option<usize>)>> {
+        let mut chain = Vec::new();
+        let mut block = match self.file_blocks(file).first() {
+            Some(block) => *block,
+            None => return Ok(chain),
+        };
+
+        let mut memory = [0; SongMemory::LEN];
+        let mut writer = Cursor::new(memory.as_mut_slice());
+
+        loop {
+            let mut reader = Cursor::new(self.block(block));
+            match decompress_block(&mut reader, &mut writer)? {
+                End::JumpToBlock(next) => {
+                    chain.push((block, Some(reader.position() as usize - 1)));
+                    block = next;
+                }
+                End::EndOfFile => {
+                    chain.push((block, None));
+                    return Ok(chain);
+                }
+            }
+
+            if chain.len() == Self::BLOCKS_CAPACITY {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The block chain never reaches the end of the file",
+                ));
+            }
+        }
+    }
+}
+
+/// Pick the blocks to compress a song into, in order, out of the usable ones
+///
+/// `needed` is the number of blocks the compressed song takes up, which fits in `usable`.
+fn allocate(
+    usable: Vec<u8>,
+    allocation: Allocation,
+    needed: usize,
+) -> Result<Vec<u8>, InsertFileError> {
+    match allocation {
+        Allocation::FirstFit => Ok(usable[..needed].to_vec()),
+        Allocation::Contiguous => runs(&usable, needed)
+            .next()
+            .map(|run| run[..needed].to_vec())
+            .ok_or(InsertFileError::NoContiguousBlocks { needed }),
+        Allocation::BestFit => {
+            let best = runs(&usable, needed)
+                .min_by_key(|run| run.len())
+                .map(|run| run[..needed].to_vec());
+            Ok(best.unwrap_or_else(|| usable[..needed].to_vec()))
+        }
+    }
+}
+
+/// Iterate over the runs of consecutive blocks that are at least `needed` blocks long
+fn runs(blocks: &[u8], needed: usize) -> impl Iterator<Item = &[u8]> {
+    blocks
+        .chunk_by(|a, b| *b == *a + 1)
+        .filter(move |run| run.len() >= needed)
+}
+
+/// Does an allocation table entry point to a file slot that doesn't exist?
+fn is_orphan(file: u8) -> bool {
+    file != UNUSED_BLOCK && file as usize >= Filesystem::FILES_CAPACITY
+}
+
+/// Options for inserting files with [`Filesystem::insert_file_with_options()`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InsertOptions {
+    /// How the blocks to store the file in are chosen
+    pub allocation: Allocation,
+}
+
+/// A strategy for choosing the blocks a file is stored in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Allocation {
+    /// Use the free blocks in ascending order, even if they're scattered
+    #[default]
+    FirstFit,
+
+    /// Use the first run of contiguous free blocks that is large enough, or fail
+    Contiguous,
+
+    /// Use the smallest run of contiguous free blocks that is large enough, and fall back to
+    /// scattered blocks (like [`Allocation::FirstFit`]) if there is none
+    BestFit,
+}
+
+/// A problem found by [`Filesystem::check()`]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Integrity {
+    /// A block in the allocation table belongs to a file slot that doesn't exist
+    #[error("Block {block} belongs to non-existent file {file:#04X}")]
+    InvalidAllocEntry { block: u8, file: u8 },
+
+    /// The active file byte points to a file slot that doesn't exist
+    #[error("The active file {0:#04X} does not exist")]
+    InvalidActiveFile(u8),
+
+    /// The name of a file contains disallowed bytes
+    #[error("File {file} has an invalid name")]
+    InvalidName {
+        file: Index,
+        error: name::FromBytesError,
+    },
+
+    /// A file slot without any blocks still has a version number set
+    #[error("File {file} has no blocks, but has version {version}")]
+    OrphanedVersion { file: Index, version: u8 },
+
+    /// A block ended without a block jump or end-of-file command
+    #[error("Block {block} of file {file} ends without a jump or end-of-file command")]
+    UnterminatedBlock { file: Index, block: u8 },
+
+    /// A block jump points to a block that isn't owned by the file
+    #[error("Block {from} of file {file} jumps to block {to}, which the file does not own")]
+    JumpToForeignBlock { file: Index, from: u8, to: u8 },
+
+    /// The block chain of a file loops back onto itself
+    #[error("The block chain of file {file} visits block {block} twice")]
+    BlockVisitedTwice { file: Index, block: u8 },
+
+    /// A block is owned by a file, but never reached through its block chain
+    #[error("Block {block} is owned by file {file}, but is never used")]
+    UnreachableBlock { file: Index, block: u8 },
+
+    /// The file decompresses to a different number of bytes than a song takes up
+    #[error("File {file} decompresses to {actual} bytes instead of {expected}")]
+    DecompressedSizeMismatch {
+        file: Index,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl Integrity {
+    /// The file slot the problem concerns, if it concerns a single file
+    ///
+    /// This can be used to group the problems per file, for example in a report.
+    pub fn file(&self) -> Option<Index> {
+        match self {
+            Self::InvalidAllocEntry { .. } | Self::InvalidActiveFile(_) => None,
+            Self::InvalidName { file, .. }
+            | Self::OrphanedVersion { file, .. }
+            | Self::UnterminatedBlock { file, .. }
+            | Self::JumpToForeignBlock { file, .. }
+            | Self::BlockVisitedTwice { file, .. }
+            | Self::UnreachableBlock { file, .. }
+            | Self::DecompressedSizeMismatch { file, .. } => Some(*file),
+        }
+    }
+}
+
+/// Errors that might be returned from [`Filesystem::insert_file()`]
+#[derive(Debug, Error)]
+pub enum InsertFileError {
+    /// The compressed song needs more blocks than are free (counting those of the file replaced)
+    #[error("The song needs {needed} blocks, but only {free} are free")]
+    NotEnoughBlocks { needed: usize, free: usize },
+
+    /// The block allocation table refers to a file slot that doesn't exist
+    #[error("The block allocation table is corrupt (block {block} belongs to file {file:#04X})")]
+    CorruptAllocTable { block: u8, file: u8 },
+
+    /// A contiguous run of blocks was required, but none of the free runs is large enough
+    #[error("There is no run of {needed} contiguous free blocks")]
+    NoContiguousBlocks { needed: usize },
+
+    /// Compressing the song into the blocks failed, for example because they ran out
+    #[error("Compressing the song failed")]
+    Compress(#[from] CompressBlockError),
+}
+
+/// Errors that might be returned from [`Filesystem::defragment()`]
+#[derive(Debug, Error)]
+pub enum DefragmentError {
+    /// The filesystem has problems, which defragmenting would turn into lost blocks
+    #[error("The filesystem is inconsistent, so defragmenting it would lose data")]
+    Inconsistent(Vec<Integrity>),
+
+    /// Any failure that has to do with I/O
+    #[error("Something failed with I/O")]
+    Io(#[from] io::Error),
 }
 
 /// Errors that might occur deserializing a [`Filesystem`] from I/O
@@ -329,15 +987,43 @@ pub enum FromReaderError {
     Io(#[from] io::Error),
 }
 
+/// Errors that might occur deserializing a [`Filesystem`] from a slice of bytes
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FromBytesError {
+    /// The slice contains fewer bytes than the filesystem takes up
+    #[error("Expected {expected} bytes of filesystem, but only got {got}")]
+    TooShort { expected: usize, got: usize },
+
+    /// The slice contains more bytes than the filesystem takes up
+    #[error("Expected {expected} bytes of filesystem, but got {got}")]
+    TooLong { expected: usize, got: usize },
+
+    /// All correctly initialized filesystem memory has certain bytes set for
+    /// verification against memory corruption.
+    ///
+    /// This error is returned when that those bytes are faulty.
+    #[error("The initialization check failed")]
+    InitializationCheckIncorrect,
+}
+
 impl Default for Filesystem {
     fn default() -> Self {
         Self::new()
     }
 }
 
-/// Iterator over all the file [`Entry`]'s in a [`Filesystem`]
-pub struct Entries<'a> {
-    fs: &'a Filesystem,
+impl<'a> TryFrom<&'a [u8]> for Filesystem {
+    type Error = FromBytesError;
+
+    #[inline]
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(bytes)
+    }
+}
+
+/// Iterator over all the file [`Entry`]'s in a [`Filesystem`]
+pub struct Entries<'a> {
+    fs: &'a Filesystem,
     index: u8,
 }
 
@@ -355,14 +1041,222 @@ impl<'a> Iterator for Entries<'a> {
     }
 }
 
+/// Iterator over the file slots in use in a [`Filesystem`], as returned by [`Filesystem::occupied_files()`]
+pub struct OccupiedEntries<'a> {
+    fs: &'a Filesystem,
+    in_use: [bool; Filesystem::FILES_CAPACITY],
+    front: u8,
+    back: u8,
+}
+
+impl<'a> OccupiedEntries<'a> {
+    fn entry(&self, index: u8) -> (Index, Entry<'a>) {
+        let index = Index::new(index);
+        (index, Entry { fs: self.fs, index })
+    }
+}
+
+impl<'a> Iterator for OccupiedEntries<'a> {
+    type Item = (Index, Entry<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            let index = self.front;
+            self.front += 1;
+
+            if self.in_use[index as usize] {
+                return Some(self.entry(index));
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.in_use[self.front as usize..self.back as usize]
+            .iter()
+            .filter(|in_use| **in_use)
+            .count();
+
+        (len, Some(len))
+    }
+}
+
+impl<'a> DoubleEndedIterator for OccupiedEntries<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            self.back -= 1;
+
+            if self.in_use[self.back as usize] {
+                return Some(self.entry(self.back));
+            }
+        }
+
+        None
+    }
+}
+
+impl<'a> ExactSizeIterator for OccupiedEntries<'a> {}
+
 /// Immutable reference to a single [`File`] in the [`Filesystem`]
 pub struct Entry<'a> {
     fs: &'a Filesystem,
     index: Index,
 }
 
+/// Lightweight information about a file in the [`Filesystem`], as returned by [`Entry::metadata()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FileMetadata {
+    /// The index of the file slot
+    pub index: u8,
+
+    /// The name of the file
+    pub name: Name<8>,
+
+    /// The file version (increased with every save)
+    pub version: u8,
+
+    /// The format version of the song stored in the file
+    pub format_version: u8,
+}
+
+/// Errors that might be returned from [`Entry::metadata()`]
+#[derive(Debug, Error)]
+pub enum MetadataError {
+    /// The name of the file couldn't be read
+    #[error("Reading the name failed: {0}")]
+    Name(#[from] name::FromBytesError),
+
+    /// The song couldn't be decompressed to find its format version
+    #[error("Decompressing the song failed")]
+    Decompress(#[from] song::FromReaderError),
+}
+
+impl<'a> Entry<'a> {
+    /// Collect the index, name, version and song format version of the file
+    ///
+    /// This decompresses the song to find its format version.
+    pub fn metadata(&self) -> Result<FileMetadata, MetadataError> {
+        Ok(FileMetadata {
+            index: self.index.into(),
+            name: self.name()?,
+            version: self.version(),
+            format_version: self.decompress()?.format_version(),
+        })
+    }
+
+    /// The number of blocks taken up by the compressed file
+    pub fn block_count(&self) -> usize {
+        let index = u8::from(self.index);
+        self.fs
+            .alloc_table()
+            .iter()
+            .filter(|file| **file == index)
+            .count()
+    }
+}
+
+/// A single row in a listing of the files in a [`Filesystem`], as returned by [`summary()`]
+///
+/// Unlike [`FileMetadata`], building a summary never fails. Whatever couldn't be read is left
+/// empty, and the reason is stored in [`FileSummary::error`]. Songs are decompressed with
+/// [`Validation::Lenient`], so songs from older LSDJ versions are still listed.
+#[derive(Debug)]
+pub struct FileSummary {
+    /// The index of the file slot
+    pub index: u8,
+
+    /// The name of the file, if it could be read
+    ///
+    /// A name containing invalid characters is still listed, with those characters replaced
+    /// (see [`Name::from_bytes_lossy()`]), and the reason stored in [`FileSummary::error`].
+    pub name: Option<Name<8>>,
+
+    /// The file version (increased with every save)
+    pub version: u8,
+
+    /// The format version of the song stored in the file, if it could be decompressed
+    pub format_version: Option<u8>,
+
+    /// The number of blocks taken up by the compressed file
+    pub block_count: usize,
+
+    /// The reason the name or format version is missing, if any
+    pub error: Option<MetadataError>,
+}
+
+impl FileSummary {
+    /// Summarize a [`File`], which sits at `index` and takes up `block_count` blocks
+    pub fn new<F>(index: u8, file: &F, block_count: usize) -> Self
+    where
+        F: File,
+    {
+        let mut error = None;
+
+        let name = match file.name() {
+            Ok(name) => Some(name),
+            Err(err) => {
+                let name = match &err {
+                    name::FromBytesError::InvalidByte { bytes, .. } => {
+                        Some(Name::from_bytes_lossy(bytes).0)
+                    }
+                    name::FromBytesError::TooLong => None,
+                };
+
+                error = Some(MetadataError::Name(err));
+                name
+            }
+        };
+
+        let format_version = file
+            .decompress_with_validation(Validation::Lenient)
+            .map(|song| song.format_version())
+            .map_err(|err| error = Some(MetadataError::Decompress(err)))
+            .ok();
+
+        Self {
+            index,
+            name,
+            version: file.version(),
+            format_version,
+            block_count,
+            error,
+        }
+    }
+}
+
+impl fmt::Display for FileSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match &self.name {
+            Some(name) => name.to_string(),
+            None => "????????".to_string(),
+        };
+
+        let format_version = match self.format_version {
+            Some(format_version) => format!("{format_version:03}"),
+            None => "---".to_string(),
+        };
+
+        write!(
+            f,
+            "{:>3} | {name:<8} | v{:03} | f{format_version}",
+            self.index, self.version
+        )
+    }
+}
+
+/// List every file in a [`Filesystem`], in slot order
+///
+/// Files that can't be read are still listed, see [`FileSummary`].
+pub fn summary(fs: &Filesystem) -> Vec<FileSummary> {
+    fs.occupied_files()
+        .map(|(index, entry)| FileSummary::new(u8::from(index), &entry, entry.block_count()))
+        .collect()
+}
+
 impl<'a> File for Entry<'a> {
-    fn name(&self) -> Result<Name<8>, FromBytesError> {
+    fn name(&self) -> Result<Name<8>, name::FromBytesError> {
         Name::from_bytes(self.fs.file_name(self.index))
     }
 
@@ -371,8 +1265,11 @@ impl<'a> File for Entry<'a> {
         self.fs.bytes[offset]
     }
 
-    fn decompress(&self) -> Result<SongMemory, song::FromReaderError> {
-        let index = self.index.into();
+    fn decompress_with_validation(
+        &self,
+        validation: Validation,
+    ) -> Result<SongMemory, song::FromReaderError> {
+        let index = u8::from(self.index);
 
         let first_block = self
             .fs
@@ -390,7 +1287,7 @@ impl<'a> File for Entry<'a> {
         // alsorithm *are* 1-indexed.
         //
         // Anyway, we're doing a +1 here.
-        self.fs.decompress(first_block as u8 + 1)
+        self.fs.decompress(first_block as u8 + 1, validation)
     }
 
     fn lsdsng(&self) -> Result<LsdSng, FileToLsdSngError> {
@@ -402,13 +1299,68 @@ impl<'a> File for Entry<'a> {
             blocks.extend_from_slice(self.fs.block(idx));
         }
 
-        Ok(LsdSng::new(name, self.version(), blocks))
+        Ok(LsdSng::from_blocks(name, self.version(), blocks))
+    }
+}
+
+/// Mutable reference to a single [`File`] in the [`Filesystem`]
+pub struct EntryMut<'a> {
+    fs: &'a mut Filesystem,
+    index: Index,
+}
+
+impl<'a> EntryMut<'a> {
+    /// Change the name of the file
+    pub fn set_name(&mut self, name: &Name<8>) {
+        self.fs
+            .file_name_mut(self.index)
+            .copy_from_slice(name.bytes());
+    }
+
+    /// Change the version of the file
+    pub fn set_version(&mut self, version: u8) {
+        *self.fs.file_version_mut(self.index) = version;
+    }
+
+    /// The number of blocks taken up by the compressed file
+    pub fn block_count(&self) -> usize {
+        self.as_entry().block_count()
+    }
+
+    /// Reborrow as an immutable [`Entry`]
+    fn as_entry(&self) -> Entry<'_> {
+        Entry {
+            fs: self.fs,
+            index: self.index,
+        }
+    }
+}
+
+impl<'a> File for EntryMut<'a> {
+    fn name(&self) -> Result<Name<8>, name::FromBytesError> {
+        self.as_entry().name()
+    }
+
+    fn version(&self) -> u8 {
+        self.as_entry().version()
+    }
+
+    fn decompress_with_validation(
+        &self,
+        validation: Validation,
+    ) -> Result<SongMemory, song::FromReaderError> {
+        self.as_entry().decompress_with_validation(validation)
+    }
+
+    fn lsdsng(&self) -> Result<LsdSng, FileToLsdSngError> {
+        self.as_entry().lsdsng()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::song::test_util::{noise_bytes, noisy_song};
 
     #[test]
     fn empty_92l() {
@@ -435,8 +1387,593 @@ mod tests {
         assert!(!filesystem.is_file_in_use(Index::new(1)));
         assert!(filesystem.file(Index::new(1)).is_none());
 
+        assert_eq!(filesystem.active_file(), Some(Index::new(0)));
         filesystem.remove_file(Index::new(0));
         assert!(!filesystem.is_file_in_use(Index::new(0)));
+        assert_eq!(filesystem.active_file(), None);
+    }
+
+    #[test]
+    fn remove_inactive_file() {
+        let mut filesystem = Filesystem::new();
+        let name = "SONG".try_into().unwrap();
+        for index in 0..2 {
+            filesystem
+                .insert_file(Index::new(index), &name, 0, &SongMemory::new())
+                .unwrap();
+        }
+
+        filesystem.set_active_file(Some(Index::new(1)));
+        filesystem.remove_file(Index::new(0));
+        assert_eq!(filesystem.active_file(), Some(Index::new(1)));
+
+        // Replacing the active file keeps it active
+        filesystem
+            .insert_file(Index::new(1), &name, 1, &SongMemory::new())
+            .unwrap();
+        assert_eq!(filesystem.active_file(), Some(Index::new(1)));
+    }
+
+    #[test]
+    fn occupied_files() {
+        let mut filesystem = Filesystem::new();
+        assert_eq!(filesystem.occupied_files().len(), 0);
+
+        for index in [3, 7, 12] {
+            filesystem
+                .insert_file(
+                    Index::new(index),
+                    &"SONG".try_into().unwrap(),
+                    index,
+                    &SongMemory::new(),
+                )
+                .unwrap();
+        }
+
+        let mut files = filesystem.occupied_files();
+        assert_eq!(files.len(), 3);
+
+        let (index, file) = files.next().unwrap();
+        assert_eq!(index, Index::new(3));
+        assert_eq!(file.version(), 3);
+        assert_eq!(files.len(), 2);
+
+        let (index, file) = files.next_back().unwrap();
+        assert_eq!(index, Index::new(12));
+        assert_eq!(file.version(), 12);
+
+        assert_eq!(
+            files.map(|(index, _)| index).collect::<Vec<_>>(),
+            [Index::new(7)]
+        );
+    }
+
+    #[test]
+    fn move_file() {
+        let mut filesystem =
+            Filesystem::try_from(&include_bytes!("../../test/92L_empty.sav")[0x8000..]).unwrap();
+        let song = filesystem
+            .file(Index::new(0))
+            .unwrap()
+            .decompress()
+            .unwrap();
+        let blocks = filesystem.file_blocks(Index::new(0));
+
+        assert!(filesystem.move_file(Index::new(0), Index::new(7)));
+        assert!(!filesystem.is_file_in_use(Index::new(0)));
+        assert_eq!(filesystem.active_file(), Some(Index::new(7)));
+        assert_eq!(filesystem.file_blocks(Index::new(7)), blocks);
+
+        let file = filesystem.file(Index::new(7)).unwrap();
+        assert_eq!(file.name(), Ok("EMPTY".try_into().unwrap()));
+        assert_eq!(file.version(), 0);
+        assert_eq!(file.decompress().unwrap().as_slice(), song.as_slice());
+        assert!(filesystem.check().is_empty());
+
+        // Nothing to move, or no room to move it to
+        assert!(!filesystem.move_file(Index::new(0), Index::new(1)));
+        filesystem
+            .insert_file(Index::new(1), &"OTHER".try_into().unwrap(), 0, &song)
+            .unwrap();
+        assert!(!filesystem.move_file(Index::new(7), Index::new(1)));
+        assert_eq!(filesystem.active_file(), Some(Index::new(7)));
+    }
+
+    #[test]
+    fn from_bytes() {
+        let source = &include_bytes!("../../test/92L_empty.sav")[0x8000..];
+        let filesystem = Filesystem::try_from(source).unwrap();
+        assert_eq!(filesystem.as_slice(), source);
+        assert_eq!(filesystem.to_vec(), source);
+
+        assert_eq!(
+            Filesystem::try_from(&source[1..]).err(),
+            Some(FromBytesError::TooShort {
+                expected: Filesystem::LEN,
+                got: Filesystem::LEN - 1
+            })
+        );
+        assert_eq!(
+            Filesystem::try_from([source, &[0]].concat().as_slice()).err(),
+            Some(FromBytesError::TooLong {
+                expected: Filesystem::LEN,
+                got: Filesystem::LEN + 1
+            })
+        );
+        assert_eq!(
+            Filesystem::try_from([0; Filesystem::LEN].as_slice()).err(),
+            Some(FromBytesError::InitializationCheckIncorrect)
+        );
+    }
+
+    #[test]
+    fn check() {
+        let mut filesystem = Filesystem::new();
+        let name = "EMPTY".try_into().unwrap();
+        filesystem
+            .insert_file(Index::new(0), &name, 0, &SongMemory::new())
+            .unwrap();
+        assert_eq!(filesystem.check(), []);
+
+        filesystem.alloc_table_mut()[10] = 0x25;
+        filesystem.alloc_table_mut()[11] = 0;
+        filesystem.bytes[FILE_VERSIONS_RANGE.start + 3] = 7;
+        filesystem.bytes[ACTIVE_FILE_INDEX] = 0x30;
+        filesystem.file_name_mut(Index::new(0))[0] = b'!';
+
+        assert_eq!(
+            filesystem.check(),
+            [
+                Integrity::InvalidAllocEntry {
+                    block: 11,
+                    file: 0x25
+                },
+                Integrity::InvalidActiveFile(0x30),
+                Integrity::InvalidName {
+                    file: Index::new(0),
+                    error: name::FromBytesError::InvalidByte {
+                        byte: b'!',
+                        index: 0,
+                        bytes: b"!MPTY\0\0\0".to_vec(),
+                    }
+                },
+                Integrity::UnreachableBlock {
+                    file: Index::new(0),
+                    block: 12
+                },
+                Integrity::OrphanedVersion {
+                    file: Index::new(3),
+                    version: 7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn check_block_chain() {
+        let mut filesystem = Filesystem::new();
+        filesystem.alloc_table_mut()[0] = 0;
+        filesystem.alloc_table_mut()[1] = 0;
+
+        // Jump from block 1 to 2 and back
+        filesystem.block_mut(1)[..2].copy_from_slice(&[0xE0, 2]);
+        filesystem.block_mut(2)[..2].copy_from_slice(&[0xE0, 1]);
+        assert_eq!(
+            filesystem.check(),
+            [Integrity::BlockVisitedTwice {
+                file: Index::new(0),
+                block: 1
+            }]
+        );
+
+        // Jump out of bounds
+        filesystem.block_mut(2)[..2].copy_from_slice(&[0xE0, 0xC5]);
+        assert_eq!(
+            filesystem.check(),
+            [Integrity::JumpToForeignBlock {
+                file: Index::new(0),
+                from: 2,
+                to: 0xC5
+            }]
+        );
+
+        // End without an end-of-file command
+        filesystem.block_mut(2).fill(0);
+        assert_eq!(
+            filesystem.check(),
+            [Integrity::UnterminatedBlock {
+                file: Index::new(0),
+                block: 2
+            }]
+        );
+
+        // End of file too soon
+        filesystem.block_mut(2)[..2].copy_from_slice(&[0xE0, 0xFF]);
+        assert_eq!(
+            filesystem.check(),
+            [Integrity::DecompressedSizeMismatch {
+                file: Index::new(0),
+                expected: SongMemory::LEN,
+                actual: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn decompress_corrupt_block_chain() {
+        let mut filesystem = Filesystem::new();
+        filesystem.alloc_table_mut()[0] = 0;
+        filesystem.alloc_table_mut()[1] = 0;
+
+        // Fill both blocks with data, and have them jump to each other endlessly
+        for (block, to) in [(1, 2), (2, 1)] {
+            let bytes = filesystem.block_mut(block);
+            bytes.fill(0x10);
+            bytes[Filesystem::BLOCK_LEN - 2..].copy_from_slice(&[0xE0, to]);
+        }
+        assert!(filesystem
+            .file(Index::new(0))
+            .unwrap()
+            .decompress()
+            .is_err());
+
+        // Jump to itself
+        filesystem.block_mut(2)[Filesystem::BLOCK_LEN - 2..].copy_from_slice(&[0xE0, 2]);
+        assert!(filesystem
+            .file(Index::new(0))
+            .unwrap()
+            .decompress()
+            .is_err());
+
+        // Jump past the end of the filesystem
+        filesystem.block_mut(2)[Filesystem::BLOCK_LEN - 2..].copy_from_slice(&[0xE0, 0xC5]);
+        assert!(filesystem
+            .file(Index::new(0))
+            .unwrap()
+            .decompress()
+            .is_err());
+
+        let problems = filesystem.check();
+        assert!(!problems.is_empty());
+        assert!(problems
+            .iter()
+            .all(|problem| problem.file() == Some(Index::new(0))));
+    }
+
+    #[test]
+    fn check_noise() {
+        let mut noise = noise_bytes(1);
+        for _ in 0..64 {
+            let mut filesystem = Filesystem::new();
+            for byte in filesystem.bytes.iter_mut() {
+                *byte = noise.next().unwrap();
+            }
+
+            filesystem.check();
+        }
+    }
+
+    #[test]
+    fn metadata() {
+        let mut filesystem = Filesystem::new();
+        let name = "EMPTY".try_into().unwrap();
+        filesystem
+            .insert_file(Index::new(2), &name, 5, &SongMemory::new())
+            .unwrap();
+
+        let metadata = filesystem.file(Index::new(2)).unwrap().metadata().unwrap();
+        assert_eq!(
+            metadata,
+            FileMetadata {
+                index: 2,
+                name,
+                version: 5,
+                format_version: 0x16
+            }
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&metadata).unwrap();
+            assert_eq!(
+                json,
+                r#"{"index":2,"name":"EMPTY","version":5,"format_version":22}"#
+            );
+            assert_eq!(
+                serde_json::from_str::<FileMetadata>(&json).unwrap(),
+                metadata
+            );
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let mut filesystem = Filesystem::new();
+        let name = "EMPTY".try_into().unwrap();
+        filesystem
+            .insert_file(Index::new(0), &name, 5, &SongMemory::new())
+            .unwrap();
+        filesystem
+            .insert_file(Index::new(3), &name, 1, &SongMemory::new())
+            .unwrap();
+
+        // End the file in slot 3 right away, so it can't be decompressed
+        let block = filesystem
+            .alloc_table()
+            .iter()
+            .position(|file| *file == 3)
+            .unwrap() as u8
+            + 1;
+        filesystem.block_mut(block)[..2].copy_from_slice(&[0xE0, 0xFF]);
+
+        let summary = super::summary(&filesystem);
+        assert_eq!(summary.len(), 2);
+
+        assert_eq!(summary[0].to_string(), "  0 | EMPTY    | v005 | f022");
+        assert_eq!(summary[0].block_count, 1);
+        assert!(summary[0].error.is_none());
+
+        assert_eq!(summary[1].to_string(), "  3 | EMPTY    | v001 | f---");
+        assert!(matches!(
+            summary[1].error,
+            Some(MetadataError::Decompress(_))
+        ));
+
+        // Corrupt names are still shown
+        filesystem.file_name_mut(Index::new(0))[2] = 0x80;
+        let summary = super::summary(&filesystem);
+        assert_eq!(summary[0].to_string(), "  0 | EM?TY    | v005 | f022");
+        assert!(matches!(summary[0].error, Some(MetadataError::Name(_))));
+    }
+
+    #[test]
+    fn rename() {
+        use std::io::Cursor;
+
+        let source = &include_bytes!("../../test/92L_empty.sav")[0x8000..];
+        let mut filesystem =
+            Filesystem::from_reader(Cursor::new(source)).expect("could not parse filesystem");
+
+        assert!(filesystem.file_mut(Index::new(1)).is_none());
+
+        let mut file = filesystem.file_mut(Index::new(0)).unwrap();
+        file.set_name(&"RENAMED".try_into().unwrap());
+        file.set_version(3);
+
+        assert_eq!(file.name(), Ok("RENAMED".try_into().unwrap()));
+        assert_eq!(file.version(), 3);
+        assert_eq!(file.decompress().unwrap().format_version(), 0x16);
+
+        let mut dest = vec![0; source.len()];
+        filesystem.to_writer(Cursor::new(&mut dest)).unwrap();
+
+        assert_eq!(&dest[0..8], b"RENAMED\0");
+        assert_eq!(dest[FILE_VERSIONS_RANGE.start], 3);
+        assert_eq!(
+            &dest[8..FILE_VERSIONS_RANGE.start],
+            &source[8..FILE_VERSIONS_RANGE.start]
+        );
+        assert_eq!(
+            &dest[FILE_VERSIONS_RANGE.start + 1..],
+            &source[FILE_VERSIONS_RANGE.start + 1..]
+        );
+    }
+
+    #[test]
+    fn lightning_bolt_name() {
+        use std::io::Cursor;
+
+        // LSDJ stores the lightning bolt as byte 95
+        let mut source = include_bytes!("../../test/92L_empty.sav")[0x8000..].to_vec();
+        source[0..8].copy_from_slice(b"EMPTY_\0\0");
+
+        let filesystem =
+            Filesystem::from_reader(Cursor::new(&source)).expect("could not parse filesystem");
+        let file = filesystem.file(Index::new(0)).unwrap();
+
+        let name = file.name().expect("lightning bolt rejected");
+        assert_eq!(name.as_str(), "EMPTY_");
+        assert_eq!(name.to_string(), "EMPTYx");
+        assert_eq!(name, "EMPTYx".try_into().unwrap());
+        assert_eq!(file.lsdsng().unwrap().name(), Ok(name));
+    }
+
+    #[test]
+    fn rename_file() {
+        let mut filesystem = Filesystem::new();
+        let song = SongMemory::new();
+        filesystem
+            .insert_file(Index::new(0), &"EMPTY".try_into().unwrap(), 0, &song)
+            .unwrap();
+
+        let blocks = filesystem.bytes[Filesystem::BLOCK_LEN..].to_vec();
+
+        let name = "RENAMED".try_into().unwrap();
+        assert!(!filesystem.rename_file(Index::new(1), &name));
+        assert!(!filesystem.set_file_version(Index::new(1), 1));
+        assert!(filesystem
+            .file_name(Index::new(1))
+            .iter()
+            .all(|byte| *byte == 0));
+
+        assert!(filesystem.rename_file(Index::new(0), &name));
+        assert!(filesystem.set_file_version(Index::new(0), 1));
+
+        let file = filesystem.file(Index::new(0)).unwrap();
+        assert_eq!(file.name(), Ok(name));
+        assert_eq!(file.version(), 1);
+        assert_eq!(
+            &filesystem.bytes[Filesystem::BLOCK_LEN..],
+            blocks.as_slice()
+        );
+    }
+
+    #[test]
+    fn rename_active_file() {
+        let mut filesystem = Filesystem::new();
+        let song = SongMemory::new();
+        filesystem
+            .insert_file(Index::new(3), &"EMPTY".try_into().unwrap(), 0, &song)
+            .unwrap();
+        filesystem.set_active_file(Some(Index::new(3)));
+
+        let before = filesystem.file(Index::new(3)).unwrap().lsdsng().unwrap();
+
+        let mut file = filesystem.file_mut(Index::new(3)).unwrap();
+        file.set_name(&"RENAMED".try_into().unwrap());
+        file.set_version(file.version() + 1);
+
+        assert_eq!(filesystem.active_file(), Some(Index::new(3)));
+        assert!(filesystem.check().is_empty());
+
+        let after = filesystem.file(Index::new(3)).unwrap().lsdsng().unwrap();
+        assert_eq!(after.name().unwrap().as_str(), "RENAMED");
+        assert_eq!(after.version(), 1);
+        assert_eq!(after.blocks(), before.blocks());
+        assert_eq!(after.decompress().unwrap().as_slice(), song.as_slice());
+    }
+
+    #[test]
+    fn insert_corrupt_alloc_table() {
+        let mut filesystem = Filesystem::new();
+        let song = SongMemory::new();
+        filesystem
+            .insert_file(Index::new(0), &"EMPTY".try_into().unwrap(), 0, &song)
+            .unwrap();
+        filesystem.alloc_table_mut()[4] = 0xC1;
+
+        let bytes = filesystem.bytes;
+        assert!(matches!(
+            filesystem.insert_file(Index::new(0), &"NEW".try_into().unwrap(), 1, &song),
+            Err(InsertFileError::CorruptAllocTable {
+                block: 5,
+                file: 0xC1
+            })
+        ));
+        assert_eq!(filesystem.bytes, bytes);
+    }
+
+    #[test]
+    fn reclaim_orphan_blocks() {
+        let mut filesystem = Filesystem::new();
+        let song = SongMemory::new();
+        let name = "LAST".try_into().unwrap();
+        filesystem
+            .insert_file(Index::new(0x1F), &name, 0, &song)
+            .unwrap();
+        let used = filesystem.blocks_used_count();
+
+        filesystem.alloc_table_mut()[10] = 0x42;
+        filesystem.alloc_table_mut()[11] = 0x20;
+        filesystem.block_mut(11).fill(0xAB);
+        assert_eq!(filesystem.blocks_used_count(), used);
+        assert_eq!(filesystem.blocks_orphaned_count(), 2);
+        assert_eq!(
+            used + filesystem.blocks_orphaned_count() + filesystem.blocks_free_count(),
+            Filesystem::FILE_BLOCKS_CAPACITY
+        );
+        assert_eq!(
+            filesystem.check(),
+            [
+                Integrity::InvalidAllocEntry {
+                    block: 11,
+                    file: 0x42
+                },
+                Integrity::InvalidAllocEntry {
+                    block: 12,
+                    file: 0x20
+                }
+            ]
+        );
+
+        assert_eq!(filesystem.reclaim_orphan_blocks(), 2);
+        assert_eq!(filesystem.blocks_orphaned_count(), 0);
+        assert_eq!(filesystem.blocks_used_count(), used);
+        assert!(filesystem.block(11).iter().all(|byte| *byte == 0));
+        assert!(filesystem.check().is_empty());
+
+        let file = filesystem.file(Index::new(0x1F)).unwrap();
+        assert_eq!(file.decompress().unwrap().as_slice(), song.as_slice());
+        assert!(filesystem
+            .insert_file(Index::new(0), &name, 0, &song)
+            .is_ok());
+    }
+
+    #[test]
+    fn defragment() {
+        let name = "NOISE".try_into().unwrap();
+        let songs = [
+            noisy_song(0x0500, 1),
+            noisy_song(0x0300, 2),
+            noisy_song(0x0700, 3),
+            noisy_song(0x0900, 4),
+        ];
+
+        // Fragment the filesystem by removing a file and inserting a larger one
+        let mut filesystem = Filesystem::new();
+        for (index, song) in songs[..3].iter().enumerate() {
+            filesystem
+                .insert_file(Index::new(index as u8), &name, 0, song)
+                .unwrap();
+        }
+        filesystem.remove_file(Index::new(1));
+        filesystem
+            .insert_file(Index::new(1), &name, 0, &songs[3])
+            .unwrap();
+
+        let is_contiguous = |filesystem: &Filesystem, index: u8| {
+            let blocks = filesystem.file_blocks(Index::new(index));
+            blocks.windows(2).all(|pair| pair[1] == pair[0] + 1)
+        };
+        assert!(!is_contiguous(&filesystem, 1));
+
+        let blocks_used = filesystem.blocks_used_count();
+        assert!(filesystem.defragment().unwrap() > 0);
+        assert_eq!(filesystem.blocks_used_count(), blocks_used);
+        assert_eq!(filesystem.defragment().unwrap(), 0);
+
+        let unused = Filesystem::block_range(blocks_used as u8 + 1).start..Filesystem::LEN;
+        assert!(filesystem.bytes[unused].iter().all(|byte| *byte == 0));
+
+        let mut next_block = 1;
+        for (index, song) in [(0, &songs[0]), (1, &songs[3]), (2, &songs[2])] {
+            assert!(is_contiguous(&filesystem, index));
+            assert_eq!(filesystem.file_blocks(Index::new(index))[0], next_block);
+            next_block += filesystem.file_blocks(Index::new(index)).len() as u8;
+
+            let file = filesystem.file(Index::new(index)).unwrap();
+            assert_eq!(file.decompress().unwrap().as_slice(), song.as_slice());
+        }
+    }
+
+    #[test]
+    fn defragment_inconsistent() {
+        let mut filesystem = Filesystem::new();
+        filesystem
+            .insert_file(
+                Index::new(0),
+                &"EMPTY".try_into().unwrap(),
+                0,
+                &SongMemory::new(),
+            )
+            .unwrap();
+
+        // An extra block for the file, which its block chain never reaches
+        filesystem.alloc_table_mut()[9] = 0;
+        filesystem.block_mut(10).fill(0xAB);
+        let before = filesystem.bytes;
+
+        match filesystem.defragment() {
+            Err(DefragmentError::Inconsistent(problems)) => assert_eq!(
+                problems,
+                [Integrity::UnreachableBlock {
+                    file: Index::new(0),
+                    block: 10
+                }]
+            ),
+            result => panic!("Expected Inconsistent, got {result:?}"),
+        }
+        assert_eq!(filesystem.bytes, before);
     }
 
     #[test]
@@ -453,10 +1990,94 @@ mod tests {
         assert!(filesystem.is_file_in_use(Index::new(0)));
         assert!(old.is_none());
 
+        let blocks = song.compressed_block_count().unwrap();
+        assert_eq!(filesystem.file_block_count(Index::new(0)), Some(blocks));
+        assert_eq!(filesystem.file_block_count(Index::new(1)), None);
+        assert_eq!(filesystem.blocks_used_count(), blocks);
+        assert_eq!(
+            filesystem.blocks_free_count(),
+            ALLOC_TABLE_RANGE.len() - blocks
+        );
+
         let old = filesystem
             .insert_file(Index::new(0), &name, 0, &song)
             .unwrap();
         assert!(filesystem.is_file_in_use(Index::new(0)));
         assert!(old.is_some());
     }
+
+    #[test]
+    fn insert_allocation() {
+        // Leave free runs at block 1, blocks 3-6 and blocks 8-9, the rest belongs to file 5
+        let fragmented = {
+            let mut filesystem = Filesystem::new();
+            filesystem.alloc_table_mut().fill(5);
+            for block in [1, 3, 4, 5, 6, 8, 9] {
+                filesystem.alloc_table_mut()[block - 1] = UNUSED_BLOCK;
+            }
+            filesystem
+        };
+
+        let two = noisy_song(0x280, 7);
+        let five = noisy_song(0x880, 7);
+        assert_eq!(two.compressed_block_count().unwrap(), 2);
+        assert_eq!(five.compressed_block_count().unwrap(), 5);
+
+        let insert = |song: &SongMemory, allocation| {
+            let mut filesystem = Filesystem::try_from(fragmented.bytes.as_slice()).unwrap();
+            let name = "SONG".try_into().unwrap();
+            let options = InsertOptions { allocation };
+            filesystem
+                .insert_file_with_options(Index::new(0), &name, 0, song, options)
+                .map(|_| filesystem.file_blocks(Index::new(0)))
+        };
+
+        assert_eq!(insert(&two, Allocation::FirstFit).unwrap(), [1, 3]);
+        assert_eq!(insert(&two, Allocation::Contiguous).unwrap(), [3, 4]);
+        assert_eq!(insert(&two, Allocation::BestFit).unwrap(), [8, 9]);
+
+        assert!(matches!(
+            insert(&five, Allocation::Contiguous),
+            Err(InsertFileError::NoContiguousBlocks { needed: 5 })
+        ));
+        assert_eq!(insert(&five, Allocation::BestFit).unwrap(), [1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn insert_rolls_back() {
+        let noise = noisy_song(SongMemory::LEN, 5);
+
+        // Free blocks are either zeroed, or still hold whatever was stored in them before
+        for stale in [0x00, 0xAB] {
+            // Two noisy songs leave less room than a third one needs, even counting file 0's block
+            let mut filesystem = Filesystem::new();
+            let name = "SONG".try_into().unwrap();
+            let song = SongMemory::new();
+            filesystem
+                .insert_file(Index::new(0), &name, 3, &song)
+                .unwrap();
+            for index in 1..3 {
+                filesystem
+                    .insert_file(Index::new(index), &name, 0, &noise)
+                    .unwrap();
+            }
+            for block in 1..Filesystem::BLOCKS_CAPACITY as u8 {
+                if filesystem.alloc_table()[block as usize - 1] == UNUSED_BLOCK {
+                    filesystem.block_mut(block).fill(stale);
+                }
+            }
+
+            let before = filesystem.bytes.to_vec();
+            assert!(matches!(
+                filesystem.insert_file(Index::new(0), &"NOISE".try_into().unwrap(), 4, &noise),
+                Err(InsertFileError::NotEnoughBlocks { .. })
+            ));
+            assert_eq!(filesystem.bytes.as_slice(), before.as_slice());
+
+            let file = filesystem.file(Index::new(0)).unwrap();
+            assert_eq!(file.name(), Ok(name));
+            assert_eq!(file.version(), 3);
+            assert_eq!(file.decompress().unwrap().as_slice(), song.as_slice());
+        }
+    }
 }
@@ -8,13 +8,20 @@
 
 mod filesystem;
 
-pub use filesystem::{Entries, Entry, Filesystem, FromReaderError, Index};
+pub use filesystem::{
+    summary, Allocation, DefragmentError, Entries, Entry, EntryMut, FileMetadata, FileSummary,
+    Filesystem, FromBytesError, FromReaderError, Index, InsertFileError, InsertOptions, Integrity,
+    MetadataError, OccupiedEntries,
+};
+pub(crate) use filesystem::{
+    ACTIVE_FILE_INDEX, ALLOC_TABLE_RANGE, CHECK_RANGE, FILE_NAMES_RANGE, FILE_VERSIONS_RANGE,
+};
 
 use crate::{
     lsdsng::LsdSng,
-    name::{FromBytesError, Name},
+    name::{self, Name},
     serde::CompressBlockError,
-    song::{self, SongMemory},
+    song::{self, SongMemory, Validation},
 };
 use thiserror::Error;
 
@@ -30,13 +37,27 @@ use thiserror::Error;
 /// an [`LsdSng`] is technically also a [`File`].
 pub trait File {
     /// The name of the song stored in the file
-    fn name(&self) -> Result<Name<8>, FromBytesError>;
+    fn name(&self) -> Result<Name<8>, name::FromBytesError>;
 
     /// The version (increased with every save) of the song
     fn version(&self) -> u8;
 
     /// Decompress the song stored in the file
-    fn decompress(&self) -> Result<SongMemory, song::FromReaderError>;
+    fn decompress(&self) -> Result<SongMemory, song::FromReaderError> {
+        self.decompress_with_validation(Validation::Strict)
+    }
+
+    /// Decompress the song stored in the file, choosing how strictly the song is verified
+    fn decompress_with_validation(
+        &self,
+        validation: Validation,
+    ) -> Result<SongMemory, song::FromReaderError>;
+
+    /// Decompress the song and hash its contents (see [`SongMemory::content_hash()`])
+    #[cfg(feature = "hash")]
+    fn content_hash(&self) -> Result<[u8; 32], song::FromReaderError> {
+        Ok(self.decompress()?.content_hash())
+    }
 
     /// Decompress and combine all fields into an [`LsdSng`]
     fn lsdsng(&self) -> Result<LsdSng, FileToLsdSngError> {
@@ -53,7 +74,7 @@ pub trait File {
 pub enum FileToLsdSngError {
     /// Deserializing the name failed
     #[error("Deserializing the name failed")]
-    Name(#[from] FromBytesError),
+    Name(#[from] name::FromBytesError),
 
     /// Decompressing the song failed
     #[error("Decompessing the song failed")]
@@ -0,0 +1,74 @@
+//! Hexadecimal (de)serialization of byte buffers, for use with `#[serde(with = "crate::hex")]`
+//!
+//! Human-readable formats (such as JSON or TOML) get a lowercase hex string, which is a lot more
+//! compact than an array of numbers. Binary formats get the bytes as they are.
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serialize bytes as a hex string (or as-is, for binary formats)
+pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    if serializer.is_human_readable() {
+        let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+        serializer.serialize_str(&hex)
+    } else {
+        bytes.serialize(serializer)
+    }
+}
+
+/// Deserialize bytes from a hex string (or as-is, for binary formats)
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if !deserializer.is_human_readable() {
+        return Vec::deserialize(deserializer);
+    }
+
+    let hex = String::deserialize(deserializer)?;
+    if hex.len() % 2 != 0 {
+        return Err(D::Error::custom("odd number of hex digits"));
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| {
+            hex.get(index..index + 2)
+                .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| D::Error::custom(format!("invalid hex digits at position {index}")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+    struct Bytes(#[serde(with = "super")] Vec<u8>);
+
+    #[test]
+    fn round_trip() {
+        let bytes = Bytes(vec![0x00, 0x0F, 0xA5, 0xFF]);
+
+        let json = serde_json::to_string(&bytes).unwrap();
+        assert_eq!(json, "\"000fa5ff\"");
+        assert_eq!(serde_json::from_str::<Bytes>(&json).unwrap(), bytes);
+
+        assert_eq!(
+            serde_json::from_str::<Bytes>("\"000FA5FF\"").unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(serde_json::from_str::<Bytes>("\"abc\"").is_err());
+        assert!(serde_json::from_str::<Bytes>("\"0g\"").is_err());
+        assert!(serde_json::from_str::<Bytes>("\"+f\"").is_err());
+        assert!(serde_json::from_str::<Bytes>("\"é0\"").is_err());
+    }
+}
@@ -41,14 +41,17 @@
 //! - [`SRAM`](crate::sram) serialization and deserialization
 //! - [`Filesystem`](crate::fs) manipulation (querying, inserting and removing files)
 //! - [`LsdSng`](crate::lsdsng) serialization and deserialization
+//! - [`LsdPrj`](crate::lsdprj) serialization and deserialization
 //! - Full implementation of the [compression algorithm](crate::serde) (verified with tests)
+//! - Parsing of format version 22 [songs](crate::song::v22) (work in progress)
+//! - Optional [serde](https://serde.rs) support for names, [`LsdSng`](crate::lsdsng)'s, [`SongMemory`](crate::song::SongMemory) and file metadata, through the `serde` feature (song bytes are written as hex in human-readable formats)
+//! - Optional SHA-256 hashing of song contents (ignoring the "file changed" flag), through the `hash` feature
 //!
 //! ## Wishlist
 //!
 //! These are features I'm interested in exploring/adding at a certain point:
 //!
 //! - [`SongMemory`](crate::song) parsing into song structures per format version. (This would allow manipulating songs.)
-//! - `.lsdprj` support
 //! - `ROM` handling, mainly for sample manipulation
 //!
 //! ## Support
@@ -56,6 +59,9 @@
 //! If you like this crate and want to support me somehow, consider buying some of [my music](https://4ntler.bandcamp.com/).
 
 pub mod fs;
+#[cfg(feature = "serde")]
+mod hex;
+pub mod lsdprj;
 pub mod lsdsng;
 pub mod name;
 pub mod serde;
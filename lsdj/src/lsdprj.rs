@@ -0,0 +1,215 @@
+//! The `.lsdprj` format
+//!
+//! LSDPatcher exports songs as projects. The header and blocks are laid out exactly like an
+//! `.lsdsng`: the `92L_empty.lsdprj` fixture in this repository is stored as the same object as
+//! `92L_empty.lsdsng`. Projects can also bundle the kits a song uses after its blocks. That
+//! payload isn't documented, so it isn't parsed, but it is kept (see [`LsdPrj::extra()`]).
+
+use crate::{
+    fs::{File, FileToLsdSngError, Filesystem},
+    lsdsng::{LsdSng, HEADER_LEN},
+    name::{self, Name},
+    serde::{decompress_block, CompressBlockError, End},
+    song::{self, SongMemory, Validation},
+};
+use std::{
+    io::{self, Cursor, Read, Write},
+    path::Path,
+};
+
+pub use crate::lsdsng::{FromPathError, FromReaderError};
+
+/// A [`Name`], version and compressed [`SongMemory`], stored as a project
+///
+/// Besides `.lsdsng`, songs are also shared as `.lsdprj` files. The song is stored the same way in
+/// both formats, so this wraps an [`LsdSng`], along with anything that follows the song's last block.
+#[derive(Clone)]
+pub struct LsdPrj {
+    lsdsng: LsdSng,
+
+    /// The bytes following the block that ends the song
+    extra: Vec<u8>,
+}
+
+impl LsdPrj {
+    /// Create an [`LsdPrj`] by compressing [`SongMemory`]
+    pub fn from_song(
+        name: Name<8>,
+        version: u8,
+        song: &SongMemory,
+    ) -> Result<Self, CompressBlockError> {
+        LsdSng::from_song(name, version, song).map(Self::from)
+    }
+
+    /// Read an [`LsdPrj`] from an arbitrary I/O reader
+    ///
+    /// The song ends with the first block containing an end-of-file command, and everything
+    /// after it is kept as [`LsdPrj::extra()`]. If no block ends the song, all bytes are
+    /// taken to be blocks, like [`LsdSng::from_reader()`] does.
+    pub fn from_reader<R>(mut reader: R) -> Result<Self, FromReaderError>
+    where
+        R: Read,
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let len = bytes
+            .get(HEADER_LEN..)
+            .and_then(song_len)
+            .map_or(bytes.len(), |len| HEADER_LEN + len);
+
+        Ok(Self {
+            lsdsng: LsdSng::from_bytes(&bytes[..len])?,
+            extra: bytes[len..].to_vec(),
+        })
+    }
+
+    /// Deserialize an [`LsdPrj`] from a path on disk (.lsdprj)
+    pub fn from_path<P>(path: P) -> Result<Self, FromPathError>
+    where
+        P: AsRef<Path>,
+    {
+        let file = std::fs::File::open(path)?;
+        Ok(Self::from_reader(file)?)
+    }
+
+    /// The bytes following the song, such as the kits LSDPatcher can bundle with it
+    ///
+    /// These aren't parsed, but are written back as-is, so a project round-trips byte-for-byte.
+    pub fn extra(&self) -> &[u8] {
+        &self.extra
+    }
+
+    /// Serialize the [`LsdPrj`] to an arbitrary I/O writer
+    pub fn to_writer<W>(&self, mut writer: W) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        self.lsdsng.to_writer(&mut writer)?;
+        writer.write_all(&self.extra)
+    }
+
+    /// Serialize the [`LsdPrj`] to a path on disk (.lsdprj)
+    pub fn to_path<P>(&self, path: P) -> Result<(), io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        self.lsdsng.to_path(path)
+    }
+}
+
+impl From<LsdSng> for LsdPrj {
+    fn from(lsdsng: LsdSng) -> Self {
+        Self {
+            lsdsng,
+            extra: Vec::new(),
+        }
+    }
+}
+
+/// Drops the [`LsdPrj::extra()`] bytes, keeping only the song
+impl From<LsdPrj> for LsdSng {
+    fn from(lsdprj: LsdPrj) -> Self {
+        lsdprj.lsdsng
+    }
+}
+
+/// The length of the blocks up to and including the one that ends the song, if there is one
+fn song_len(blocks: &[u8]) -> Option<usize> {
+    let mut scratch = Cursor::new(Vec::with_capacity(SongMemory::LEN));
+
+    for (index, block) in blocks.chunks_exact(Filesystem::BLOCK_LEN).enumerate() {
+        scratch.get_mut().clear();
+        scratch.set_position(0);
+
+        match decompress_block(Cursor::new(block), &mut scratch).ok()? {
+            End::JumpToBlock(_) => (),
+            End::EndOfFile => return Some((index + 1) * Filesystem::BLOCK_LEN),
+        }
+    }
+
+    None
+}
+
+impl File for LsdPrj {
+    fn name(&self) -> Result<Name<8>, name::FromBytesError> {
+        self.lsdsng.name()
+    }
+
+    fn version(&self) -> u8 {
+        self.lsdsng.version()
+    }
+
+    fn decompress_with_validation(
+        &self,
+        validation: Validation,
+    ) -> Result<SongMemory, song::FromReaderError> {
+        self.lsdsng.decompress_with_validation(validation)
+    }
+
+    fn lsdsng(&self) -> Result<LsdSng, FileToLsdSngError> {
+        Ok(self.lsdsng.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{Filesystem, Index};
+    use std::io::Cursor;
+
+    #[test]
+    fn empty() {
+        let source = include_bytes!("../test/92L_empty.lsdprj");
+        let lsdprj = LsdPrj::from_reader(Cursor::new(source)).unwrap();
+
+        assert_eq!(lsdprj.name(), Ok("EMPTY".try_into().unwrap()));
+        assert_eq!(lsdprj.version(), 0);
+
+        let song = lsdprj.decompress().unwrap();
+        assert_eq!(song.format_version(), 0x16);
+
+        let mut dest = vec![0; source.len()];
+        lsdprj.to_writer(Cursor::new(&mut dest)).unwrap();
+        assert_eq!(&dest, source);
+
+        let mut filesystem = Filesystem::new();
+        filesystem
+            .insert_file(Index::new(0), &lsdprj.name().unwrap(), 0, &song)
+            .unwrap();
+        assert!(filesystem.file(Index::new(0)).is_some());
+    }
+
+    #[test]
+    fn same_as_lsdsng() {
+        let lsdsng = include_bytes!("../test/92L_empty.lsdsng");
+        let lsdprj = LsdPrj::from(LsdSng::from_reader(Cursor::new(lsdsng)).unwrap());
+
+        let mut dest = Vec::new();
+        lsdprj.to_writer(&mut dest).unwrap();
+        assert_eq!(&dest, lsdsng);
+    }
+
+    #[test]
+    fn extra() {
+        let song = SongMemory::new();
+        let lsdsng = LsdSng::from_song("KITS".try_into().unwrap(), 2, &song).unwrap();
+
+        let mut source = Vec::new();
+        lsdsng.to_writer(&mut source).unwrap();
+        let kits = [0x60, 0x40, 0x4B, 0x49, 0x54];
+        source.extend_from_slice(&kits);
+
+        let lsdprj = LsdPrj::from_reader(Cursor::new(&source)).unwrap();
+        assert_eq!(lsdprj.extra(), kits);
+        assert_eq!(lsdprj.lsdsng().unwrap().blocks(), lsdsng.blocks());
+        assert_eq!(lsdprj.decompress().unwrap().as_slice(), song.as_slice());
+
+        let mut dest = Vec::new();
+        lsdprj.to_writer(&mut dest).unwrap();
+        assert_eq!(dest, source);
+
+        let lsdprj = LsdPrj::from(lsdsng);
+        assert!(lsdprj.extra().is_empty());
+    }
+}
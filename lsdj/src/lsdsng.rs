@@ -3,22 +3,32 @@
 use crate::{
     fs::{File, FileToLsdSngError, Filesystem},
     name::{self, Name},
-    serde::{compress_block, decompress_block, CompressBlockError, End},
-    song::{self, SongMemory},
+    serde::{
+        compress_block_from_slice, decompress_block, BlockJumps, CompressBlockError, Decompressor,
+        End,
+    },
+    song::{self, SongMemory, Validation},
 };
 use std::{
-    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    io::{self, Cursor, Read, Write},
     path::Path,
     slice,
 };
 use thiserror::Error;
 
+/// The number of bytes taken up by the name and version
+pub(crate) const HEADER_LEN: usize = 9;
+
+/// The maximal number of bytes of blocks, as many as fit in a filesystem
+const MAX_BLOCKS_LEN: usize = Filesystem::FILE_BLOCKS_CAPACITY * Filesystem::BLOCK_LEN;
+
 /// A [`Name`], version and compressed [`SongMemory`]
 ///
 /// Because [`SRam`](crate::sram) consists of multiple songs, artists often export/import them to/from a
 /// format called `.lsdsng`. It's a simple "dumbed-down" version of the SRAM filesystem, containing the
 /// name and version along with compressed data for just _one_ song.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct LsdSng {
     /// The name of the song stored in the [`LsdSng`]
     name: Name<8>,
@@ -31,12 +41,17 @@ pub struct LsdSng {
     /// The `.lsdsng` format is weird in the sense that any block jumps in the decompression algorithm
     /// are to be discarded, because the blocks are just linearly copied over from the filesystem (which
     /// might have had blocks from other songs interleaved).
+    #[cfg_attr(feature = "serde", serde(with = "crate::hex"))]
     blocks: Vec<u8>,
 }
 
 impl LsdSng {
     /// Create a new [`LsdSng`] from its parts
-    pub(crate) fn new(name: Name<8>, version: u8, blocks: Vec<u8>) -> Self {
+    ///
+    /// The blocks are stored as-is, without checking whether they decompress (see
+    /// [`LsdSng::validate()`]). Their length doesn't need to be a multiple of
+    /// [`Filesystem::BLOCK_LEN`] either, because a song can end halfway through its last block.
+    pub fn from_blocks(name: Name<8>, version: u8, blocks: Vec<u8>) -> Self {
         Self {
             name,
             version,
@@ -51,15 +66,18 @@ impl LsdSng {
         song: &SongMemory,
     ) -> Result<Self, CompressBlockError> {
         let mut blocks = Vec::new();
-
-        let mut reader = Cursor::new(song.as_slice());
+        let mut position = 0;
 
         // Loop until we've reached end-of-file
         loop {
             let mut block = [0; Filesystem::BLOCK_LEN];
-            let end = compress_block(&mut reader, Cursor::new(block.as_mut_slice()), || {
-                Some(blocks.len() as u8)
-            })?;
+            let end = compress_block_from_slice(
+                song.as_slice(),
+                &mut position,
+                Cursor::new(block.as_mut_slice()),
+                // Jump to the block that directly follows this one
+                || Some(blocks.len() as u8 + 1),
+            )?;
 
             blocks.push(block);
 
@@ -68,44 +86,173 @@ impl LsdSng {
             }
         }
 
-        Ok(Self::new(
+        Ok(Self::from_blocks(
             name,
             version,
             blocks.iter().flatten().copied().collect(),
         ))
     }
 
+    /// The compressed blocks, as stored after the name and version in an `.lsdsng` file
+    pub fn blocks(&self) -> &[u8] {
+        &self.blocks
+    }
+
+    /// Check whether the blocks decompress into a valid song
+    ///
+    /// This performs a full decompression pass and discards the result.
+    pub fn validate(&self) -> Result<(), song::FromReaderError> {
+        self.decompress().map(|_| ())
+    }
+
+    /// The number of compressed blocks stored in the [`LsdSng`]
+    ///
+    /// A truncated file can end in an incomplete block, which is counted as well.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len().div_ceil(Filesystem::BLOCK_LEN)
+    }
+
+    /// Decompress and recompress the song data, replacing the blocks
+    ///
+    /// Blocks copied over from a filesystem can contain stale jump commands and whatever
+    /// garbage followed the end of each block. This results in identical songs being stored
+    /// as different bytes. After normalization the blocks are identical to those produced by
+    /// [`LsdSng::from_song()`].
+    pub fn normalize(&mut self) -> Result<(), NormalizeError> {
+        let song = self.decompress()?;
+        self.blocks = Self::from_song(self.name.clone(), self.version, &song)?.blocks;
+
+        Ok(())
+    }
+
+    /// Rewrite the block jump commands to the ones [`LsdSng::from_song()`] produces
+    ///
+    /// Blocks copied over from a filesystem (for example through [`File::lsdsng()`]) still jump
+    /// to the filesystem blocks they were stored in. Unlike [`LsdSng::normalize()`], this only
+    /// rewrites those jumps to follow the block order, without recompressing anything. Like
+    /// LSDPatcher does, every block jumps to the one directly following it, so block 0 jumps to
+    /// 1, block 1 to 2, and so on.
+    ///
+    /// The jumps are rewritten in a copy of the blocks, so nothing is changed when an error is
+    /// returned.
+    pub fn normalize_jumps(&mut self) -> Result<(), song::FromReaderError> {
+        let mut blocks = self.blocks.clone();
+        let mut scratch = Cursor::new(Vec::with_capacity(SongMemory::LEN));
+
+        for (index, block) in blocks.chunks_mut(Filesystem::BLOCK_LEN).enumerate() {
+            scratch.get_mut().clear();
+            scratch.set_position(0);
+
+            let mut reader = Cursor::new(&*block);
+            let end = decompress_block(&mut reader, &mut scratch)
+                .map_err(song::FromReaderError::from_decompression)?;
+
+            match end {
+                End::JumpToBlock(_) => {
+                    let jump = reader.position() as usize - 1;
+                    block[jump] = index as u8 + 1;
+                }
+                End::EndOfFile => break,
+            }
+        }
+
+        self.blocks = blocks;
+
+        Ok(())
+    }
+
+    /// Does the last block stop short of [`Filesystem::BLOCK_LEN`] bytes?
+    ///
+    /// This can only happen for songs read with [`ReadOptions::allow_partial_block`] set.
+    pub fn has_partial_block(&self) -> bool {
+        !self.blocks.len().is_multiple_of(Filesystem::BLOCK_LEN)
+    }
+
     /// Read an [`LsdSng`] from an arbitrary I/O reader
-    pub fn from_reader<R>(mut reader: R) -> Result<Self, FromReaderError>
+    pub fn from_reader<R>(reader: R) -> Result<Self, FromReaderError>
     where
         R: Read,
     {
-        let name = {
-            let mut bytes = [0; 8];
-            reader.read_exact(&mut bytes)?;
-            Name::from_bytes(bytes.as_mut_slice())?
-        };
+        Self::from_reader_with_options(reader, ReadOptions::default())
+    }
+
+    /// Read an [`LsdSng`] from an arbitrary I/O reader, with control over how strict to be
+    ///
+    /// Reading stops after the maximum number of blocks a song can take up, so very large
+    /// files are rejected without reading them entirely.
+    pub fn from_reader_with_options<R>(
+        reader: R,
+        options: ReadOptions,
+    ) -> Result<Self, FromReaderError>
+    where
+        R: Read,
+    {
+        let mut bytes = Vec::new();
+        reader
+            .take((HEADER_LEN + MAX_BLOCKS_LEN + 1) as u64)
+            .read_to_end(&mut bytes)?;
 
-        let mut version = 0;
-        reader.read_exact(slice::from_mut(&mut version))?;
+        Ok(Self::from_bytes_with_options(&bytes, options)?)
+    }
 
-        let mut blocks = Vec::new();
-        reader.read_to_end(&mut blocks)?;
+    /// Read an [`LsdSng`] from a slice of bytes
+    ///
+    /// The slice should contain a name and version, followed by at least one block.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromBytesError> {
+        Self::from_bytes_with_options(bytes, ReadOptions::default())
+    }
+
+    /// Read an [`LsdSng`] from a slice of bytes, with control over how strict to be
+    pub fn from_bytes_with_options(
+        bytes: &[u8],
+        options: ReadOptions,
+    ) -> Result<Self, FromBytesError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(FromBytesError::TooShort {
+                expected: HEADER_LEN,
+                got: bytes.len(),
+            });
+        }
+
+        let name = Name::from_bytes(&bytes[..8])?;
+        let blocks = &bytes[HEADER_LEN..];
+
+        if blocks.is_empty() {
+            return Err(BlocksError::EmptyBlocks.into());
+        }
+
+        if blocks.len() > MAX_BLOCKS_LEN {
+            return Err(BlocksError::TooManyBlocks.into());
+        }
+
+        match blocks.len() % Filesystem::BLOCK_LEN {
+            0 => (),
+            _ if options.allow_partial_block => (),
+            len => return Err(BlocksError::PartialBlock { len }.into()),
+        }
 
-        Ok(LsdSng {
+        Ok(Self {
             name,
-            version,
-            blocks,
+            version: bytes[8],
+            blocks: blocks.to_vec(),
         })
     }
 
     /// Deserialize an [`LsdSng`] from a path on disk (.lsdsng)
     pub fn from_path<P>(path: P) -> Result<Self, FromPathError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_path_with_options(path, ReadOptions::default())
+    }
+
+    /// Deserialize an [`LsdSng`] from a path on disk (.lsdsng), with control over how strict to be
+    pub fn from_path_with_options<P>(path: P, options: ReadOptions) -> Result<Self, FromPathError>
     where
         P: AsRef<Path>,
     {
         let file = std::fs::File::open(path)?;
-        Ok(Self::from_reader(file)?)
+        Ok(Self::from_reader_with_options(file, options)?)
     }
 
     /// Serialize the [`LsdSng`] to an arbitrary I/O writer
@@ -138,22 +285,20 @@ impl File for LsdSng {
         self.version
     }
 
-    fn decompress(&self) -> Result<SongMemory, song::FromReaderError> {
-        let mut reader = Cursor::new(&self.blocks);
-        let mut memory = [0; SongMemory::LEN];
-        let mut writer = Cursor::new(memory.as_mut_slice());
-
+    fn decompress_with_validation(
+        &self,
+        validation: Validation,
+    ) -> Result<SongMemory, song::FromReaderError> {
         // .lsdsng's are weird in that they completely disregard the block jump values, and
         // assume that all blocks were serialized in order
-        let mut block = 0;
-        while decompress_block(&mut reader, &mut writer)? != End::EndOfFile {
-            block += 1;
-            reader.seek(SeekFrom::Start((block * Filesystem::BLOCK_LEN) as u64))?;
-        }
+        let mut memory = Vec::with_capacity(SongMemory::LEN);
+        Decompressor::new(Cursor::new(&self.blocks), BlockJumps::Linear)
+            .read_to_end(&mut memory)
+            .map_err(song::FromReaderError::from_decompression)?;
 
-        assert_eq!(writer.stream_position()?, SongMemory::LEN as u64);
+        song::FromReaderError::check_decompressed_size(memory.len() as u64)?;
 
-        SongMemory::from_reader(Cursor::new(memory))
+        Ok(SongMemory::from_bytes_with_validation(&memory, validation)?)
     }
 
     fn lsdsng(&self) -> Result<LsdSng, FileToLsdSngError> {
@@ -161,6 +306,25 @@ impl File for LsdSng {
     }
 }
 
+impl<'a> TryFrom<&'a [u8]> for LsdSng {
+    type Error = FromBytesError;
+
+    #[inline]
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(bytes)
+    }
+}
+
+/// Options for deserializing an [`LsdSng`] with [`LsdSng::from_reader_with_options()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReadOptions {
+    /// Accept blocks that don't add up to a multiple of [`Filesystem::BLOCK_LEN`]
+    ///
+    /// Some tools write `.lsdsng` files that end halfway through the last block. Check
+    /// [`LsdSng::has_partial_block()`] to warn about them.
+    pub allow_partial_block: bool,
+}
+
 /// Errors that might be returned from [`LsdSng::from_reader()`]
 #[derive(Debug, Error)]
 pub enum FromReaderError {
@@ -168,9 +332,71 @@ pub enum FromReaderError {
     #[error("Something failed with I/O")]
     Read(#[from] io::Error),
 
+    /// The source is too short to even contain the name and version
+    #[error("Expected at least {expected} bytes, but only got {got}")]
+    TooShort { expected: usize, got: usize },
+
     /// Could not deserialize the name successfully
     #[error("Reading the name failed")]
     Name(#[from] name::FromBytesError),
+
+    /// The blocks following the name and version aren't valid
+    #[error("The blocks are invalid")]
+    Blocks(#[from] BlocksError),
+}
+
+/// Errors that might be returned from [`LsdSng::from_bytes()`]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FromBytesError {
+    /// The slice is too short to even contain the name and version
+    #[error("Expected at least {expected} bytes, but only got {got}")]
+    TooShort { expected: usize, got: usize },
+
+    /// Could not deserialize the name successfully
+    #[error("Reading the name failed")]
+    Name(#[from] name::FromBytesError),
+
+    /// The blocks following the name and version aren't valid
+    #[error("The blocks are invalid")]
+    Blocks(#[from] BlocksError),
+}
+
+/// Problems with the size of the blocks in an `.lsdsng`, found while reading it
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BlocksError {
+    /// There are no blocks at all
+    #[error("There are no blocks following the name and version")]
+    EmptyBlocks,
+
+    /// The last block is incomplete (see [`ReadOptions::allow_partial_block`])
+    #[error("The last block is only {len} bytes long")]
+    PartialBlock { len: usize },
+
+    /// There are more blocks than fit in a filesystem
+    #[error("There are more than {} blocks", Filesystem::FILE_BLOCKS_CAPACITY)]
+    TooManyBlocks,
+}
+
+/// Errors that might be returned from [`LsdSng::normalize()`]
+#[derive(Debug, Error)]
+pub enum NormalizeError {
+    /// Decompressing the existing blocks failed
+    #[error("Decompressing the song failed")]
+    Decompress(#[from] song::FromReaderError),
+
+    /// Recompressing the song failed
+    #[error("Compressing the song failed")]
+    Compress(#[from] CompressBlockError),
+}
+
+impl From<FromBytesError> for FromReaderError {
+    fn from(error: FromBytesError) -> Self {
+        match error {
+            FromBytesError::TooShort { expected, got } => Self::TooShort { expected, got },
+            FromBytesError::Name(error) => Self::Name(error),
+            FromBytesError::Blocks(error) => Self::Blocks(error),
+        }
+    }
 }
 
 /// Errors that might be returned from [`LsdSng::from_path()`]
@@ -188,6 +414,7 @@ pub enum FromPathError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::song::test_util::noisy_song;
     use std::{io::Cursor, str::FromStr};
 
     #[test]
@@ -207,4 +434,278 @@ mod tests {
 
         assert_eq!(&dest, source);
     }
+
+    #[test]
+    fn compressed_block_count() {
+        let song = SongMemory::new();
+        let lsdsng = LsdSng::from_song(Name::from_str("EMPTY").unwrap(), 0, &song).unwrap();
+
+        assert_eq!(
+            song.compressed_block_count().unwrap(),
+            lsdsng.blocks.len() / Filesystem::BLOCK_LEN
+        );
+        assert_eq!(song.compressed_block_count().unwrap(), lsdsng.block_count());
+    }
+
+    #[test]
+    fn block_count_truncated() {
+        let name = Name::from_str("EMPTY").unwrap();
+        assert_eq!(
+            LsdSng::from_blocks(name.clone(), 0, vec![]).block_count(),
+            0
+        );
+        assert_eq!(
+            LsdSng::from_blocks(name.clone(), 0, vec![0; 0x200]).block_count(),
+            1
+        );
+        assert_eq!(
+            LsdSng::from_blocks(name, 0, vec![0; 0x201]).block_count(),
+            2
+        );
+    }
+
+    #[test]
+    fn decompress_truncated() {
+        let song = SongMemory::new();
+        let mut lsdsng = LsdSng::from_song(Name::from_str("EMPTY").unwrap(), 0, &song).unwrap();
+        lsdsng.blocks.truncate(0x10);
+
+        assert!(matches!(
+            lsdsng.decompress(),
+            Err(song::FromReaderError::UnexpectedEndOfBlocks)
+        ));
+    }
+
+    #[test]
+    fn from_blocks() {
+        let name = Name::from_str("EMPTY").unwrap();
+        let song = LsdSng::from_song(name.clone(), 3, &SongMemory::new()).unwrap();
+
+        let copy = LsdSng::from_blocks(name.clone(), 3, song.blocks().to_vec());
+        assert_eq!(copy.blocks(), song.blocks());
+        assert!(copy.validate().is_ok());
+
+        let truncated = LsdSng::from_blocks(name, 3, song.blocks()[..0x10].to_vec());
+        assert!(truncated.validate().is_err());
+    }
+
+    #[test]
+    fn decompress_too_short() {
+        let lsdsng = LsdSng::from_blocks(Name::from_str("EMPTY").unwrap(), 0, vec![0xE0, 0xFF]);
+
+        assert!(matches!(
+            lsdsng.decompress(),
+            Err(song::FromReaderError::DecompressedSizeMismatch {
+                expected: SongMemory::LEN,
+                actual: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn decompress_too_long() {
+        // Every RLE command produces 255 zeroes, which eventually overruns song memory
+        let blocks = [0xC0, 0, 0xFF].repeat(SongMemory::LEN / 0xFF + 1);
+        let lsdsng = LsdSng::from_blocks(Name::from_str("EMPTY").unwrap(), 0, blocks);
+
+        assert!(matches!(
+            lsdsng.decompress(),
+            Err(song::FromReaderError::DecompressedSizeMismatch {
+                expected: SongMemory::LEN,
+                actual
+            }) if actual > SongMemory::LEN
+        ));
+    }
+
+    fn crafted(blocks_len: usize) -> Vec<u8> {
+        let mut bytes = b"EMPTY\0\0\0\0".to_vec();
+        bytes.resize(HEADER_LEN + blocks_len, 0);
+        bytes
+    }
+
+    #[test]
+    fn read_checks() {
+        assert!(matches!(
+            LsdSng::from_reader(Cursor::new(&crafted(0)[..5])),
+            Err(FromReaderError::TooShort {
+                expected: 9,
+                got: 5
+            })
+        ));
+
+        assert!(matches!(
+            LsdSng::from_reader(Cursor::new(crafted(0))),
+            Err(FromReaderError::Blocks(BlocksError::EmptyBlocks))
+        ));
+
+        assert_eq!(
+            LsdSng::from_bytes(&crafted(Filesystem::BLOCK_LEN + 10)).err(),
+            Some(FromBytesError::Blocks(BlocksError::PartialBlock {
+                len: 10
+            }))
+        );
+
+        assert_eq!(
+            LsdSng::from_bytes(&crafted(
+                (Filesystem::FILE_BLOCKS_CAPACITY + 1) * Filesystem::BLOCK_LEN
+            ))
+            .err(),
+            Some(FromBytesError::Blocks(BlocksError::TooManyBlocks))
+        );
+
+        let full = LsdSng::from_bytes(&crafted(MAX_BLOCKS_LEN)).unwrap();
+        assert_eq!(full.block_count(), Filesystem::FILE_BLOCKS_CAPACITY);
+        assert!(!full.has_partial_block());
+    }
+
+    #[test]
+    fn read_partial_block() {
+        let options = ReadOptions {
+            allow_partial_block: true,
+        };
+
+        let bytes = crafted(Filesystem::BLOCK_LEN + 10);
+        let lsdsng = LsdSng::from_reader_with_options(Cursor::new(&bytes), options).unwrap();
+        assert!(lsdsng.has_partial_block());
+        assert_eq!(lsdsng.block_count(), 2);
+
+        assert!(matches!(
+            LsdSng::from_reader_with_options(Cursor::new(crafted(0)), options),
+            Err(FromReaderError::Blocks(BlocksError::EmptyBlocks))
+        ));
+    }
+
+    #[test]
+    fn from_bytes() {
+        let source = include_bytes!("../test/92L_empty.lsdsng");
+        let lsdsng = LsdSng::try_from(source.as_slice()).unwrap();
+        assert_eq!(lsdsng.name, Name::<8>::from_str("EMPTY").unwrap());
+        assert_eq!(lsdsng.blocks, &source[9..]);
+
+        assert_eq!(
+            LsdSng::try_from(&source[..4]).err(),
+            Some(FromBytesError::TooShort {
+                expected: 9,
+                got: 4
+            })
+        );
+    }
+
+    #[test]
+    fn normalize() {
+        let song = SongMemory::new();
+        let name = Name::from_str("EMPTY").unwrap();
+        let normal = LsdSng::from_song(name.clone(), 0, &song).unwrap();
+
+        // Put garbage after the end-of-file command
+        let mut blocks = normal.blocks.clone();
+        *blocks.last_mut().unwrap() = 0xAB;
+        let mut garbage = LsdSng::from_blocks(name, 0, blocks);
+        assert_ne!(garbage.blocks, normal.blocks);
+
+        garbage.normalize().unwrap();
+        assert_eq!(garbage.blocks, normal.blocks);
+    }
+
+    /// The destination of the jump command ending each block, except the last
+    fn jumps(lsdsng: &LsdSng) -> Vec<u8> {
+        let mut scratch = Cursor::new(Vec::new());
+        lsdsng
+            .blocks
+            .chunks(Filesystem::BLOCK_LEN)
+            .map_while(|block| {
+                let mut reader = Cursor::new(block);
+                match decompress_block(&mut reader, &mut scratch).unwrap() {
+                    End::JumpToBlock(to) => Some(to),
+                    End::EndOfFile => None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn from_song_jumps() {
+        let song = noisy_song(0x600, 11);
+        let lsdsng = LsdSng::from_song(Name::from_str("NOISE").unwrap(), 0, &song).unwrap();
+
+        let count = lsdsng.block_count() as u8;
+        assert!(count > 2);
+        assert_eq!(jumps(&lsdsng), (1..count).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn normalize_jumps() {
+        use crate::fs::Index;
+
+        let song = noisy_song(0x600, 11);
+        let name = Name::from_str("NOISE").unwrap();
+
+        // Store the song after another one, so its blocks jump to other indices
+        let mut filesystem = Filesystem::new();
+        filesystem
+            .insert_file(Index::new(0), &name, 0, &SongMemory::new())
+            .unwrap();
+        filesystem
+            .insert_file(Index::new(1), &name, 0, &song)
+            .unwrap();
+
+        let mut copied = filesystem.file(Index::new(1)).unwrap().lsdsng().unwrap();
+        let count = copied.block_count() as u8;
+        assert!(count > 2);
+        assert_eq!(jumps(&copied), (3..count + 2).collect::<Vec<_>>());
+
+        copied.normalize_jumps().unwrap();
+        assert_eq!(jumps(&copied), (1..count).collect::<Vec<_>>());
+        assert_eq!(copied.decompress().unwrap().as_slice(), song.as_slice());
+    }
+
+    #[test]
+    fn normalize_jumps_corrupt() {
+        use crate::fs::Index;
+
+        let song = noisy_song(0x600, 11);
+        let name = Name::from_str("NOISE").unwrap();
+
+        let mut filesystem = Filesystem::new();
+        filesystem
+            .insert_file(Index::new(0), &name, 0, &SongMemory::new())
+            .unwrap();
+        filesystem
+            .insert_file(Index::new(1), &name, 0, &song)
+            .unwrap();
+
+        // Without a jump or end command, decompressing the last block runs off its end
+        let mut copied = filesystem.file(Index::new(1)).unwrap().lsdsng().unwrap();
+        let last = copied.blocks.len() - Filesystem::BLOCK_LEN;
+        copied.blocks[last..].fill(0);
+
+        let blocks = copied.blocks.clone();
+        assert!(copied.normalize_jumps().is_err());
+        assert_eq!(copied.blocks, blocks);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let song = SongMemory::new();
+        let lsdsng = LsdSng::from_song(Name::from_str("EMPTY").unwrap(), 3, &song).unwrap();
+
+        let json = serde_json::to_string(&lsdsng).unwrap();
+        assert!(json.starts_with(r#"{"name":"EMPTY","version":3,"blocks":"c0"#));
+
+        let result: LsdSng = serde_json::from_str(&json).unwrap();
+        assert_eq!(result.name, lsdsng.name);
+        assert_eq!(result.version, 3);
+        assert_eq!(result.blocks, lsdsng.blocks);
+        assert_eq!(result.decompress().unwrap().as_slice(), song.as_slice());
+
+        let value = serde_json::to_value(&lsdsng).unwrap();
+        let result: LsdSng = serde_json::from_value(value).unwrap();
+        assert_eq!(result.name, lsdsng.name);
+        assert_eq!(result.blocks, lsdsng.blocks);
+
+        let result: LsdSng = serde_json::from_reader(json.as_bytes()).unwrap();
+        assert_eq!(result.name, lsdsng.name);
+        assert_eq!(result.blocks, lsdsng.blocks);
+    }
 }
@@ -12,22 +12,40 @@ use thiserror::Error;
 ///
 /// The maximum length isn't the same everywhere, which is why this struct is generic over its length.
 ///
-/// The allowed characters in a [`Name`] are (ASCII) `A-Z`, `0-9`, space and `x`. The `x` is represented
-/// as a lightning glyph in the default LSDJ ROM.
+/// The allowed characters in a [`Name`] are (ASCII) `A-Z`, `0-9`, space and the lightning bolt. LSDJ
+/// stores the lightning bolt as byte 95 (`_`), but because that doesn't read very well, it is
+/// displayed as `x`. Both `x` and `_` are accepted when converting from strings. Byte 120 (`x`) is
+/// read as a lightning bolt too, because older versions of this crate stored it that way.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Name<const N: usize> {
     bytes: [u8; N],
 }
 
 impl<const N: usize> Name<N> {
-    // The special lightning bolt character (the actual glyph depends on your ROM)
-    const LIGHTNING_BOLT_CHAR: u8 = 120; // x
+    /// The byte LSDJ uses to store the lightning bolt character
+    pub const LIGHTNING_BOLT: u8 = 95; // _
+
+    // The character the lightning bolt is displayed as, and which is accepted as a stand-in for
+    // it when converting from strings and bytes
+    const LIGHTNING_BOLT_DISPLAY: u8 = 120; // x
+
+    // The character invalid bytes are replaced with by from_bytes_lossy()
+    const REPLACEMENT_CHAR: u8 = 63; // ?
 
     /// Try to convert a byte slice to a name
     ///
     /// This function fails if the bytes are longer than the allowed length, or an invalid
-    /// character is found.
+    /// character is found. Byte 120 (`x`) is stored as a lightning bolt (see [`Name`]).
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromBytesError> {
+        Self::from_bytes_with_charset(bytes, Charset::Lsdj)
+    }
+
+    /// Try to convert a byte slice to a name, choosing which characters are allowed
+    ///
+    /// This works like [`Name::from_bytes()`], which uses [`Charset::Lsdj`]. Byte 120 (`x`) is
+    /// only turned into a lightning bolt for [`Charset::Lsdj`], as it is a regular character
+    /// otherwise.
+    pub fn from_bytes_with_charset(bytes: &[u8], charset: Charset) -> Result<Self, FromBytesError> {
         if bytes.len() > N {
             return Err(FromBytesError::TooLong);
         }
@@ -35,15 +53,104 @@ impl<const N: usize> Name<N> {
         let mut dest = [0; N];
         for (index, byte) in bytes.iter().enumerate() {
             match *byte {
-                byte if Self::is_byte_allowed(byte) => dest[index] = byte,
+                byte if charset.contains(byte) => dest[index] = byte,
+                Self::LIGHTNING_BOLT_DISPLAY if charset == Charset::Lsdj => {
+                    dest[index] = Self::LIGHTNING_BOLT
+                }
                 0 => break,
-                _ => return Err(FromBytesError::InvalidByte { byte: *byte, index }),
+                _ => {
+                    return Err(FromBytesError::InvalidByte {
+                        byte: *byte,
+                        index,
+                        bytes: bytes.to_vec(),
+                    })
+                }
             }
         }
 
         Ok(Self { bytes: dest })
     }
 
+    /// Convert a byte slice to a name, replacing whatever isn't allowed
+    ///
+    /// Unlike [`Name::from_bytes()`], this function never fails. Bytes that aren't allowed are
+    /// replaced with a `?`, and anything beyond N characters is dropped. The returned flag tells
+    /// whether any of that happened.
+    ///
+    /// This is meant for presenting corrupted names. Because `?` isn't a valid name character,
+    /// a lossy name shouldn't be written back to LSDJ as-is.
+    pub fn from_bytes_lossy(bytes: &[u8]) -> (Self, bool) {
+        let len = bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(bytes.len());
+        let mut lossy = len > N;
+
+        let mut dest = [0; N];
+        for (dest, byte) in dest.iter_mut().zip(&bytes[..len]) {
+            *dest = if Self::is_byte_allowed(*byte) {
+                *byte
+            } else if *byte == Self::LIGHTNING_BOLT_DISPLAY {
+                Self::LIGHTNING_BOLT
+            } else {
+                lossy = true;
+                Self::REPLACEMENT_CHAR
+            };
+        }
+
+        (Self { bytes: dest }, lossy)
+    }
+
+    /// Convert a string to a name, salvaging whatever can be salvaged
+    ///
+    /// Unlike [`Name::from_bytes()`], this function never fails. Instead:
+    ///
+    /// * Characters that are already allowed (see [`Name::is_byte_allowed()`]) are kept as-is
+    /// * A lowercase `x` becomes a lightning bolt
+    /// * Other lowercase ASCII letters are converted to uppercase
+    /// * Any remaining characters are dropped
+    /// * The result is truncated to N characters
+    ///
+    /// Note that this means a lowercase `x` stays a lightning bolt, while the rest of the
+    /// alphabet is uppercased.
+    pub fn from_str_lossy(str: &str) -> Self {
+        let mut name = Self::default();
+        for char in str.chars() {
+            let byte = match u8::try_from(char) {
+                Ok(byte) if Self::is_byte_allowed(byte) => byte,
+                Ok(Self::LIGHTNING_BOLT_DISPLAY) => Self::LIGHTNING_BOLT,
+                Ok(byte) if byte.is_ascii_lowercase() => byte.to_ascii_uppercase(),
+                _ => continue,
+            };
+
+            if name.try_push(byte).is_err() {
+                break;
+            }
+        }
+
+        name
+    }
+
+    /// Append a single character to the end of the name
+    ///
+    /// This fails if the name is already at capacity, or if the byte isn't allowed in names.
+    pub fn try_push(&mut self, byte: u8) -> Result<(), FromBytesError> {
+        let index = self.len();
+        if index == N {
+            return Err(FromBytesError::TooLong);
+        }
+
+        if !Self::is_byte_allowed(byte) {
+            let mut bytes = self.bytes[..index].to_vec();
+            bytes.push(byte);
+
+            return Err(FromBytesError::InvalidByte { byte, index, bytes });
+        }
+
+        self.bytes[index] = byte;
+        Ok(())
+    }
+
     /// Access the underlying bytes that make up the name
     ///
     /// This includes any amount of 0's used for null-termination
@@ -67,20 +174,50 @@ impl<const N: usize> Name<N> {
     }
 
     /// Convert to a [`prim@str`] slice
+    ///
+    /// This returns the characters as they are stored, so a lightning bolt shows up as `_`. Use
+    /// the [`fmt::Display`] implementation for the more readable `x`.
     pub fn as_str(&self) -> &str {
         // SAFETY: Safe, because in from_bytes we check whether any of the characters are within
-        // the ASCII subset allowed by LSDJ, which is per definition UTF8-safe.
+        // the chosen charset, which only ever contains printable ASCII and is per definition
+        // UTF8-safe. from_bytes_lossy only adds the ASCII replacement character to that.
         unsafe { str::from_utf8_unchecked(&self.bytes[..self.len()]) }
     }
 
     /// Is a specific byte within the subset of ASCII usable for name strings?
+    ///
+    /// This checks against [`Charset::Lsdj`].
     pub fn is_byte_allowed(byte: u8) -> bool {
-        // The only allowed characters are the capitals A-Z, digits 0-9, space or the special
-        // lightning bolt character
-        (65..=90).contains(&byte) // A-Z
-            || (48..=57).contains(&byte) // 0-9
-            || byte == 32 // space
-            || byte == Self::LIGHTNING_BOLT_CHAR // x
+        Charset::Lsdj.contains(byte)
+    }
+}
+
+/// The characters allowed in a [`Name`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Charset {
+    /// The capitals A-Z, digits 0-9, space and the lightning bolt, as entered in LSDJ
+    #[default]
+    Lsdj,
+
+    /// Any printable ASCII character, for names written by other tools or firmwares
+    ///
+    /// Names using characters outside of [`Charset::Lsdj`] might display differently (or not at
+    /// all) depending on the ROM.
+    PrintableAscii,
+}
+
+impl Charset {
+    /// Is a byte part of this character set?
+    pub fn contains(self, byte: u8) -> bool {
+        match self {
+            Self::Lsdj => {
+                byte.is_ascii_uppercase()
+                    || byte.is_ascii_digit()
+                    || byte == b' '
+                    || byte == Name::<0>::LIGHTNING_BOLT
+            }
+            Self::PrintableAscii => (b' '..=b'~').contains(&byte),
+        }
     }
 }
 
@@ -92,7 +229,9 @@ impl<const N: usize> Default for Name<N> {
 
 impl<const N: usize> fmt::Display for Name<N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.as_str())
+        let str = self.as_str().replace(Self::LIGHTNING_BOLT as char, "x");
+
+        f.pad(&str)
     }
 }
 
@@ -110,7 +249,7 @@ impl<'a, const N: usize> TryFrom<&'a str> for Name<N> {
 
     #[inline]
     fn try_from(str: &'a str) -> Result<Self, Self::Error> {
-        str.as_bytes().try_into()
+        Self::from_bytes(str.as_bytes())
     }
 }
 
@@ -123,6 +262,28 @@ impl<const N: usize> FromStr for Name<N> {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<const N: usize> ::serde::Serialize for Name<N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> ::serde::Deserialize<'de> for Name<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        // Owned, because not every deserializer can lend out its strings (e.g. escaped ones)
+        let str = String::deserialize(deserializer)?;
+        Self::from_str(&str).map_err(::serde::de::Error::custom)
+    }
+}
+
 /// Errors that can result from trying to convert a byte slice to a [`Name`]
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum FromBytesError {
@@ -132,9 +293,14 @@ pub enum FromBytesError {
 
     /// Only a specific subset of ASCII characters are allowed in [`Name`] strings
     ///
-    /// An invalid byte was found during conversion from bytes
-    #[error("Byte {byte} at position {index} is not allowed as a name character")]
-    InvalidByte { byte: u8, index: usize },
+    /// An invalid byte was found during conversion from bytes. `bytes` holds the entire name
+    /// that was being converted, so it can be shown (e.g. with [`Name::from_bytes_lossy()`]).
+    #[error("Byte {byte} at position {index} of {bytes:02X?} is not allowed as a name character")]
+    InvalidByte {
+        byte: u8,
+        index: usize,
+        bytes: Vec<u8>,
+    },
 }
 
 #[cfg(test)]
@@ -160,11 +326,122 @@ mod tests {
             Name::<8>::from_str("A!"),
             Err(FromBytesError::InvalidByte {
                 byte: 33, // '!'
-                index: 1
+                index: 1,
+                bytes: b"A!".to_vec(),
             })
         );
     }
 
+    #[test]
+    fn charset() {
+        assert!(Name::<8>::from_bytes(b"honey!").is_err());
+
+        let name = Name::<8>::from_bytes_with_charset(b"honey!", Charset::PrintableAscii).unwrap();
+        assert_eq!(name.as_str(), "honey!");
+
+        assert!(Name::<8>::from_bytes_with_charset(b"\x7F", Charset::PrintableAscii).is_err());
+        assert_eq!(
+            Name::<8>::from_bytes_with_charset(b"HONEY", Charset::Lsdj),
+            Name::from_bytes(b"HONEY")
+        );
+    }
+
+    #[test]
+    fn from_bytes_lossy() {
+        let (name, lossy) = Name::<8>::from_bytes_lossy(b"HONEY\0\x80");
+        assert_eq!(name.as_str(), "HONEY");
+        assert!(!lossy);
+
+        let (name, lossy) = Name::<8>::from_bytes_lossy(b"HO\x80EY");
+        assert_eq!(name.as_str(), "HO?EY");
+        assert!(lossy);
+
+        let (name, lossy) = Name::<4>::from_bytes_lossy(b"HONEY");
+        assert_eq!(name.as_str(), "HONE");
+        assert!(lossy);
+    }
+
+    #[test]
+    fn from_str_lossy() {
+        assert_eq!(Name::<8>::from_str_lossy("honey!").as_str(), "HONEY");
+        assert_eq!(
+            Name::<8>::from_str_lossy("ABCDEFGHIJKL").as_str(),
+            "ABCDEFGH"
+        );
+        assert_eq!(Name::<8>::from_str_lossy("A B-Cx").as_str(), "A BC_");
+        assert_eq!(Name::<8>::from_str_lossy("ÜBER").as_str(), "BER");
+        assert!(Name::<8>::from_str_lossy("!?").is_empty());
+    }
+
+    #[test]
+    fn try_push() {
+        let mut name = Name::<2>::default();
+        assert_eq!(name.try_push(b'A'), Ok(()));
+        assert_eq!(
+            name.try_push(b'a'),
+            Err(FromBytesError::InvalidByte {
+                byte: b'a',
+                index: 1,
+                bytes: b"Aa".to_vec(),
+            })
+        );
+        assert_eq!(name.try_push(b'1'), Ok(()));
+        assert_eq!(name.try_push(b'B'), Err(FromBytesError::TooLong));
+        assert_eq!(name.as_str(), "A1");
+    }
+
+    #[test]
+    fn special_characters() {
+        let name = Name::<8>::from_bytes(b"MY SONG_").expect("bytes rejected");
+        assert_eq!(name.as_str(), "MY SONG_");
+        assert_eq!(name.to_string(), "MY SONGx");
+        assert_eq!(format!("{name:10}|"), "MY SONGx  |");
+
+        assert_eq!(Name::<8>::from_str("MY SONGx"), Ok(name.clone()));
+        assert_eq!(Name::<8>::from_str("MY SONG_"), Ok(name));
+
+        assert!(Name::<8>::is_byte_allowed(32)); // space
+        assert!(Name::<8>::is_byte_allowed(95)); // lightning bolt
+        assert!(!Name::<8>::is_byte_allowed(20));
+        assert!(!Name::<8>::is_byte_allowed(120));
+    }
+
+    #[test]
+    fn legacy_lightning_bolt() {
+        // Older versions of this crate stored the lightning bolt as x
+        let name = Name::<8>::from_bytes(b"MY SONGx").unwrap();
+        assert_eq!(name.as_str(), "MY SONG_");
+        assert_eq!(name, Name::from_bytes(b"MY SONG_").unwrap());
+
+        let (lossy, replaced) = Name::<8>::from_bytes_lossy(b"MY SONGx");
+        assert_eq!(lossy, name);
+        assert!(!replaced);
+
+        let ascii = Name::<8>::from_bytes_with_charset(b"MY SONGx", Charset::PrintableAscii);
+        assert_eq!(ascii.unwrap().as_str(), "MY SONGx");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let name = Name::<8>::from_str("HONEY").unwrap();
+        let json = serde_json::to_string(&name).unwrap();
+        assert_eq!(json, "\"HONEY\"");
+        assert_eq!(serde_json::from_str::<Name<8>>(&json).unwrap(), name);
+
+        assert!(serde_json::from_str::<Name<8>>("\"honey\"").is_err());
+        let value = serde_json::to_value(&name).unwrap();
+        assert_eq!(serde_json::from_value::<Name<8>>(value).unwrap(), name);
+        assert_eq!(
+            serde_json::from_reader::<_, Name<8>>(json.as_bytes()).unwrap(),
+            name
+        );
+        assert_eq!(
+            serde_json::from_str::<Name<8>>(r#""HO\u004eEY""#).unwrap(),
+            name
+        );
+    }
+
     #[test]
     fn default() {
         let name = Name::<8>::default();
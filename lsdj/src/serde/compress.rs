@@ -1,13 +1,12 @@
 use super::{
-    utils::{read_byte, CMD_BYTE, DEFAULT_INSTRUMENT_BYTE, DEFAULT_WAVE_BYTE, RLE_BYTE},
+    utils::{write_repeated_byte, CMD_BYTE, DEFAULT_INSTRUMENT_BYTE, DEFAULT_WAVE_BYTE, RLE_BYTE},
     End,
 };
-use crate::song::{instrument::DEFAULT_INSTRUMENT, wave::DEFAULT_WAVE};
-use std::{
-    io::{self, BufRead, Read, Seek, SeekFrom, Write},
-    slice,
+use crate::{
+    fs::Filesystem,
+    song::{instrument::DEFAULT_INSTRUMENT, wave::DEFAULT_WAVE},
 };
-use system_interface::io::Peek;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use thiserror::Error;
 
 /// Compress data from an I/O reader into an LSDJ block
@@ -17,42 +16,235 @@ use thiserror::Error;
 ///
 ///  * The end of the reader has been reached, which returns [`End::EndOfFile`]
 ///  * The block is full. `next_block()` is called for retrieve the index of the next block, and [`End::JumpToBlock`] is returned.
+///
+/// This reads the rest of the reader into memory, compresses it with [`compress_block_from_slice()`]
+/// and seeks the reader back to just after the bytes that were compressed. Compressing a song block
+/// by block this way reads the remainder of the song again for every block, so this is deprecated
+/// in favor of [`compress_block_from_slice()`], which keeps track of the position itself.
+///
+/// The read can't be bounded, because a single block can stand for far more bytes than it holds
+/// (every 3-byte run-length command covers up to 255 of them).
+#[deprecated(
+    note = "Reads the rest of the reader for every block, use compress_block_from_slice()"
+)]
 pub fn compress_block<R, W, F>(
     mut reader: R,
+    writer: W,
+    next_block: F,
+) -> Result<End, CompressBlockError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+    F: FnOnce() -> Option<u8>,
+{
+    let start = reader.stream_position()?;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut position = 0;
+    let end = compress_block_from_slice(&bytes, &mut position, writer, next_block)?;
+    reader.seek(SeekFrom::Start(start + position as u64))?;
+
+    Ok(end)
+}
+
+/// Compress data from a byte slice into an LSDJ block
+///
+/// This works like [`compress_block()`], but compresses `bytes` starting at `position`, which
+/// is advanced past the bytes that were compressed into the block.
+///
+/// The writer needs room for at least the two bytes that end a block, or
+/// [`CompressBlockError::BlockTooSmall`] is returned without anything being written.
+pub fn compress_block_from_slice<W, F>(
+    bytes: &[u8],
+    position: &mut usize,
     mut writer: W,
     next_block: F,
 ) -> Result<End, CompressBlockError>
 where
-    R: Read + BufRead + Seek,
     W: Write + Seek,
     F: FnOnce() -> Option<u8>,
 {
-    let read_end = end(&mut reader)?;
     let write_end = end(&mut writer)?;
 
+    if write_end.saturating_sub(writer.stream_position()?) < 2 {
+        return Err(CompressBlockError::BlockTooSmall);
+    }
+
     loop {
         let write_pos = writer.stream_position()?;
         let left = write_end - write_pos;
 
         // Check if we've reached the end-of-file
-        if reader.stream_position()? == read_end {
+        if *position >= bytes.len() {
             writer.write_all(&[0xE0, 0xFF])?;
-            writer.write_all(&vec![0; (left - 2) as usize])?;
+            write_repeated_byte(0, (left - 2) as usize, &mut writer)?;
             return Ok(End::EndOfFile);
         }
 
+        // A step writes at most 3 bytes, which always leaves room for the 2 that end the block
         if left >= 5 {
-            let compression = compress_step(&mut reader)?;
+            let (compression, len) = compress_step(&bytes[*position..]);
+            *position += len;
             compression.write(&mut writer)?;
         } else {
             let index = next_block().ok_or(CompressBlockError::NoBlockLeft)?;
             writer.write_all(&[0xE0, index])?;
-            writer.write_all(&vec![0; (left - 2) as usize])?;
+            write_repeated_byte(0, (left - 2) as usize, &mut writer)?;
             return Ok(End::JumpToBlock(index));
         }
     }
 }
 
+/// Compress all data from an I/O reader into LSDJ blocks
+///
+/// Unlike [`compress_block_from_slice()`], this compresses everything up until end-of-file,
+/// writing every block directly into `writer`. `next_block()` is called for the index of every
+/// block, including the first, and each block is written `index * 512` bytes into the writer.
+/// This makes it possible to compress straight into a filesystem, without collecting the blocks
+/// first.
+///
+/// Returns the number of blocks written. On error, the blocks written so far are left as is.
+pub fn compress<R, W, F>(
+    mut reader: R,
+    writer: W,
+    next_block: F,
+) -> Result<usize, CompressBlockError>
+where
+    R: Read,
+    W: Write + Seek,
+    F: FnMut() -> Option<u8>,
+{
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    compress_from_slice(&bytes, writer, next_block)
+}
+
+/// Compress all data from a byte slice into LSDJ blocks
+///
+/// This works like [`compress()`], for data that is already in memory.
+pub fn compress_from_slice<W, F>(
+    bytes: &[u8],
+    mut writer: W,
+    mut next_block: F,
+) -> Result<usize, CompressBlockError>
+where
+    W: Write + Seek,
+    F: FnMut() -> Option<u8>,
+{
+    let mut position = 0;
+    let mut block = next_block().ok_or(CompressBlockError::NoBlockLeft)?;
+    let mut count = 0;
+
+    loop {
+        count += 1;
+
+        let start = block as u64 * Filesystem::BLOCK_LEN as u64;
+        writer.seek(SeekFrom::Start(start))?;
+
+        let end = compress_block_from_slice(
+            bytes,
+            &mut position,
+            BlockWriter {
+                inner: &mut writer,
+                start,
+                position: 0,
+            },
+            &mut next_block,
+        )?;
+
+        match end {
+            End::EndOfFile => return Ok(count),
+            End::JumpToBlock(next) => block = next,
+        }
+    }
+}
+
+/// A view onto a single block of a larger writer, so it can be compressed into
+///
+/// The position within the block is tracked here, so that the many position queries made
+/// during compression don't need to go through the inner writer.
+struct BlockWriter<W> {
+    inner: W,
+    start: u64,
+    position: u64,
+}
+
+impl<W> BlockWriter<W> {
+    const LEN: u64 = Filesystem::BLOCK_LEN as u64;
+}
+
+impl<W> Write for BlockWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let left = Self::LEN.saturating_sub(self.position) as usize;
+        let written = self.inner.write(&buf[..buf.len().min(left)])?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W> Seek for BlockWriter<W>
+where
+    W: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => Self::LEN.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+        if position != self.position {
+            self.inner.seek(SeekFrom::Start(self.start + position))?;
+            self.position = position;
+        }
+
+        Ok(position)
+    }
+}
+
+/// Count the number of blocks the data in an I/O reader would take up once compressed
+///
+/// This runs the same algorithm as [`compress_block_from_slice()`], but only ever writes into a
+/// single scratch block, so nothing but the count is kept. The result matches the number of
+/// blocks used by [`LsdSng::from_song()`](crate::lsdsng::LsdSng::from_song) or
+/// [`Filesystem::insert_file()`](crate::fs::Filesystem::insert_file).
+pub fn compressed_block_count<R>(mut reader: R) -> Result<usize, CompressBlockError>
+where
+    R: Read,
+{
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut position = 0;
+    let mut block = [0; Filesystem::BLOCK_LEN];
+    let mut count = 0;
+
+    loop {
+        count += 1;
+
+        let end = compress_block_from_slice(
+            &bytes,
+            &mut position,
+            Cursor::new(block.as_mut_slice()),
+            || Some(count as u8),
+        )?;
+
+        if end == End::EndOfFile {
+            return Ok(count);
+        }
+    }
+}
+
 /// Errors that might be returned from [`compress_block()`]
 #[derive(Debug, Error)]
 pub enum CompressBlockError {
@@ -63,6 +255,10 @@ pub enum CompressBlockError {
     // There are no more empty blocks left to continue to
     #[error("The filesystem ran out of blocks")]
     NoBlockLeft,
+
+    // The block being written to can't even fit the bytes that end it
+    #[error("The block is too small to write to")]
+    BlockTooSmall,
 }
 
 fn end<S>(mut seeker: S) -> io::Result<u64>
@@ -76,28 +272,32 @@ where
     Ok(end)
 }
 
-fn compress_step<R>(mut reader: R) -> io::Result<Compression>
-where
-    R: Read + Peek + BufRead + Seek,
-{
-    if let count @ 1.. = count_matches(&mut reader, 0, &DEFAULT_INSTRUMENT)? {
-        return Ok(Compression::DefaultInstrument { count });
+/// Find the best compression for the start of the slice, returning it with the number of bytes it covers
+fn compress_step(bytes: &[u8]) -> (Compression, usize) {
+    if let count @ 1.. = count_matches(bytes, &DEFAULT_INSTRUMENT) {
+        return (
+            Compression::DefaultInstrument { count },
+            count as usize * DEFAULT_INSTRUMENT.len(),
+        );
     }
 
-    if let count @ 1.. = count_matches(&mut reader, 0, &DEFAULT_WAVE)? {
-        return Ok(Compression::DefaultWave { count });
+    if let count @ 1.. = count_matches(bytes, &DEFAULT_WAVE) {
+        return (
+            Compression::DefaultWave { count },
+            count as usize * DEFAULT_WAVE.len(),
+        );
     }
 
-    match read_byte(&mut reader)? {
-        CMD_BYTE => Ok(Compression::CmdLiteral),
-        RLE_BYTE => Ok(Compression::RleLiteral),
-        value => {
-            if let count @ 2.. = count_matches(&mut reader, 1, slice::from_ref(&value))? {
-                Ok(Compression::RunLengthEncoding { value, count })
-            } else {
-                Ok(Compression::Literal { value })
-            }
-        }
+    match bytes[0] {
+        CMD_BYTE => (Compression::CmdLiteral, 1),
+        RLE_BYTE => (Compression::RleLiteral, 1),
+        value => match count_matches(bytes, &[value]) {
+            count @ 2.. => (
+                Compression::RunLengthEncoding { value, count },
+                count as usize,
+            ),
+            _ => (Compression::Literal { value }, 1),
+        },
     }
 }
 
@@ -129,34 +329,27 @@ impl Compression {
     }
 }
 
-fn count_matches<R>(mut reader: R, init: u8, slice: &[u8]) -> io::Result<u8>
-where
-    R: Read + Peek + BufRead + Seek,
-{
-    let mut count = init;
-    while matches_slice(&mut reader, slice)? && count < u8::MAX {
-        count += 1;
-        reader.seek(SeekFrom::Current(slice.len() as i64))?;
-    }
-    Ok(count)
-}
-
-fn matches_slice<R>(mut reader: R, slice: &[u8]) -> io::Result<bool>
-where
-    R: Read + Peek,
-{
-    let mut dest = vec![0; slice.len()];
-    if reader.peek(&mut dest)? == slice.len() {
-        Ok(dest == slice)
-    } else {
-        Ok(false)
-    }
+/// Count how many times `pattern` is repeated at the start of the slice (at most 255 times)
+fn count_matches(bytes: &[u8], pattern: &[u8]) -> u8 {
+    bytes
+        .chunks_exact(pattern.len())
+        .take(u8::MAX as usize)
+        .take_while(|chunk| *chunk == pattern)
+        .count() as u8
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Cursor;
+    use crate::{
+        fs::File,
+        lsdsng::LsdSng,
+        serde::{decompress_block, BlockJumps, Decompressor},
+        song::{
+            test_util::{noise, noise_bytes},
+            SongMemory,
+        },
+    };
 
     fn assert_write<const N: usize>(compression: Compression, expected: [u8; N]) {
         let mut dest = [0; N];
@@ -166,72 +359,74 @@ mod tests {
 
     #[test]
     fn matches() {
-        assert!(matches_slice(Cursor::new([0, 1]), &[0, 1]).unwrap());
-        assert!(!matches_slice(Cursor::new([0, 1]), &[0, 4]).unwrap());
-
-        assert_eq!(
-            count_matches(Cursor::new([5, 5, 5, 5, 6]), 0, &[5, 5]).unwrap(),
-            2
-        );
+        assert_eq!(count_matches(&[0, 1], &[0, 1]), 1);
+        assert_eq!(count_matches(&[0, 1], &[0, 4]), 0);
+        assert_eq!(count_matches(&[5, 5, 5, 5, 6], &[5, 5]), 2);
+        assert_eq!(count_matches(&[5; 300], &[5]), 255);
     }
 
     #[test]
     fn cmd_literal() {
-        let compression = compress_step(Cursor::new([0xE0])).unwrap();
+        let (compression, len) = compress_step(&[0xE0]);
         assert_eq!(compression, Compression::CmdLiteral);
+        assert_eq!(len, 1);
         assert_write(compression, [0xE0, 0xE0]);
     }
 
     #[test]
     fn rle_literal() {
-        let compression = compress_step(Cursor::new([0xC0])).unwrap();
+        let (compression, len) = compress_step(&[0xC0]);
         assert_eq!(compression, Compression::RleLiteral);
+        assert_eq!(len, 1);
         assert_write(compression, [0xC0, 0xC0]);
     }
 
     #[test]
     fn rle() {
-        let compression = compress_step(Cursor::new([4, 4, 4, 4, 4, 4, 4])).unwrap();
+        let (compression, len) = compress_step(&[4, 4, 4, 4, 4, 4, 4]);
         assert_eq!(
             compression,
             Compression::RunLengthEncoding { value: 4, count: 7 }
         );
+        assert_eq!(len, 7);
         assert_write(compression, [0xC0, 0x04, 0x07]);
     }
 
     #[test]
     fn value() {
-        let compression = compress_step(Cursor::new([4, 9])).unwrap();
+        let (compression, len) = compress_step(&[4, 9]);
         assert_eq!(compression, Compression::Literal { value: 4 });
+        assert_eq!(len, 1);
         assert_write(compression, [0x04]);
     }
 
     #[test]
     fn default_instrument() {
-        let compression = compress_step(Cursor::new([
+        let (compression, len) = compress_step(&[
             0xA8, 0x0, 0x0, 0xFF, 0x0, 0x0, 0x3, 0x0, 0x0, 0xD0, 0x0, 0x0, 0x0, 0xF3, 0x0, 0x0,
             0xA8, 0x0, 0x0, 0xFF, 0x0, 0x0, 0x3, 0x0, 0x0, 0xD0, 0x0, 0x0, 0x0, 0xF3, 0x0, 0x0,
             0xA8, 0x0, 0x0, 0xFF, 0x0, 0x0, 0x3, 0x0, 0x0, 0xD0, 0x0, 0x0, 0x0, 0xF3, 0x0, 0xFF,
-        ]))
-        .unwrap();
+        ]);
         assert_eq!(compression, Compression::DefaultInstrument { count: 2 });
+        assert_eq!(len, 32);
         assert_write(compression, [0xE0, 0xF1, 0x02]);
     }
 
     #[test]
     fn default_wave() {
-        let compression = compress_step(Cursor::new([
+        let (compression, len) = compress_step(&[
             0x8E, 0xCD, 0xCC, 0xBB, 0xAA, 0xA9, 0x99, 0x88, 0x87, 0x76, 0x66, 0x55, 0x54, 0x43,
             0x32, 0x31, 0x8E, 0xCD, 0xCC, 0xBB, 0xAA, 0xA9, 0x99, 0x88, 0x87, 0x76, 0x66, 0x55,
             0x54, 0x43, 0x32, 0x31, 0x8E, 0xCD, 0xCC, 0xBB, 0xAA, 0xA9, 0x99, 0x88, 0x87, 0x76,
             0x66, 0x55, 0x54, 0x43, 0x32, 0xFF,
-        ]))
-        .unwrap();
+        ]);
         assert_eq!(compression, Compression::DefaultWave { count: 2 });
+        assert_eq!(len, 32);
         assert_write(compression, [0xE0, 0xF0, 0x02]);
     }
 
     #[test]
+    #[allow(deprecated)]
     fn block() {
         let mut reader = Cursor::new([4, 4, 4, 9]);
 
@@ -244,4 +439,221 @@ mod tests {
 
         assert_eq!(dest, [0xC0, 4, 3, 0xE0, 1, 9, 0xE0, 0xFF, 0x0, 0x0]);
     }
+
+    #[test]
+    fn tiny_blocks() {
+        let inputs: [&[u8]; 4] = [&[], &[4, 4, 4, 9], &[0xE0, 0xC0, 1, 2], &DEFAULT_WAVE];
+
+        for len in 0..=8 {
+            for input in inputs {
+                let mut dest = vec![0xAA; len];
+                let mut position = 0;
+                let end =
+                    compress_block_from_slice(input, &mut position, Cursor::new(&mut dest), || {
+                        Some(1)
+                    });
+
+                if len < 2 {
+                    assert!(matches!(end, Err(CompressBlockError::BlockTooSmall)));
+                    assert_eq!(position, 0);
+                    assert!(dest.iter().all(|byte| *byte == 0xAA));
+                    continue;
+                }
+
+                let end = end.unwrap();
+                assert_eq!(end == End::EndOfFile, position == input.len());
+
+                let mut output = Vec::new();
+                assert_eq!(
+                    decompress_block(dest.as_slice(), Cursor::new(&mut output)).unwrap(),
+                    end
+                );
+                assert_eq!(output, &input[..position]);
+            }
+        }
+    }
+
+    /// Build song memory from a generator, with the initialization check bytes patched in
+    fn song(mut byte: impl FnMut(usize) -> u8) -> SongMemory {
+        let mut bytes: Vec<u8> = (0..SongMemory::LEN).map(&mut byte).collect();
+        for offset in [0x1E78, 0x3E80, 0x7FF0] {
+            bytes[offset..offset + 2].copy_from_slice(&[0x72, 0x62]);
+        }
+
+        SongMemory::from_bytes(&bytes).unwrap()
+    }
+
+    fn assert_round_trip(song: &SongMemory) {
+        let lsdsng = LsdSng::from_song("NOISE".try_into().unwrap(), 0, song).unwrap();
+        assert_eq!(lsdsng.decompress().unwrap().as_slice(), song.as_slice());
+        assert_eq!(
+            compressed_block_count(Cursor::new(song.as_slice())).unwrap(),
+            lsdsng.block_count()
+        );
+    }
+
+    #[test]
+    fn round_trip_noise() {
+        let mut noise = noise_bytes(1);
+        for _ in 0..32 {
+            let song = song(|_| noise.next().unwrap());
+
+            assert_round_trip(&song);
+        }
+    }
+
+    #[test]
+    fn round_trip_runs() {
+        // Runs of the bytes with special meaning, of random lengths
+        let special = [
+            RLE_BYTE,
+            CMD_BYTE,
+            DEFAULT_WAVE_BYTE,
+            DEFAULT_INSTRUMENT_BYTE,
+            0xFF,
+            0,
+        ];
+
+        let mut noise = noise(7);
+        for _ in 0..32 {
+            let mut value = 0;
+            let mut left = 0;
+            let song = song(|_| {
+                if left == 0 {
+                    let state = noise.next().unwrap();
+                    value = special[(state >> 16) as usize % special.len()];
+                    left = (state >> 24) as usize * 2 + 1;
+                }
+
+                left -= 1;
+                value
+            });
+
+            assert_round_trip(&song);
+        }
+    }
+
+    #[test]
+    fn round_trip_defaults() {
+        // Default waves and instruments, shifted so that they straddle block boundaries
+        for shift in 0..DEFAULT_WAVE.len() {
+            let song = song(|offset| match (offset + shift) / 0x100 % 3 {
+                0 => DEFAULT_WAVE[(offset + shift) % DEFAULT_WAVE.len()],
+                1 => DEFAULT_INSTRUMENT[(offset + shift) % DEFAULT_INSTRUMENT.len()],
+                _ => CMD_BYTE,
+            });
+
+            assert_round_trip(&song);
+        }
+    }
+
+    #[test]
+    fn compress_scattered() {
+        let mut noise = noise_bytes(3);
+        let song = song(|offset| match offset < 0x1000 {
+            true => noise.next().unwrap(),
+            false => 0,
+        });
+
+        // Hand out every third block, starting at the back
+        let mut blocks = (1..Filesystem::BLOCKS_CAPACITY as u8).rev().step_by(3);
+        let mut used = Vec::new();
+        let mut memory = vec![0; Filesystem::LEN];
+        let count = compress(
+            Cursor::new(song.as_slice()),
+            Cursor::new(&mut memory),
+            || {
+                let block = blocks.next()?;
+                used.push(block);
+                Some(block)
+            },
+        )
+        .unwrap();
+
+        // A block is only requested when the previous one jumps to it
+        assert_eq!(
+            count,
+            compressed_block_count(Cursor::new(song.as_slice())).unwrap()
+        );
+        assert!(count > 1);
+        assert_eq!(used.len(), count);
+
+        let mut reader = Cursor::new(&memory);
+        reader.set_position((used[0] as usize * Filesystem::BLOCK_LEN) as u64);
+        let mut decompressed = Vec::new();
+        Decompressor::new(
+            reader,
+            BlockJumps::FollowJumps {
+                block_len: Filesystem::BLOCK_LEN,
+            },
+        )
+        .read_to_end(&mut decompressed)
+        .unwrap();
+        assert_eq!(decompressed, song.as_slice());
+
+        // Every block not handed out is left untouched
+        for (block, bytes) in memory.chunks(Filesystem::BLOCK_LEN).enumerate() {
+            if !used.contains(&(block as u8)) {
+                assert!(bytes.iter().all(|byte| *byte == 0));
+            }
+        }
+    }
+
+    #[test]
+    fn compress_no_block_left() {
+        let mut blocks = [1, 2].into_iter();
+        let mut memory = vec![0; Filesystem::LEN];
+        let song = song(|offset| offset as u8);
+
+        assert!(matches!(
+            compress(
+                Cursor::new(song.as_slice()),
+                Cursor::new(&mut memory),
+                || blocks.next()
+            ),
+            Err(CompressBlockError::NoBlockLeft)
+        ));
+        assert!(matches!(
+            compress(
+                Cursor::new(song.as_slice()),
+                Cursor::new(&mut memory),
+                || None
+            ),
+            Err(CompressBlockError::NoBlockLeft)
+        ));
+    }
+
+    #[test]
+    fn compress_matches_lsdsng() {
+        let song = SongMemory::new();
+        let lsdsng = LsdSng::from_song("EMPTY".try_into().unwrap(), 0, &song).unwrap();
+
+        let mut blocks = Vec::new();
+        let mut next = 0..;
+        compress(
+            Cursor::new(song.as_slice()),
+            Cursor::new(&mut blocks),
+            || next.next(),
+        )
+        .unwrap();
+
+        // Only the jump targets differ, which .lsdsng files ignore
+        assert_eq!(blocks.len(), lsdsng.blocks().len());
+        let mut decompressed = Vec::new();
+        Decompressor::new(Cursor::new(&blocks), BlockJumps::Linear)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, song.as_slice());
+    }
+
+    #[test]
+    fn block_count() {
+        let song = SongMemory::new();
+        let lsdsng = LsdSng::from_song("EMPTY".try_into().unwrap(), 0, &song).unwrap();
+
+        assert_eq!(
+            compressed_block_count(Cursor::new(song.as_slice())).unwrap(),
+            lsdsng.block_count()
+        );
+    }
 }
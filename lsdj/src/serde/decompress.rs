@@ -5,11 +5,15 @@ use super::{
     },
     End,
 };
-use crate::song::{instrument::DEFAULT_INSTRUMENT, wave::DEFAULT_WAVE};
+use crate::{
+    fs::Filesystem,
+    song::{instrument::DEFAULT_INSTRUMENT, wave::DEFAULT_WAVE, SongMemory},
+};
 use std::{
-    io::{Read, Result, Seek, Write},
+    io::{self, Read, Result, Seek, SeekFrom, Write},
     slice,
 };
+use thiserror::Error;
 
 /// Decompress data from an LSDJ block reader to an arbitrary I/O writer
 ///
@@ -35,6 +39,230 @@ where
     }
 }
 
+/// A streaming decompressor, reading LSDJ blocks and producing the decompressed bytes
+///
+/// Unlike [`decompress_block()`], which needs a writer to decompress into, [`Decompressor`]
+/// implements [`Read`] itself. Every call decompresses just enough to fill the caller's buffer,
+/// following block jumps as dictated by [`BlockJumps`]. Reading ends at the end-of-file command.
+///
+/// Producing more than [`SongMemory::LEN`] bytes results in an [`io::ErrorKind::InvalidData`]
+/// error, as does jumping away from a block without having decompressed anything from it (which
+/// could otherwise loop forever). Running out of blocks before the end-of-file command results in
+/// an [`io::ErrorKind::UnexpectedEof`] error.
+pub struct Decompressor<R> {
+    /// The reader the compressed blocks are read from
+    reader: Input<R>,
+
+    /// How block jump commands are handled
+    jumps: BlockJumps,
+
+    /// The position of the reader when decompression started, recorded on the first read
+    start: Option<u64>,
+
+    /// The number of jumps followed so far
+    block: u64,
+
+    /// Bytes that have been decompressed, but not yet read
+    pending: Vec<u8>,
+
+    /// The number of bytes that have been decompressed so far
+    produced: usize,
+
+    /// The value of `produced` when the last block was entered
+    produced_at_jump: usize,
+
+    /// Has the end-of-file command been read?
+    done: bool,
+}
+
+impl<R> Decompressor<R>
+where
+    R: Read + Seek,
+{
+    /// Decompress the blocks from a reader, starting at its current position
+    ///
+    /// The position is taken when reading starts, so seeking the reader is possible until then.
+    pub fn new(reader: R, jumps: BlockJumps) -> Self {
+        Self {
+            reader: Input::new(reader),
+            jumps,
+            start: None,
+            block: 0,
+            pending: Vec::new(),
+            produced: 0,
+            produced_at_jump: 0,
+            done: false,
+        }
+    }
+
+    /// Unwrap the decompressor, returning the underlying reader
+    ///
+    /// The compressed bytes are read in chunks, so the reader might be positioned past the
+    /// last command that was decompressed.
+    pub fn into_inner(self) -> R {
+        self.reader.reader
+    }
+
+    /// Decompress the next command into the pending bytes
+    fn step(&mut self) -> Result<()> {
+        let len = self.pending.len();
+
+        match read_byte(&mut self.reader)? {
+            RLE_BYTE => decompress_rle_byte(&mut self.reader, &mut self.pending)?,
+            CMD_BYTE => match decompress_cmd_byte(&mut self.reader, &mut self.pending)? {
+                CmdContinuation::Continue => (),
+                CmdContinuation::End(End::EndOfFile) => self.done = true,
+                CmdContinuation::End(End::JumpToBlock(block)) => {
+                    if self.produced == self.produced_at_jump {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, EmptyBlock));
+                    }
+                    self.produced_at_jump = self.produced;
+
+                    self.block += 1;
+                    let position = match self.jumps {
+                        BlockJumps::Linear => {
+                            self.start.unwrap_or(0) + self.block * Filesystem::BLOCK_LEN as u64
+                        }
+                        BlockJumps::FollowJumps { block_len } => block as u64 * block_len as u64,
+                    };
+                    self.reader.seek(position)?;
+                }
+            },
+            value => {
+                // Literal bytes tend to come in runs, so copy over all that are buffered at once
+                self.pending.push(value);
+                self.pending.extend_from_slice(self.reader.literals());
+            }
+        }
+
+        self.produced += self.pending.len() - len;
+        if self.produced > SongMemory::LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                DecompressedTooLong,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<R> Read for Decompressor<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.start.is_none() {
+            self.start = Some(self.reader.reader.stream_position()?);
+        }
+
+        while self.pending.len() < buf.len() && !self.done {
+            self.step()?;
+        }
+
+        let len = self.pending.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+
+        Ok(len)
+    }
+}
+
+/// The compressed input of a [`Decompressor`], read from the underlying reader in chunks
+struct Input<R> {
+    reader: R,
+
+    /// The bytes read from the reader, of which the ones from `position` onward are unused
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<R> Input<R>
+where
+    R: Read + Seek,
+{
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Take the run of buffered bytes that don't start a command, without reading any more
+    fn literals(&mut self) -> &[u8] {
+        let unused = &self.buffer[self.position..];
+        let len = unused
+            .iter()
+            .position(|byte| *byte == RLE_BYTE || *byte == CMD_BYTE)
+            .unwrap_or(unused.len());
+
+        self.position += len;
+        &unused[..len]
+    }
+
+    /// Continue reading at an absolute position of the reader
+    fn seek(&mut self, position: u64) -> Result<()> {
+        self.buffer.clear();
+        self.position = 0;
+        self.reader.seek(SeekFrom::Start(position))?;
+        Ok(())
+    }
+}
+
+impl<R> Read for Input<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.position == self.buffer.len() {
+            self.buffer.resize(Filesystem::BLOCK_LEN, 0);
+            self.position = 0;
+
+            match self.reader.read(&mut self.buffer) {
+                Ok(len) => self.buffer.truncate(len),
+                Err(error) => {
+                    self.buffer.clear();
+                    return Err(error);
+                }
+            }
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.position);
+        buf[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// How a [`Decompressor`] handles block jump commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockJumps {
+    /// Ignore the jump target and continue with the next block
+    ///
+    /// The blocks are expected to be stored back to back from where decompression started, each
+    /// [`Filesystem::BLOCK_LEN`] bytes long. This is how `.lsdsng` files store them.
+    Linear,
+
+    /// Continue with the block the jump command points to
+    ///
+    /// The target is looked up as `block * block_len` from the start of the reader. This is how
+    /// the blocks in a [`Filesystem`] are stored.
+    FollowJumps { block_len: usize },
+}
+
+/// The error wrapped by the [`io::Error`] a [`Decompressor`] returns when it would produce more
+/// than [`SongMemory::LEN`] bytes
+#[derive(Debug, Error)]
+#[error("Decompression produced more than {} bytes", SongMemory::LEN)]
+pub(crate) struct DecompressedTooLong;
+
+/// The error wrapped by the [`io::Error`] a [`Decompressor`] returns when a block jumps away
+/// without containing any data
+#[derive(Debug, Error)]
+#[error("A block jumped to another block without containing any data")]
+struct EmptyBlock;
+
 #[derive(Debug, PartialEq, Eq)]
 enum CmdContinuation {
     Continue,
@@ -82,6 +310,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::song::test_util::{noise, noisy_song};
     use std::io::Cursor;
 
     #[test]
@@ -174,6 +403,111 @@ mod tests {
         );
     }
 
+    #[test]
+    fn decompressor_follow_jumps() {
+        // Block 0 jumps to block 2, which holds the end of the data
+        let mut blocks = [0_u8; 12];
+        blocks[..5].copy_from_slice(&[1, RLE_BYTE, 2, 3, CMD_BYTE]);
+        blocks[5] = 2;
+        blocks[8..].copy_from_slice(&[4, 5, CMD_BYTE, EOF_BYTE]);
+
+        let jumps = BlockJumps::FollowJumps { block_len: 4 };
+        let mut plain = Vec::new();
+        Decompressor::new(Cursor::new(blocks), jumps)
+            .read_to_end(&mut plain)
+            .unwrap();
+        assert_eq!(plain, [1, 2, 2, 2, 4, 5]);
+    }
+
+    #[test]
+    fn decompressor_linear_mid_stream() {
+        let song = noisy_song(0x600, 3);
+        let lsdsng =
+            crate::lsdsng::LsdSng::from_song("NOISE".try_into().unwrap(), 0, &song).unwrap();
+        assert!(lsdsng.block_count() > 1);
+
+        // Something else is stored before the blocks, like the header of an .lsdsng
+        let mut bytes = vec![0xAB; 9];
+        bytes.extend_from_slice(lsdsng.blocks());
+        let mut reader = Cursor::new(bytes);
+        reader.set_position(9);
+
+        let mut plain = Vec::new();
+        Decompressor::new(reader, BlockJumps::Linear)
+            .read_to_end(&mut plain)
+            .unwrap();
+        assert_eq!(plain, song.as_slice());
+    }
+
+    #[test]
+    fn decompressor_small_reads() {
+        let lsdsng =
+            crate::lsdsng::LsdSng::from_song("EMPTY".try_into().unwrap(), 0, &SongMemory::new())
+                .unwrap();
+
+        let mut decompressor = Decompressor::new(Cursor::new(lsdsng.blocks()), BlockJumps::Linear);
+        let mut plain = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            match decompressor.read(&mut buf).unwrap() {
+                0 => break,
+                len => plain.extend_from_slice(&buf[..len]),
+            }
+        }
+
+        assert_eq!(plain, SongMemory::new().as_slice());
+    }
+
+    #[test]
+    fn decompressor_too_long() {
+        // Every RLE command produces 255 bytes, which eventually exceeds song memory
+        let blocks = [RLE_BYTE, 0, 0xFF].repeat(SongMemory::LEN / 0xFF + 1);
+
+        let mut plain = Vec::new();
+        let error = Decompressor::new(Cursor::new(blocks), BlockJumps::Linear)
+            .read_to_end(&mut plain)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(plain.len() <= SongMemory::LEN);
+    }
+
+    #[test]
+    fn decompressor_empty_block() {
+        // A block that jumps to itself would never end
+        let jumps = BlockJumps::FollowJumps { block_len: 2 };
+        let error = Decompressor::new(Cursor::new([CMD_BYTE, 0]), jumps)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decompressor_noise() {
+        let mut noise = noise(1);
+        for _ in 0..256 {
+            let blocks: Vec<u8> = (&mut noise)
+                .take(0x800)
+                .map(|state| {
+                    // Favor the special bytes, so commands are hit often
+                    match (state >> 16) % 8 {
+                        0 => RLE_BYTE,
+                        1 => CMD_BYTE,
+                        _ => (state >> 24) as u8,
+                    }
+                })
+                .collect();
+
+            for jumps in [
+                BlockJumps::Linear,
+                BlockJumps::FollowJumps { block_len: 0x10 },
+            ] {
+                let mut plain = Vec::new();
+                let _ = Decompressor::new(Cursor::new(&blocks), jumps).read_to_end(&mut plain);
+                assert!(plain.len() <= SongMemory::LEN);
+            }
+        }
+    }
+
     #[test]
     fn eof() {
         let mut plain = [0_u8; 1];
@@ -4,12 +4,18 @@ mod compress;
 mod decompress;
 mod utils;
 
-pub use compress::{compress_block, CompressBlockError};
-pub use decompress::decompress_block;
+#[allow(deprecated)]
+pub use compress::compress_block;
+pub use compress::{
+    compress, compress_block_from_slice, compress_from_slice, compressed_block_count,
+    CompressBlockError,
+};
+pub(crate) use decompress::DecompressedTooLong;
+pub use decompress::{decompress_block, BlockJumps, Decompressor};
 
 /// The result of block compression/decompression
 ///
-/// See [`compress_block`] and [`decompress_block`] for more information on when this is returned
+/// See [`compress_block_from_slice`] and [`decompress_block`] for more information on when this is returned
 #[derive(Debug, PartialEq, Eq)]
 pub enum End {
     /// A block-jump command has been written/read
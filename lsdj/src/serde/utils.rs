@@ -29,9 +29,47 @@ pub fn write_repeated_bytes<W>(bytes: &[u8], count: usize, mut writer: W) -> Res
 where
     W: Write,
 {
-    for _ in 0..count {
-        writer.write_all(bytes)?
+    // Fill a buffer with as many repetitions as fit once, and write that in as few calls as possible
+    let mut buffer = [0; 0x100];
+    if bytes.is_empty() || bytes.len() > buffer.len() {
+        for _ in 0..count {
+            writer.write_all(bytes)?
+        }
+
+        return Ok(());
+    }
+
+    let per_chunk = (buffer.len() / bytes.len()).min(count);
+    for chunk in buffer.chunks_exact_mut(bytes.len()).take(per_chunk) {
+        chunk.copy_from_slice(bytes);
+    }
+
+    let mut left = count;
+    while left > 0 {
+        let repetitions = left.min(per_chunk);
+        writer.write_all(&buffer[..repetitions * bytes.len()])?;
+        left -= repetitions;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repeated(bytes: &[u8], count: usize) -> Vec<u8> {
+        let mut dest = Vec::new();
+        write_repeated_bytes(bytes, count, &mut dest).unwrap();
+        dest
+    }
+
+    #[test]
+    fn repeated_bytes() {
+        assert!(repeated(&[1, 2], 0).is_empty());
+        assert_eq!(repeated(&[1, 2], 1), [1, 2]);
+        assert_eq!(repeated(&[7], 300), [7; 300]);
+        assert_eq!(repeated(&[1, 2, 3], 100), [1, 2, 3].repeat(100));
+        assert_eq!(repeated(&[9; 0x180], 3), [9; 0x480]);
+    }
+}
@@ -0,0 +1,227 @@
+//! Comparing two versions of a song
+
+use super::{v22, SongMemory};
+use std::ops::Range;
+use thiserror::Error;
+
+/// A region of song memory, grouping related data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum Region {
+    /// The song arrangement (the chains played per channel)
+    Song,
+
+    /// Chains (the phrases and transpositions per step, and allocation)
+    Chains,
+
+    /// Phrases (the notes, instruments and commands per step, and allocation)
+    Phrases,
+
+    /// Instrument parameters, names and allocation
+    Instruments,
+
+    /// Tables (envelopes, transpositions and commands, and allocation)
+    Tables,
+
+    /// Grooves
+    Grooves,
+
+    /// Wave frames
+    Waves,
+
+    /// Synth parameters
+    Synths,
+
+    /// Speech synth words and their names
+    Words,
+
+    /// Anything not part of the other regions (settings, bookmarks, etc.)
+    Other,
+}
+
+/// The changes in a single [`Region`] between two songs
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub struct RegionDiff {
+    /// The region in which the changes were found
+    pub region: Region,
+
+    /// The number of bytes that differ within the region
+    pub bytes_changed: usize,
+}
+
+/// The differences between two songs, as returned by [`diff()`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub struct SongDiff {
+    /// The regions that contain changes, in order of appearance in [`Region`]
+    pub regions: Vec<RegionDiff>,
+}
+
+impl SongDiff {
+    /// Are the songs identical?
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+/// Compare two songs and report which regions of memory differ
+///
+/// The regions are based on the memory layout of the song's format version, which is why
+/// both songs need to be of the same (supported) format version.
+pub fn diff(a: &SongMemory, b: &SongMemory) -> Result<SongDiff, DiffError> {
+    let version = a.format_version();
+    if b.format_version() != version {
+        return Err(DiffError::FormatVersionMismatch {
+            a: version,
+            b: b.format_version(),
+        });
+    }
+
+    let regions = regions(version).ok_or(DiffError::UnsupportedFormatVersion(version))?;
+
+    let mut changes = vec![0; REGION_ORDER.len()];
+    for (offset, (a, b)) in a.as_slice().iter().zip(b.as_slice()).enumerate() {
+        if a != b {
+            let region = regions
+                .iter()
+                .find_map(|(region, range)| range.contains(&offset).then_some(*region))
+                .unwrap_or(Region::Other);
+
+            let position = REGION_ORDER.iter().position(|r| *r == region).unwrap();
+            changes[position] += 1;
+        }
+    }
+
+    let regions = REGION_ORDER
+        .iter()
+        .zip(changes)
+        .filter(|(_, bytes_changed)| *bytes_changed > 0)
+        .map(|(region, bytes_changed)| RegionDiff {
+            region: *region,
+            bytes_changed,
+        })
+        .collect();
+
+    Ok(SongDiff { regions })
+}
+
+/// Errors that might be returned from [`diff()`]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DiffError {
+    /// The songs are stored in different format versions and can't be compared
+    #[error("The songs have different format versions ({a} and {b})")]
+    FormatVersionMismatch { a: u8, b: u8 },
+
+    /// The memory layout for this format version isn't known
+    #[error("Format version {0} is not supported")]
+    UnsupportedFormatVersion(u8),
+}
+
+const REGION_ORDER: [Region; 10] = [
+    Region::Song,
+    Region::Chains,
+    Region::Phrases,
+    Region::Instruments,
+    Region::Tables,
+    Region::Grooves,
+    Region::Waves,
+    Region::Synths,
+    Region::Words,
+    Region::Other,
+];
+
+/// The memory ranges per region for format version 22, taken from its memory layout
+const V22: [(Region, Range<usize>); 24] = [
+    (Region::Phrases, v22::PHRASE_NOTES_RANGE),
+    (Region::Grooves, v22::GROOVES_RANGE),
+    (Region::Song, v22::CHAIN_ASSIGNMENTS_RANGE),
+    (Region::Tables, v22::TABLE_ENVELOPES_RANGE),
+    (Region::Words, v22::WORDS_RANGE),
+    (Region::Words, v22::WORD_NAMES_RANGE),
+    (Region::Instruments, v22::INSTRUMENT_NAMES_RANGE),
+    (Region::Tables, v22::TABLE_ALLOCATION_RANGE),
+    (Region::Instruments, v22::INSTRUMENT_ALLOCATION_RANGE),
+    (Region::Chains, v22::CHAIN_PHRASES_RANGE),
+    (Region::Chains, v22::CHAIN_TRANSPOSES_RANGE),
+    (Region::Instruments, v22::INSTRUMENT_PARAMETERS_RANGE),
+    (Region::Tables, v22::TABLE_TRANSPOSES_RANGE),
+    (Region::Tables, v22::TABLE_COMMANDS1_RANGE),
+    (Region::Tables, v22::TABLE_COMMAND1_VALUES_RANGE),
+    (Region::Tables, v22::TABLE_COMMANDS2_RANGE),
+    (Region::Tables, v22::TABLE_COMMAND2_VALUES_RANGE),
+    (Region::Phrases, v22::PHRASE_ALLOCATION_RANGE),
+    (Region::Chains, v22::CHAIN_ALLOCATION_RANGE),
+    (Region::Synths, v22::SYNTH_PARAMETERS_RANGE),
+    (Region::Phrases, v22::PHRASE_COMMANDS_RANGE),
+    (Region::Phrases, v22::PHRASE_COMMAND_VALUES_RANGE),
+    (Region::Waves, v22::WAVES_RANGE),
+    (Region::Phrases, v22::PHRASE_INSTRUMENTS_RANGE),
+];
+
+/// Look up the memory regions for a specific format version
+///
+/// Only the layouts that have been verified are known, other versions are unsupported.
+fn regions(format_version: u8) -> Option<&'static [(Region, Range<usize>)]> {
+    match format_version {
+        22 => Some(&V22),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical() {
+        let song = SongMemory::new();
+        assert!(diff(&song, &song).unwrap().is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let a = SongMemory::new();
+        let mut b = SongMemory::new();
+        b.as_mut_slice()[0x6000] ^= 0xFF;
+        b.as_mut_slice()[0x6001] ^= 0xFF;
+        b.as_mut_slice()[0x2080] ^= 0xFF;
+
+        assert_eq!(
+            diff(&a, &b).unwrap().regions,
+            [
+                RegionDiff {
+                    region: Region::Chains,
+                    bytes_changed: 1
+                },
+                RegionDiff {
+                    region: Region::Waves,
+                    bytes_changed: 2
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn format_version_mismatch() {
+        let a = SongMemory::new();
+        let mut b = SongMemory::new();
+        b.as_mut_slice()[0x7FFF] = 0x15;
+
+        assert_eq!(
+            diff(&a, &b),
+            Err(DiffError::FormatVersionMismatch { a: 0x16, b: 0x15 })
+        );
+    }
+
+    #[test]
+    fn unsupported_format_version() {
+        let mut song = SongMemory::new();
+        song.as_mut_slice()[0x7FFF] = 0x15;
+
+        assert_eq!(
+            diff(&song, &song),
+            Err(DiffError::UnsupportedFormatVersion(0x15))
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! Offsets of well-known fields within [`SongMemory`](super::SongMemory)
+//!
+//! Most of the song memory layout changes between format versions, but some fields live at the
+//! same place for several versions in a row. Supporting a new format version for these fields
+//! should only require adding an entry to [`FIELD_OFFSETS`].
+
+/// The location of well-known fields within song memory for a specific format version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FieldOffsets {
+    pub work_hours: usize,
+    pub work_minutes: usize,
+    pub tempo: usize,
+    pub total_days: usize,
+    pub total_hours: usize,
+    pub total_minutes: usize,
+    pub sync: usize,
+    pub clone: usize,
+    pub file_changed: usize,
+}
+
+/// The field offsets shared by format versions 20 up to 22
+const V20_22: FieldOffsets = FieldOffsets {
+    work_hours: 0x3FB2,
+    work_minutes: 0x3FB3,
+    tempo: 0x3FB4,
+    total_days: 0x3FB6,
+    total_hours: 0x3FB7,
+    total_minutes: 0x3FB8,
+    sync: 0x3FBD,
+    clone: 0x3FC0,
+    file_changed: 0x3FC1,
+};
+
+/// The field offsets for every supported format version
+const FIELD_OFFSETS: [(u8, FieldOffsets); 3] = [(20, V20_22), (21, V20_22), (22, V20_22)];
+
+/// Look up the field offsets for a specific format version
+pub(crate) fn field_offsets(format_version: u8) -> Option<&'static FieldOffsets> {
+    FIELD_OFFSETS
+        .iter()
+        .find_map(|(version, offsets)| (*version == format_version).then_some(offsets))
+}
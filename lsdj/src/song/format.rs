@@ -0,0 +1,115 @@
+//! Song format versions, and the LSDJ releases that write them
+//!
+//! Every song stores the version of the format it is encoded in as its last byte. Naming a
+//! newly identified format version only requires a [`KnownFormatVersion`] variant and an entry
+//! in [`KNOWN_FORMAT_VERSIONS`].
+
+use std::fmt;
+
+/// A format version LSDJ is known to write songs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum KnownFormatVersion {
+    /// Format version 22 (0x16)
+    V22,
+}
+
+/// Every known format version, with the byte it is stored as and the LSDJ releases writing it
+const KNOWN_FORMAT_VERSIONS: [(KnownFormatVersion, u8, &str); 1] =
+    [(KnownFormatVersion::V22, 0x16, "9.2.x")];
+
+impl KnownFormatVersion {
+    /// The byte the format version is stored as
+    pub fn byte(self) -> u8 {
+        self.entry().1
+    }
+
+    /// The range of LSDJ releases that write songs in this format version
+    pub fn lsdj_versions(self) -> &'static str {
+        self.entry().2
+    }
+
+    fn entry(self) -> &'static (KnownFormatVersion, u8, &'static str) {
+        KNOWN_FORMAT_VERSIONS
+            .iter()
+            .find(|(version, _, _)| *version == self)
+            .expect("every known format version has an entry")
+    }
+}
+
+/// The format version a song is encoded in, as returned by [`SongMemory::format()`](super::SongMemory::format)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FormatVersion {
+    /// A format version written by a known range of LSDJ releases
+    Known(KnownFormatVersion),
+
+    /// A format version that isn't (yet) known, with its raw byte
+    Unknown(u8),
+}
+
+impl FormatVersion {
+    /// Look up the format version stored as a specific byte
+    pub fn from_byte(byte: u8) -> Self {
+        KNOWN_FORMAT_VERSIONS
+            .iter()
+            .find_map(|(version, known, _)| (*known == byte).then_some(Self::Known(*version)))
+            .unwrap_or(Self::Unknown(byte))
+    }
+
+    /// The byte the format version is stored as
+    pub fn byte(self) -> u8 {
+        match self {
+            Self::Known(version) => version.byte(),
+            Self::Unknown(byte) => byte,
+        }
+    }
+}
+
+impl From<u8> for FormatVersion {
+    fn from(byte: u8) -> Self {
+        Self::from_byte(byte)
+    }
+}
+
+impl fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Known(version) => {
+                write!(f, "{} (LSDJ {})", version.byte(), version.lsdj_versions())
+            }
+            Self::Unknown(byte) => write!(f, "{byte} (unknown LSDJ version)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known() {
+        for (version, byte, lsdj) in KNOWN_FORMAT_VERSIONS {
+            assert_eq!(
+                FormatVersion::from_byte(byte),
+                FormatVersion::Known(version)
+            );
+            assert_eq!(FormatVersion::Known(version).byte(), byte);
+            assert_eq!(version.lsdj_versions(), lsdj);
+        }
+    }
+
+    #[test]
+    fn v22() {
+        let version = FormatVersion::from_byte(0x16);
+        assert_eq!(version, FormatVersion::Known(KnownFormatVersion::V22));
+        assert_eq!(version.to_string(), "22 (LSDJ 9.2.x)");
+    }
+
+    #[test]
+    fn unknown() {
+        let version = FormatVersion::from(5);
+        assert_eq!(version, FormatVersion::Unknown(5));
+        assert_eq!(version.byte(), 5);
+        assert_eq!(version.to_string(), "5 (unknown LSDJ version)");
+    }
+}
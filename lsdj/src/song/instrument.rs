@@ -1,4 +1,182 @@
+//! Instruments, as played by the channels
+
+use ux::u4;
+
 /// The default string of bytes for a new instrument
 pub const DEFAULT_INSTRUMENT: [u8; 16] = [
     0xA8, 0x0, 0x0, 0xFF, 0x0, 0x0, 0x3, 0x0, 0x0, 0xD0, 0x0, 0x0, 0x0, 0xF3, 0x0, 0x0,
 ];
+
+/// An instrument, with the parameters specific to its type
+///
+/// Not every parameter is parsed yet. The fields available are those that have been
+/// implemented so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instrument {
+    /// An instrument for the pulse channels
+    Pulse(PulseInstrument),
+
+    /// An instrument playing wave frames or synths on the wave channel
+    Wave(WaveInstrument),
+
+    /// An instrument playing samples from kits on the wave channel
+    Kit(KitInstrument),
+
+    /// An instrument for the noise channel
+    Noise(NoiseInstrument),
+}
+
+impl Instrument {
+    /// The table run alongside the instrument, if any
+    pub fn table(&self) -> Option<u8> {
+        match self {
+            Self::Pulse(instrument) => instrument.table,
+            Self::Wave(instrument) => instrument.table,
+            Self::Kit(instrument) => instrument.table,
+            Self::Noise(instrument) => instrument.table,
+        }
+    }
+
+    /// Does the table advance with every note, instead of running on its own?
+    pub fn automate(&self) -> bool {
+        match self {
+            Self::Pulse(instrument) => instrument.automate,
+            Self::Wave(instrument) => instrument.automate,
+            Self::Kit(instrument) => instrument.automate,
+            Self::Noise(instrument) => instrument.automate,
+        }
+    }
+
+    /// The speaker(s) the instrument plays on
+    pub fn output(&self) -> Output {
+        match self {
+            Self::Pulse(instrument) => instrument.output,
+            Self::Wave(instrument) => instrument.output,
+            Self::Kit(instrument) => instrument.output,
+            Self::Noise(instrument) => instrument.output,
+        }
+    }
+}
+
+/// The parameters of an [`Instrument::Pulse`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PulseInstrument {
+    /// The raw volume envelope
+    pub envelope: u8,
+
+    /// The number of semitones the second pulse channel is transposed by in PU1+2 mode
+    pub phase_transpose: u8,
+
+    /// The length of the sound, or `None` if it sounds until the next note
+    pub length: Option<u8>,
+
+    /// The raw frequency sweep
+    pub sweep: u8,
+
+    /// The duty cycle of the pulse wave
+    pub pulse_width: PulseWidth,
+
+    /// The fine tuning of the pitch
+    pub finetune: u4,
+
+    /// The speaker(s) the instrument plays on
+    pub output: Output,
+
+    /// The table run alongside the instrument, if any
+    pub table: Option<u8>,
+
+    /// Does the table advance with every note, instead of running on its own?
+    pub automate: bool,
+}
+
+/// The parameters of an [`Instrument::Wave`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WaveInstrument {
+    /// The raw volume
+    pub volume: u8,
+
+    /// The synth played by the instrument
+    pub synth: u4,
+
+    /// The speaker(s) the instrument plays on
+    pub output: Output,
+
+    /// The table run alongside the instrument, if any
+    pub table: Option<u8>,
+
+    /// Does the table advance with every note, instead of running on its own?
+    pub automate: bool,
+}
+
+/// The parameters of an [`Instrument::Kit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KitInstrument {
+    /// The raw volume
+    pub volume: u8,
+
+    /// The index of the first kit in the ROM
+    pub kit1: u8,
+
+    /// The index of the second kit in the ROM
+    pub kit2: u8,
+
+    /// The speaker(s) the instrument plays on
+    pub output: Output,
+
+    /// The table run alongside the instrument, if any
+    pub table: Option<u8>,
+
+    /// Does the table advance with every note, instead of running on its own?
+    pub automate: bool,
+}
+
+/// The parameters of an [`Instrument::Noise`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NoiseInstrument {
+    /// The raw volume envelope
+    pub envelope: u8,
+
+    /// The length of the sound, or `None` if it sounds until the next note
+    pub length: Option<u8>,
+
+    /// The speaker(s) the instrument plays on
+    pub output: Output,
+
+    /// The table run alongside the instrument, if any
+    pub table: Option<u8>,
+
+    /// Does the table advance with every note, instead of running on its own?
+    pub automate: bool,
+}
+
+/// The duty cycle of a [`PulseInstrument`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PulseWidth {
+    /// 12.5%
+    Eighth,
+
+    /// 25%
+    Quarter,
+
+    /// 50%
+    Half,
+
+    /// 75%
+    ThreeQuarters,
+}
+
+/// The speaker(s) an [`Instrument`] plays on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Output {
+    /// Muted
+    None,
+
+    /// Only the right speaker
+    Right,
+
+    /// Only the left speaker
+    Left,
+
+    /// Both speakers
+    Both,
+}
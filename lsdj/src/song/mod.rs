@@ -1,15 +1,29 @@
-//! Unparsed LSDJ song memory
+//! LSDJ song memory, and parsing it into songs
+//!
+//! [`SongMemory`] holds the raw bytes of a song. Songs stored in format version 22 can be parsed
+//! with [`v22::Song::from_memory()`].
 
-pub(crate) mod instrument;
-pub(crate) mod wave;
+pub mod diff;
+mod fields;
+mod format;
+pub mod instrument;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod v22;
+pub mod wave;
 
-use std::io::{self, Read, Write};
+use crate::serde::{self, CompressBlockError};
+pub use diff::{diff, SongDiff};
+use fields::{field_offsets, FieldOffsets};
+pub use format::{FormatVersion, KnownFormatVersion};
+use std::io::{self, Cursor, Read, Write};
 use thiserror::Error;
 
 /// A contiguous block of memory that represents unparsed song data
 ///
-/// Future versions of this create might parse [`SongMemory`] into different formatted versions
-/// of songs, but for now this suffices to import and export songs from [`SRam`](crate::sram).
+/// This is what gets imported and exported from [`SRam`](crate::sram). To read the song itself,
+/// check its [`format_version()`](SongMemory::format_version) and parse it with the matching
+/// module, such as [`v22::Song::from_memory()`].
 pub struct SongMemory {
     /// The bytes that make up the song
     bytes: [u8; Self::LEN],
@@ -30,32 +44,63 @@ impl SongMemory {
 
     /// Deserialize [`SongMemory`] from bytes
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromBytesError> {
+        Self::from_bytes_with_validation(bytes, Validation::Strict)
+    }
+
+    /// Deserialize [`SongMemory`] from bytes, choosing how strictly the bytes are verified
+    pub fn from_bytes_with_validation(
+        bytes: &[u8],
+        validation: Validation,
+    ) -> Result<Self, FromBytesError> {
         let bytes: [u8; Self::LEN] = bytes
             .try_into()
             .map_err(|_| FromBytesError::IncorrectSize)?;
 
-        let check = |offset| bytes[offset] == 0x72 && bytes[offset + 1] == 0x62;
+        let song = Self { bytes };
 
-        if check(0x1E78) || check(0x3E80) || check(0x7FF0) {
-            Ok(Self { bytes })
+        if validation == Validation::Lenient || song.has_initialization_check() {
+            Ok(song)
         } else {
             Err(FromBytesError::InitializationCheckIncorrect)
         }
     }
 
     /// Deserialize [`SongMemory`] from an arbitrary I/O reader
-    pub fn from_reader<R>(mut reader: R) -> Result<Self, FromReaderError>
+    pub fn from_reader<R>(reader: R) -> Result<Self, FromReaderError>
+    where
+        R: Read,
+    {
+        Self::from_reader_with_validation(reader, Validation::Strict)
+    }
+
+    /// Deserialize [`SongMemory`] from an arbitrary I/O reader, choosing how strictly the bytes
+    /// are verified
+    pub fn from_reader_with_validation<R>(
+        mut reader: R,
+        validation: Validation,
+    ) -> Result<Self, FromReaderError>
     where
         R: Read,
     {
         let mut bytes = [0; Self::LEN];
         reader.read_exact(bytes.as_mut_slice())?;
 
-        let song = Self::from_bytes(&bytes)?;
+        let song = Self::from_bytes_with_validation(&bytes, validation)?;
 
         Ok(song)
     }
 
+    /// Are the bytes LSDJ uses to verify song memory against corruption set?
+    ///
+    /// Only the offsets used by recent versions of LSDJ are known, so songs saved by very old
+    /// versions might fail this check while being perfectly fine. [`Validation::Lenient`] can
+    /// be used to load those anyway.
+    pub fn has_initialization_check(&self) -> bool {
+        let check = |offset: usize| self.bytes[offset] == 0x72 && self.bytes[offset + 1] == 0x62;
+
+        check(0x1E78) || check(0x3E80) || check(0x7FF0)
+    }
+
     /// Serialize [`SongMemory`] to an arbitrary I/O writer
     pub fn to_writer<W>(&self, mut writer: W) -> Result<(), io::Error>
     where
@@ -65,10 +110,125 @@ impl SongMemory {
     }
 
     /// The version of the format the song is encoded in
+    ///
+    /// This is the raw byte, see [`SongMemory::format()`] for the interpreted version.
     pub fn format_version(&self) -> u8 {
         self.bytes[0x7FFF]
     }
 
+    /// The version of the format the song is encoded in, and the LSDJ releases writing it
+    pub fn format(&self) -> FormatVersion {
+        FormatVersion::from_byte(self.format_version())
+    }
+
+    /// The tempo of the song, in beats per minute
+    ///
+    /// This is the raw byte stored in memory. LSDJ uses the values 0-39 for tempos above 255 BPM.
+    pub fn tempo(&self) -> Result<u8, UnsupportedFormatVersion> {
+        Ok(self.bytes[self.field_offsets()?.tempo])
+    }
+
+    /// Change the tempo of the song (see [`SongMemory::tempo()`])
+    pub fn set_tempo(&mut self, tempo: u8) -> Result<(), UnsupportedFormatVersion> {
+        let offset = self.field_offsets()?.tempo;
+        self.bytes[offset] = tempo;
+        Ok(())
+    }
+
+    /// The number of hours worked on the song (in the current session)
+    pub fn work_hours(&self) -> Result<u8, UnsupportedFormatVersion> {
+        Ok(self.bytes[self.field_offsets()?.work_hours])
+    }
+
+    /// The number of minutes worked on the song (in the current session)
+    pub fn work_minutes(&self) -> Result<u8, UnsupportedFormatVersion> {
+        Ok(self.bytes[self.field_offsets()?.work_minutes])
+    }
+
+    /// The total amount of time worked on the song
+    pub fn total_time(&self) -> Result<TotalTime, UnsupportedFormatVersion> {
+        let offsets = self.field_offsets()?;
+
+        Ok(TotalTime {
+            days: self.bytes[offsets.total_days],
+            hours: self.bytes[offsets.total_hours],
+            minutes: self.bytes[offsets.total_minutes],
+        })
+    }
+
+    /// The raw sync setting, where 0 means the song doesn't sync to anything
+    pub fn sync(&self) -> Result<u8, UnsupportedFormatVersion> {
+        Ok(self.bytes[self.field_offsets()?.sync])
+    }
+
+    /// Change the raw sync setting (see [`SongMemory::sync()`])
+    pub fn set_sync(&mut self, sync: u8) -> Result<(), UnsupportedFormatVersion> {
+        let offset = self.field_offsets()?.sync;
+        self.bytes[offset] = sync;
+        Ok(())
+    }
+
+    /// How chains and phrases are cloned in the song editor
+    pub fn clone_mode(&self) -> Result<CloneMode, UnsupportedFormatVersion> {
+        Ok(match self.bytes[self.field_offsets()?.clone] {
+            0 => CloneMode::Deep,
+            _ => CloneMode::Slim,
+        })
+    }
+
+    /// Change how chains and phrases are cloned (see [`SongMemory::clone_mode()`])
+    pub fn set_clone_mode(&mut self, mode: CloneMode) -> Result<(), UnsupportedFormatVersion> {
+        let offset = self.field_offsets()?.clone;
+        self.bytes[offset] = match mode {
+            CloneMode::Deep => 0,
+            CloneMode::Slim => 1,
+        };
+        Ok(())
+    }
+
+    /// Has the song been changed since it was last saved to the filesystem?
+    pub fn file_changed(&self) -> Result<bool, UnsupportedFormatVersion> {
+        Ok(self.bytes[self.field_offsets()?.file_changed] != 0)
+    }
+
+    /// A SHA-256 hash of the song contents
+    ///
+    /// The "file changed" flag (see [`SongMemory::file_changed()`]) is hashed as if it were
+    /// cleared, so a song that was merely opened and closed again hashes the same. Songs in an
+    /// unsupported format version are hashed as-is.
+    #[cfg(feature = "hash")]
+    pub fn content_hash(&self) -> [u8; 32] {
+        use sha2::{Digest, Sha256};
+
+        let mut hasher = Sha256::new();
+        match self.field_offsets() {
+            Ok(offsets) => {
+                let (before, after) = self.bytes.split_at(offsets.file_changed);
+                hasher.update(before);
+                hasher.update([0]);
+                hasher.update(&after[1..]);
+            }
+            Err(_) => hasher.update(self.bytes),
+        }
+
+        hasher.finalize().into()
+    }
+
+    /// Look up where the well-known fields are stored for this song's format version
+    fn field_offsets(&self) -> Result<&'static FieldOffsets, UnsupportedFormatVersion> {
+        let version = self.format_version();
+        field_offsets(version).ok_or(UnsupportedFormatVersion(version))
+    }
+
+    /// The number of filesystem blocks the song takes up once compressed
+    ///
+    /// This runs the actual compression algorithm without storing the result, so the count
+    /// takes the block jump commands at the end of every block into account. See
+    /// [`serde::compressed_block_count()`](crate::serde::compressed_block_count).
+    pub fn compressed_block_count(&self) -> Result<usize, CompressBlockError> {
+        serde::compressed_block_count(Cursor::new(self.as_slice()))
+    }
+
     /// Access the bytes that make up the song
     pub fn as_slice(&self) -> &[u8] {
         &self.bytes
@@ -86,6 +246,75 @@ impl Default for SongMemory {
     }
 }
 
+impl<'a> TryFrom<&'a [u8]> for SongMemory {
+    type Error = FromBytesError;
+
+    #[inline]
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(bytes)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for SongMemory {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        crate::hex::serialize(&self.bytes, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for SongMemory {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        let bytes = crate::hex::deserialize(deserializer)?;
+        Self::from_bytes(&bytes).map_err(::serde::de::Error::custom)
+    }
+}
+
+/// How strictly [`SongMemory`] is verified when it's deserialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Validation {
+    /// Reject songs that fail the initialization check (see [`SongMemory::has_initialization_check()`])
+    #[default]
+    Strict,
+
+    /// Accept songs regardless of the initialization check, only checking their size
+    Lenient,
+}
+
+/// The total amount of time worked on a song
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TotalTime {
+    /// The number of days
+    pub days: u8,
+
+    /// The number of hours
+    pub hours: u8,
+
+    /// The number of minutes
+    pub minutes: u8,
+}
+
+/// How LSDJ clones chains and phrases, as returned by [`SongMemory::clone_mode()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloneMode {
+    /// Cloning a chain also clones the phrases it contains
+    Deep,
+
+    /// Cloning a chain keeps referring to the same phrases
+    Slim,
+}
+
+/// Error returned when accessing fields of a song whose format version isn't supported
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Format version {0} is not supported")]
+pub struct UnsupportedFormatVersion(pub u8);
+
 /// Errors that might be returned from [`SongMemory::from_bytes()`]
 #[derive(Debug, Error)]
 pub enum FromBytesError {
@@ -111,6 +340,51 @@ pub enum FromReaderError {
     /// Deserialization from the read bytes failed
     #[error("Deserialiazation from the read bytes failed")]
     FromBytes(#[from] FromBytesError),
+
+    /// Decompression didn't result in exactly [`SongMemory::LEN`] bytes
+    ///
+    /// When decompression would produce more bytes than fit in song memory, `actual`
+    /// is reported as one byte over `expected`.
+    #[error("Decompression resulted in {actual} bytes instead of {expected}")]
+    DecompressedSizeMismatch { expected: usize, actual: usize },
+
+    /// The compressed data ended before an end-of-file command was found
+    #[error("The compressed data ended before the end of the song")]
+    UnexpectedEndOfBlocks,
+}
+
+impl FromReaderError {
+    /// Convert an I/O error that occurred during block decompression into a clearer error
+    ///
+    /// Running out of input means the blocks were truncated, while a
+    /// [`Decompressor`](crate::serde::Decompressor) refusing to produce more bytes means the
+    /// blocks decompress to more than fits in song memory.
+    pub(crate) fn from_decompression(error: io::Error) -> Self {
+        let too_long = error
+            .get_ref()
+            .is_some_and(|error| error.is::<serde::DecompressedTooLong>());
+
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => Self::UnexpectedEndOfBlocks,
+            _ if too_long => Self::DecompressedSizeMismatch {
+                expected: SongMemory::LEN,
+                actual: SongMemory::LEN + 1,
+            },
+            _ => Self::Read(error),
+        }
+    }
+
+    /// Check that decompression produced exactly enough bytes to fill song memory
+    pub(crate) fn check_decompressed_size(actual: u64) -> Result<(), Self> {
+        if actual == SongMemory::LEN as u64 {
+            Ok(())
+        } else {
+            Err(Self::DecompressedSizeMismatch {
+                expected: SongMemory::LEN,
+                actual: actual as usize,
+            })
+        }
+    }
 }
 
 #[cfg(test)]
@@ -127,5 +401,86 @@ mod tests {
         };
 
         assert_eq!(song.format_version(), 0x16);
+        assert_eq!(song.format(), FormatVersion::Known(KnownFormatVersion::V22));
+        assert_eq!(song.tempo(), Ok(128));
+        assert_eq!(song.sync(), Ok(0));
+        assert_eq!(song.clone_mode(), Ok(CloneMode::Deep));
+        assert_eq!(song.file_changed(), Ok(false));
+    }
+
+    #[test]
+    fn settings() {
+        let mut song = SongMemory::new();
+
+        song.set_sync(2).unwrap();
+        song.set_clone_mode(CloneMode::Slim).unwrap();
+
+        assert_eq!(song.sync(), Ok(2));
+        assert_eq!(song.clone_mode(), Ok(CloneMode::Slim));
+        assert_eq!(&song.as_slice()[0x3FBD..0x3FC1], [2, 0, 0, 1]);
+    }
+
+    #[test]
+    fn lenient() {
+        let mut bytes = SongMemory::new().as_slice().to_vec();
+        for offset in [0x1E78, 0x3E80, 0x7FF0] {
+            bytes[offset] = 0;
+        }
+
+        assert!(matches!(
+            SongMemory::from_bytes(&bytes),
+            Err(FromBytesError::InitializationCheckIncorrect)
+        ));
+
+        let song = SongMemory::from_bytes_with_validation(&bytes, Validation::Lenient).unwrap();
+        assert!(!song.has_initialization_check());
+        assert_eq!(song.format_version(), 0x16);
+
+        assert!(matches!(
+            SongMemory::from_bytes_with_validation(&bytes[1..], Validation::Lenient),
+            Err(FromBytesError::IncorrectSize)
+        ));
+    }
+
+    #[test]
+    fn unsupported_format_version() {
+        let mut song = SongMemory::new();
+        song.as_mut_slice()[0x7FFF] = 5;
+
+        assert_eq!(song.tempo(), Err(UnsupportedFormatVersion(5)));
+        assert_eq!(song.set_tempo(100), Err(UnsupportedFormatVersion(5)));
+        assert_eq!(song.total_time(), Err(UnsupportedFormatVersion(5)));
+        assert_eq!(song.clone_mode(), Err(UnsupportedFormatVersion(5)));
+        assert_eq!(song.file_changed(), Err(UnsupportedFormatVersion(5)));
+    }
+
+    #[cfg(feature = "hash")]
+    #[test]
+    fn content_hash() {
+        let song = SongMemory::new();
+        let hash = song.content_hash();
+
+        let mut changed = SongMemory::new();
+        changed.as_mut_slice()[0x3FC1] = 1;
+        assert_eq!(changed.file_changed(), Ok(true));
+        assert_eq!(changed.content_hash(), hash);
+
+        changed.set_tempo(200).unwrap();
+        assert_ne!(changed.content_hash(), hash);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let song = SongMemory::new();
+
+        let json = serde_json::to_string(&song).unwrap();
+        assert_eq!(json.len(), SongMemory::LEN * 2 + 2);
+
+        let result: SongMemory = serde_json::from_str(&json).unwrap();
+        assert_eq!(result.as_slice(), song.as_slice());
+
+        // Too short, and not a valid song
+        assert!(serde_json::from_str::<SongMemory>("\"0000\"").is_err());
     }
 }
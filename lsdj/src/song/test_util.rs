@@ -0,0 +1,31 @@
+//! Songs and noise for use in tests
+
+use super::SongMemory;
+
+/// A deterministic stream of pseudo-random numbers, from a linear congruential generator
+///
+/// The same seed always produces the same numbers, so tests stay reproducible.
+pub(crate) fn noise(seed: u32) -> impl Iterator<Item = u32> {
+    let mut state = seed;
+    std::iter::repeat_with(move || {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        state
+    })
+}
+
+/// Pseudo-random bytes, as produced by [`noise()`]
+pub(crate) fn noise_bytes(seed: u32) -> impl Iterator<Item = u8> {
+    noise(seed).map(|state| (state >> 16) as u8)
+}
+
+/// An empty song with its first `len` bytes replaced by noise, so that it compresses badly
+///
+/// With `len` below 0x1E78 the initialization check bytes are left alone, so the song still
+/// decompresses with strict validation.
+pub(crate) fn noisy_song(len: usize, seed: u32) -> SongMemory {
+    let mut song = SongMemory::new();
+    for (byte, noise) in song.as_mut_slice()[..len].iter_mut().zip(noise_bytes(seed)) {
+        *byte = noise;
+    }
+    song
+}
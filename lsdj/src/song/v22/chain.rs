@@ -0,0 +1,22 @@
+//! Chains, the sequences of phrases placed in the song arrangement
+
+use super::STEP_COUNT;
+
+/// A chain of up to 16 phrases, each with a transposition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Chain {
+    /// The steps of the chain, in playback order
+    pub steps: [ChainStep; STEP_COUNT],
+}
+
+/// A single step in a [`Chain`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChainStep {
+    /// The phrase played at this step, if any
+    pub phrase: Option<u8>,
+
+    /// The number of semitones the phrase is transposed by
+    ///
+    /// This is the raw byte, where values from 0x80 upward transpose down.
+    pub transpose: u8,
+}
@@ -0,0 +1,34 @@
+//! Grooves, the number of ticks per phrase step
+
+use super::STEP_COUNT;
+
+/// A groove, which sets the number of ticks every phrase step lasts
+///
+/// The groove repeats its steps until the first step of zero ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Groove {
+    /// The number of ticks per step
+    pub steps: [u8; STEP_COUNT],
+}
+
+impl Groove {
+    /// The steps that are actually played, up until the first step of zero ticks
+    pub fn ticks(&self) -> &[u8] {
+        let len = self
+            .steps
+            .iter()
+            .position(|ticks| *ticks == 0)
+            .unwrap_or(STEP_COUNT);
+
+        &self.steps[..len]
+    }
+}
+
+impl Default for Groove {
+    /// The groove LSDJ starts out with, alternating steps of 6 ticks
+    fn default() -> Self {
+        let mut steps = [0; STEP_COUNT];
+        steps[..2].copy_from_slice(&[6, 6]);
+        Self { steps }
+    }
+}
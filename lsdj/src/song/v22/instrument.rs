@@ -0,0 +1,168 @@
+//! Reading and writing the parameters of an [`Instrument`] in format version 22
+
+use crate::song::instrument::{
+    Instrument, KitInstrument, NoiseInstrument, Output, PulseInstrument, PulseWidth, WaveInstrument,
+};
+use ux::u4;
+
+/// The number of parameter bytes per instrument
+pub(super) const PARAMETERS_LEN: usize = 0x10;
+
+const TYPE: usize = 0;
+const ENVELOPE: usize = 1;
+const PHASE_TRANSPOSE: usize = 2;
+const SYNTH: usize = 2;
+const KIT1: usize = 2;
+const LENGTH: usize = 3;
+const SWEEP: usize = 4;
+const AUTOMATE: usize = 5;
+const TABLE: usize = 6;
+const OUTPUT: usize = 7;
+const KIT2: usize = 9;
+
+/// The bit in the automate byte that enables table automation
+const AUTOMATE_BIT: u8 = 0x08;
+
+const TYPE_PULSE: u8 = 0;
+const TYPE_WAVE: u8 = 1;
+const TYPE_KIT: u8 = 2;
+const TYPE_NOISE: u8 = 3;
+
+/// Parse an instrument from its parameter bytes, or return the unknown type byte
+pub(super) fn read(bytes: &[u8]) -> Result<Instrument, u8> {
+    let output = read_output(bytes[OUTPUT]);
+    let table = read_table(bytes[TABLE]);
+    let automate = bytes[AUTOMATE] & AUTOMATE_BIT != 0;
+
+    match bytes[TYPE] {
+        TYPE_PULSE => Ok(Instrument::Pulse(PulseInstrument {
+            envelope: bytes[ENVELOPE],
+            phase_transpose: bytes[PHASE_TRANSPOSE],
+            length: read_length(bytes[LENGTH]),
+            sweep: bytes[SWEEP],
+            pulse_width: match bytes[OUTPUT] >> 6 {
+                0 => PulseWidth::Eighth,
+                1 => PulseWidth::Quarter,
+                2 => PulseWidth::Half,
+                _ => PulseWidth::ThreeQuarters,
+            },
+            finetune: u4::new((bytes[OUTPUT] >> 2) & 0x0F),
+            output,
+            table,
+            automate,
+        })),
+        TYPE_WAVE => Ok(Instrument::Wave(WaveInstrument {
+            volume: bytes[ENVELOPE],
+            synth: u4::new(bytes[SYNTH] >> 4),
+            output,
+            table,
+            automate,
+        })),
+        TYPE_KIT => Ok(Instrument::Kit(KitInstrument {
+            volume: bytes[ENVELOPE],
+            kit1: bytes[KIT1] & 0x3F,
+            kit2: bytes[KIT2] & 0x3F,
+            output,
+            table,
+            automate,
+        })),
+        TYPE_NOISE => Ok(Instrument::Noise(NoiseInstrument {
+            envelope: bytes[ENVELOPE],
+            length: read_length(bytes[LENGTH]),
+            output,
+            table,
+            automate,
+        })),
+        kind => Err(kind),
+    }
+}
+
+/// Write an instrument into its parameter bytes
+///
+/// Bits of the parameters that aren't parsed are left untouched.
+pub(super) fn write(instrument: &Instrument, bytes: &mut [u8]) {
+    write_output(&mut bytes[OUTPUT], instrument.output());
+    write_table(&mut bytes[TABLE], instrument.table());
+    bytes[AUTOMATE] = match instrument.automate() {
+        true => bytes[AUTOMATE] | AUTOMATE_BIT,
+        false => bytes[AUTOMATE] & !AUTOMATE_BIT,
+    };
+
+    match instrument {
+        Instrument::Pulse(pulse) => {
+            bytes[TYPE] = TYPE_PULSE;
+            bytes[ENVELOPE] = pulse.envelope;
+            bytes[PHASE_TRANSPOSE] = pulse.phase_transpose;
+            write_length(&mut bytes[LENGTH], pulse.length);
+            bytes[SWEEP] = pulse.sweep;
+
+            let pulse_width = match pulse.pulse_width {
+                PulseWidth::Eighth => 0,
+                PulseWidth::Quarter => 1,
+                PulseWidth::Half => 2,
+                PulseWidth::ThreeQuarters => 3,
+            };
+            bytes[OUTPUT] = pulse_width << 6 | u8::from(pulse.finetune) << 2 | bytes[OUTPUT] & 0x03;
+        }
+        Instrument::Wave(wave) => {
+            bytes[TYPE] = TYPE_WAVE;
+            bytes[ENVELOPE] = wave.volume;
+            bytes[SYNTH] = u8::from(wave.synth) << 4 | bytes[SYNTH] & 0x0F;
+        }
+        Instrument::Kit(kit) => {
+            bytes[TYPE] = TYPE_KIT;
+            bytes[ENVELOPE] = kit.volume;
+            bytes[KIT1] = bytes[KIT1] & !0x3F | kit.kit1 & 0x3F;
+            bytes[KIT2] = bytes[KIT2] & !0x3F | kit.kit2 & 0x3F;
+        }
+        Instrument::Noise(noise) => {
+            bytes[TYPE] = TYPE_NOISE;
+            bytes[ENVELOPE] = noise.envelope;
+            write_length(&mut bytes[LENGTH], noise.length);
+        }
+    }
+}
+
+fn read_output(byte: u8) -> Output {
+    match byte & 0x03 {
+        0 => Output::None,
+        1 => Output::Right,
+        2 => Output::Left,
+        _ => Output::Both,
+    }
+}
+
+fn write_output(byte: &mut u8, output: Output) {
+    let bits = match output {
+        Output::None => 0,
+        Output::Right => 1,
+        Output::Left => 2,
+        Output::Both => 3,
+    };
+
+    *byte = *byte & !0x03 | bits;
+}
+
+/// The table is stored in the lower 5 bits, with bit 5 telling whether it's enabled
+fn read_table(byte: u8) -> Option<u8> {
+    (byte & 0x20 != 0).then_some(byte & 0x1F)
+}
+
+fn write_table(byte: &mut u8, table: Option<u8>) {
+    *byte = match table {
+        Some(table) => *byte & !0x3F | 0x20 | table & 0x1F,
+        None => *byte & !0x20,
+    };
+}
+
+/// The length is stored in the lower 6 bits, with bit 6 telling whether it's limited at all
+fn read_length(byte: u8) -> Option<u8> {
+    (byte & 0x40 != 0).then_some(byte & 0x3F)
+}
+
+fn write_length(byte: &mut u8, length: Option<u8>) {
+    *byte = match length {
+        Some(length) => *byte & !0x7F | 0x40 | length & 0x3F,
+        None => *byte & !0x40,
+    };
+}
@@ -0,0 +1,843 @@
+//! Songs stored in format version 22 (LSDJ 9.x)
+
+mod chain;
+mod groove;
+mod instrument;
+mod phrase;
+mod table;
+mod word;
+
+pub use chain::{Chain, ChainStep};
+pub use groove::Groove;
+pub use phrase::{Phrase, PhraseStep};
+pub use table::{Table, TableStep};
+pub use word::{Sound, Word};
+
+use super::{
+    instrument::Instrument,
+    wave::{Wave, WaveValue, WAVE_LEN},
+    FormatVersion, KnownFormatVersion, SongMemory, UnsupportedFormatVersion,
+};
+use crate::name::{FromBytesError, Name};
+use std::{array, ops::Range};
+use thiserror::Error;
+
+pub(crate) const PHRASE_NOTES_RANGE: Range<usize> = 0x0000..0x0FF0;
+pub(crate) const GROOVES_RANGE: Range<usize> = 0x1090..0x1290;
+pub(crate) const CHAIN_ASSIGNMENTS_RANGE: Range<usize> = 0x1290..0x1690;
+pub(crate) const TABLE_ENVELOPES_RANGE: Range<usize> = 0x1690..0x1890;
+pub(crate) const WORDS_RANGE: Range<usize> = 0x1890..0x1DD0;
+pub(crate) const WORD_NAMES_RANGE: Range<usize> = 0x1DD0..0x1E78;
+pub(crate) const INSTRUMENT_NAMES_RANGE: Range<usize> = 0x1E7A..0x1FBA;
+pub(crate) const TABLE_ALLOCATION_RANGE: Range<usize> = 0x2020..0x2040;
+pub(crate) const INSTRUMENT_ALLOCATION_RANGE: Range<usize> = 0x2040..0x2080;
+pub(crate) const CHAIN_PHRASES_RANGE: Range<usize> = 0x2080..0x2880;
+pub(crate) const CHAIN_TRANSPOSES_RANGE: Range<usize> = 0x2880..0x3080;
+pub(crate) const INSTRUMENT_PARAMETERS_RANGE: Range<usize> = 0x3080..0x3480;
+pub(crate) const TABLE_TRANSPOSES_RANGE: Range<usize> = 0x3480..0x3680;
+pub(crate) const TABLE_COMMANDS1_RANGE: Range<usize> = 0x3680..0x3880;
+pub(crate) const TABLE_COMMAND1_VALUES_RANGE: Range<usize> = 0x3880..0x3A80;
+pub(crate) const TABLE_COMMANDS2_RANGE: Range<usize> = 0x3A80..0x3C80;
+pub(crate) const TABLE_COMMAND2_VALUES_RANGE: Range<usize> = 0x3C80..0x3E80;
+pub(crate) const PHRASE_ALLOCATION_RANGE: Range<usize> = 0x3E82..0x3EA2;
+pub(crate) const CHAIN_ALLOCATION_RANGE: Range<usize> = 0x3EA2..0x3EB2;
+pub(crate) const SYNTH_PARAMETERS_RANGE: Range<usize> = 0x3EB2..0x3FB2;
+pub(crate) const PHRASE_COMMANDS_RANGE: Range<usize> = 0x4000..0x4FF0;
+pub(crate) const PHRASE_COMMAND_VALUES_RANGE: Range<usize> = 0x4FF0..0x5FE0;
+pub(crate) const WAVES_RANGE: Range<usize> = 0x6000..0x7000;
+pub(crate) const PHRASE_INSTRUMENTS_RANGE: Range<usize> = 0x7000..0x7FF0;
+
+/// The byte LSDJ uses for empty song rows, chain steps and phrase steps
+const EMPTY: u8 = 0xFF;
+
+/// The number of steps in a [`Chain`], [`Phrase`], [`Table`] or [`Groove`]
+const STEP_COUNT: usize = 0x10;
+
+/// A song in format version 22, parsed from [`SongMemory`]
+///
+/// Not every part of the song is parsed yet. The fields available are those
+/// that have been implemented so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Song {
+    /// The tempo, in beats per minute (see [`SongMemory::tempo()`])
+    pub tempo: u8,
+
+    /// The chains played per song row, for every [`Channel`]
+    ///
+    /// Rows referring to a chain that isn't allocated are considered empty.
+    pub arrangement: [[Option<u8>; Self::CHANNEL_COUNT]; Self::ROW_COUNT],
+
+    /// All chains, where chains that aren't allocated are `None`
+    pub chains: [Option<Chain>; Self::CHAIN_COUNT],
+
+    /// All phrases, where phrases that aren't allocated are `None`
+    pub phrases: [Option<Phrase>; Self::PHRASE_COUNT],
+
+    /// All instruments, where instruments that aren't allocated are `None`
+    pub instruments: [Option<Instrument>; Self::INSTRUMENT_COUNT],
+
+    /// The names of all instruments
+    pub instrument_names: [Name<5>; Self::INSTRUMENT_COUNT],
+
+    /// All tables, where tables that aren't allocated are `None`
+    pub tables: [Option<Table>; Self::TABLE_COUNT],
+
+    /// All grooves
+    pub grooves: [Groove; Self::GROOVE_COUNT],
+
+    /// The words spoken by the speech synth
+    pub words: [Word; Self::WORD_COUNT],
+
+    /// The names of all words
+    ///
+    /// These are kept as raw bytes, because LSDJ names words after the note they're played on,
+    /// which includes characters (like `#`) that aren't allowed in a [`Name`].
+    pub word_names: [[u8; 4]; Self::WORD_COUNT],
+
+    /// The wave frames, as used by wave instruments and the synths
+    pub waves: [Wave; Self::WAVE_COUNT],
+}
+
+impl Song {
+    /// The number of channels in the song arrangement
+    pub const CHANNEL_COUNT: usize = 4;
+
+    /// The number of rows in the song arrangement
+    pub const ROW_COUNT: usize = 0x100;
+
+    /// The number of chains in a song
+    pub const CHAIN_COUNT: usize = 0x80;
+
+    /// The number of phrases in a song
+    pub const PHRASE_COUNT: usize = 0xFF;
+
+    /// The number of instruments in a song
+    pub const INSTRUMENT_COUNT: usize = 0x40;
+
+    /// The number of tables in a song
+    pub const TABLE_COUNT: usize = 0x20;
+
+    /// The number of grooves in a song
+    pub const GROOVE_COUNT: usize = 0x20;
+
+    /// The number of speech synth words in a song
+    pub const WORD_COUNT: usize = 0x2A;
+
+    /// The number of wave frames in a song
+    pub const WAVE_COUNT: usize = 0x100;
+
+    /// Parse a song from (format version 22) [`SongMemory`]
+    pub fn from_memory(memory: &SongMemory) -> Result<Self, FromMemoryError> {
+        match memory.format() {
+            FormatVersion::Known(KnownFormatVersion::V22) => (),
+            version => return Err(UnsupportedFormatVersion(version.byte()).into()),
+        }
+
+        let bytes = memory.as_slice();
+
+        let phrases: [Option<Phrase>; Self::PHRASE_COUNT] = array::from_fn(|index| {
+            is_allocated(&bytes[PHRASE_ALLOCATION_RANGE], index).then(|| {
+                let offset = index * STEP_COUNT;
+                Phrase {
+                    steps: array::from_fn(|step| {
+                        let offset = offset + step;
+                        PhraseStep {
+                            note: match bytes[PHRASE_NOTES_RANGE][offset] {
+                                0 => None,
+                                note => Some(note),
+                            },
+                            instrument: match bytes[PHRASE_INSTRUMENTS_RANGE][offset] {
+                                EMPTY => None,
+                                instrument => Some(instrument),
+                            },
+                            command: bytes[PHRASE_COMMANDS_RANGE][offset],
+                            command_value: bytes[PHRASE_COMMAND_VALUES_RANGE][offset],
+                        }
+                    }),
+                }
+            })
+        });
+
+        let chains: [Option<Chain>; Self::CHAIN_COUNT] = array::from_fn(|index| {
+            is_allocated(&bytes[CHAIN_ALLOCATION_RANGE], index).then(|| {
+                let offset = index * STEP_COUNT;
+                Chain {
+                    steps: array::from_fn(|step| ChainStep {
+                        phrase: reference(bytes[CHAIN_PHRASES_RANGE][offset + step], &phrases),
+                        transpose: bytes[CHAIN_TRANSPOSES_RANGE][offset + step],
+                    }),
+                }
+            })
+        });
+
+        let mut arrangement = [[None; Self::CHANNEL_COUNT]; Self::ROW_COUNT];
+        for (row, bytes) in arrangement
+            .iter_mut()
+            .zip(bytes[CHAIN_ASSIGNMENTS_RANGE].chunks_exact(Self::CHANNEL_COUNT))
+        {
+            for (chain, byte) in row.iter_mut().zip(bytes) {
+                *chain = reference(*byte, &chains);
+            }
+        }
+
+        let mut instruments = [None; Self::INSTRUMENT_COUNT];
+        for (index, (instrument, (allocated, bytes))) in
+            instruments
+                .iter_mut()
+                .zip(bytes[INSTRUMENT_ALLOCATION_RANGE].iter().zip(
+                    bytes[INSTRUMENT_PARAMETERS_RANGE].chunks_exact(instrument::PARAMETERS_LEN),
+                ))
+                .enumerate()
+        {
+            if *allocated != 0 {
+                *instrument = Some(
+                    instrument::read(bytes)
+                        .map_err(|kind| FromMemoryError::InstrumentType { index, kind })?,
+                );
+            }
+        }
+
+        let mut instrument_names: [Name<5>; Self::INSTRUMENT_COUNT] =
+            array::from_fn(|_| Name::default());
+        for (index, (name, bytes)) in instrument_names
+            .iter_mut()
+            .zip(bytes[INSTRUMENT_NAMES_RANGE].chunks_exact(5))
+            .enumerate()
+        {
+            *name = Name::from_bytes(bytes)
+                .map_err(|source| FromMemoryError::InstrumentName { index, source })?;
+        }
+
+        let tables: [Option<Table>; Self::TABLE_COUNT] = array::from_fn(|index| {
+            (bytes[TABLE_ALLOCATION_RANGE][index] != 0).then(|| {
+                let offset = index * STEP_COUNT;
+                Table {
+                    steps: array::from_fn(|step| {
+                        let offset = offset + step;
+                        TableStep {
+                            envelope: bytes[TABLE_ENVELOPES_RANGE][offset],
+                            transpose: bytes[TABLE_TRANSPOSES_RANGE][offset],
+                            command1: bytes[TABLE_COMMANDS1_RANGE][offset],
+                            command1_value: bytes[TABLE_COMMAND1_VALUES_RANGE][offset],
+                            command2: bytes[TABLE_COMMANDS2_RANGE][offset],
+                            command2_value: bytes[TABLE_COMMAND2_VALUES_RANGE][offset],
+                        }
+                    }),
+                }
+            })
+        });
+
+        let grooves = array::from_fn(|index| {
+            let offset = GROOVES_RANGE.start + index * STEP_COUNT;
+            Groove {
+                steps: array::from_fn(|step| bytes[offset + step]),
+            }
+        });
+
+        let words = array::from_fn(|index| {
+            let offset = WORDS_RANGE.start + index * Word::SOUND_COUNT * 2;
+            Word {
+                sounds: array::from_fn(|sound| Sound {
+                    allophone: bytes[offset + sound * 2],
+                    length: bytes[offset + sound * 2 + 1],
+                }),
+            }
+        });
+
+        let word_names = array::from_fn(|index| {
+            let offset = WORD_NAMES_RANGE.start + index * 4;
+            array::from_fn(|byte| bytes[offset + byte])
+        });
+
+        let mut waves = [[WaveValue::default(); WAVE_LEN]; Self::WAVE_COUNT];
+        for (wave, bytes) in waves.iter_mut().zip(bytes[WAVES_RANGE].chunks_exact(16)) {
+            for (values, byte) in wave.chunks_exact_mut(2).zip(bytes) {
+                values.copy_from_slice(&WaveValue::unpack(*byte));
+            }
+        }
+
+        Ok(Self {
+            tempo: memory.tempo()?,
+            arrangement,
+            chains,
+            phrases,
+            instruments,
+            instrument_names,
+            tables,
+            grooves,
+            words,
+            word_names,
+            waves,
+        })
+    }
+
+    /// Write the song back into (format version 22) [`SongMemory`]
+    ///
+    /// Only the parts of the song that are parsed into this struct are written. Everything else
+    /// in the memory is left untouched, which is why this takes existing memory (usually the memory
+    /// the song was parsed from) instead of constructing new memory.
+    pub fn to_memory(&self, memory: &mut SongMemory) -> Result<(), UnsupportedFormatVersion> {
+        match memory.format() {
+            FormatVersion::Known(KnownFormatVersion::V22) => (),
+            version => return Err(UnsupportedFormatVersion(version.byte())),
+        }
+
+        memory.set_tempo(self.tempo)?;
+
+        let bytes = memory.as_mut_slice();
+
+        for (index, phrase) in self.phrases.iter().enumerate() {
+            set_allocated(&mut bytes[PHRASE_ALLOCATION_RANGE], index, phrase.is_some());
+
+            for (offset, step) in phrase
+                .iter()
+                .flat_map(|phrase| phrase.steps.iter())
+                .enumerate()
+                .map(|(step, data)| (index * STEP_COUNT + step, data))
+            {
+                bytes[PHRASE_NOTES_RANGE][offset] = step.note.unwrap_or(0);
+                bytes[PHRASE_INSTRUMENTS_RANGE][offset] = step.instrument.unwrap_or(EMPTY);
+                bytes[PHRASE_COMMANDS_RANGE][offset] = step.command;
+                bytes[PHRASE_COMMAND_VALUES_RANGE][offset] = step.command_value;
+            }
+        }
+
+        for (index, chain) in self.chains.iter().enumerate() {
+            set_allocated(&mut bytes[CHAIN_ALLOCATION_RANGE], index, chain.is_some());
+
+            for (offset, step) in chain
+                .iter()
+                .flat_map(|chain| chain.steps.iter())
+                .enumerate()
+                .map(|(step, data)| (index * STEP_COUNT + step, data))
+            {
+                write_reference(
+                    &mut bytes[CHAIN_PHRASES_RANGE][offset],
+                    step.phrase,
+                    &self.phrases,
+                );
+                bytes[CHAIN_TRANSPOSES_RANGE][offset] = step.transpose;
+            }
+        }
+
+        for (row, bytes) in self
+            .arrangement
+            .iter()
+            .zip(bytes[CHAIN_ASSIGNMENTS_RANGE].chunks_exact_mut(Self::CHANNEL_COUNT))
+        {
+            for (chain, byte) in row.iter().zip(bytes) {
+                write_reference(byte, *chain, &self.chains);
+            }
+        }
+
+        for (index, instrument) in self.instruments.iter().enumerate() {
+            let allocated = &mut bytes[INSTRUMENT_ALLOCATION_RANGE][index];
+            match instrument {
+                Some(_) if *allocated == 0 => *allocated = 1,
+                Some(_) => (),
+                None => *allocated = 0,
+            }
+
+            if let Some(instrument) = instrument {
+                let offset = INSTRUMENT_PARAMETERS_RANGE.start + index * instrument::PARAMETERS_LEN;
+                instrument::write(
+                    instrument,
+                    &mut bytes[offset..offset + instrument::PARAMETERS_LEN],
+                );
+            }
+        }
+
+        for (name, bytes) in self
+            .instrument_names
+            .iter()
+            .zip(bytes[INSTRUMENT_NAMES_RANGE].chunks_exact_mut(5))
+        {
+            bytes.copy_from_slice(name.bytes());
+        }
+
+        for (index, table) in self.tables.iter().enumerate() {
+            let allocated = &mut bytes[TABLE_ALLOCATION_RANGE][index];
+            match table {
+                Some(_) if *allocated == 0 => *allocated = 1,
+                Some(_) => (),
+                None => *allocated = 0,
+            }
+
+            for (offset, step) in table
+                .iter()
+                .flat_map(|table| table.steps.iter())
+                .enumerate()
+                .map(|(step, data)| (index * STEP_COUNT + step, data))
+            {
+                bytes[TABLE_ENVELOPES_RANGE][offset] = step.envelope;
+                bytes[TABLE_TRANSPOSES_RANGE][offset] = step.transpose;
+                bytes[TABLE_COMMANDS1_RANGE][offset] = step.command1;
+                bytes[TABLE_COMMAND1_VALUES_RANGE][offset] = step.command1_value;
+                bytes[TABLE_COMMANDS2_RANGE][offset] = step.command2;
+                bytes[TABLE_COMMAND2_VALUES_RANGE][offset] = step.command2_value;
+            }
+        }
+
+        for (groove, bytes) in self
+            .grooves
+            .iter()
+            .zip(bytes[GROOVES_RANGE].chunks_exact_mut(STEP_COUNT))
+        {
+            bytes.copy_from_slice(&groove.steps);
+        }
+
+        for (word, bytes) in self
+            .words
+            .iter()
+            .zip(bytes[WORDS_RANGE].chunks_exact_mut(Word::SOUND_COUNT * 2))
+        {
+            for (sound, bytes) in word.sounds.iter().zip(bytes.chunks_exact_mut(2)) {
+                bytes.copy_from_slice(&[sound.allophone, sound.length]);
+            }
+        }
+
+        for (name, bytes) in self
+            .word_names
+            .iter()
+            .zip(bytes[WORD_NAMES_RANGE].chunks_exact_mut(4))
+        {
+            bytes.copy_from_slice(name);
+        }
+
+        for (wave, bytes) in self
+            .waves
+            .iter()
+            .zip(bytes[WAVES_RANGE].chunks_exact_mut(16))
+        {
+            for (values, byte) in wave.chunks_exact(2).zip(bytes) {
+                *byte = WaveValue::pack([values[0], values[1]]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The indices of all kits referenced by the kit instruments, sorted and without duplicates
+    pub fn kits(&self) -> Vec<u8> {
+        let mut kits: Vec<u8> = self
+            .instruments
+            .iter()
+            .flat_map(|instrument| match instrument {
+                Some(Instrument::Kit(kit)) => vec![kit.kit1, kit.kit2],
+                _ => vec![],
+            })
+            .collect();
+
+        kits.sort_unstable();
+        kits.dedup();
+        kits
+    }
+
+    /// The chain at an index, if it's allocated
+    pub fn chain(&self, index: u8) -> Option<&Chain> {
+        self.chains.get(index as usize)?.as_ref()
+    }
+
+    /// The phrase at an index, if it's allocated
+    pub fn phrase(&self, index: u8) -> Option<&Phrase> {
+        self.phrases.get(index as usize)?.as_ref()
+    }
+
+    /// Iterate over every note played on a channel, in playback order
+    ///
+    /// This walks the song arrangement from the first row until the first empty row, through
+    /// every chain and phrase on the way. LSDJ loops back when it reaches an empty row, but the
+    /// iterator stops there, so it always ends.
+    pub fn notes(&self, channel: Channel) -> impl Iterator<Item = NoteEvent> + '_ {
+        self.arrangement
+            .iter()
+            .map_while(move |row| row[channel as usize])
+            .enumerate()
+            .flat_map(move |(row, chain)| {
+                let steps = self
+                    .chain(chain)
+                    .into_iter()
+                    .flat_map(|chain| chain.steps.iter());
+
+                steps
+                    .enumerate()
+                    .filter_map(move |(chain_step, step)| {
+                        step.phrase
+                            .map(|phrase| (chain_step, phrase, step.transpose))
+                    })
+                    .flat_map(move |(chain_step, phrase, transpose)| {
+                        let steps = self
+                            .phrase(phrase)
+                            .into_iter()
+                            .flat_map(|phrase| phrase.steps.iter());
+
+                        steps.enumerate().filter_map(move |(phrase_step, step)| {
+                            step.note.map(|note| NoteEvent {
+                                row: row as u8,
+                                chain,
+                                chain_step: chain_step as u8,
+                                phrase,
+                                phrase_step: phrase_step as u8,
+                                note,
+                                transpose,
+                                instrument: step.instrument,
+                            })
+                        })
+                    })
+            })
+    }
+}
+
+/// One of the four Game Boy sound channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// The first pulse channel
+    Pulse1,
+
+    /// The second pulse channel
+    Pulse2,
+
+    /// The wave channel
+    Wave,
+
+    /// The noise channel
+    Noise,
+}
+
+/// A single note played by a song, as returned by [`Song::notes()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NoteEvent {
+    /// The song row the note is played from
+    pub row: u8,
+
+    /// The chain the note is played from
+    pub chain: u8,
+
+    /// The step within the chain
+    pub chain_step: u8,
+
+    /// The phrase the note is part of
+    pub phrase: u8,
+
+    /// The step within the phrase
+    pub phrase_step: u8,
+
+    /// The raw note, before transposition
+    pub note: u8,
+
+    /// The transposition applied by the chain
+    pub transpose: u8,
+
+    /// The instrument the note is played with, if any
+    pub instrument: Option<u8>,
+}
+
+/// Is the slot at `index` marked as allocated in an allocation bitmap?
+fn is_allocated(bitmap: &[u8], index: usize) -> bool {
+    bitmap[index / 8] & (1 << (index % 8)) != 0
+}
+
+/// Mark the slot at `index` as (un)allocated in an allocation bitmap
+fn set_allocated(bitmap: &mut [u8], index: usize, allocated: bool) {
+    if allocated {
+        bitmap[index / 8] |= 1 << (index % 8);
+    } else {
+        bitmap[index / 8] &= !(1 << (index % 8));
+    }
+}
+
+/// Read a reference to a chain or phrase, which is empty when it doesn't point to an allocated one
+fn reference<T>(byte: u8, targets: &[Option<T>]) -> Option<u8> {
+    targets
+        .get(byte as usize)
+        .and_then(Option::as_ref)
+        .map(|_| byte)
+}
+
+/// Write a reference to a chain or phrase
+///
+/// Empty references are only overwritten when the byte currently refers to something, so that
+/// memory left untouched by LSDJ round-trips exactly.
+fn write_reference<T>(byte: &mut u8, value: Option<u8>, targets: &[Option<T>]) {
+    match value {
+        Some(value) => *byte = value,
+        None if reference(*byte, targets).is_some() => *byte = EMPTY,
+        None => (),
+    }
+}
+
+/// Errors that might be returned from [`Song::from_memory()`]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FromMemoryError {
+    /// The song memory isn't stored in format version 22
+    #[error("The song memory has the wrong format version")]
+    FormatVersion(#[from] UnsupportedFormatVersion),
+
+    /// One of the allocated instruments has a type that doesn't exist
+    #[error("Instrument {index} has an unknown type ({kind:#04X})")]
+    InstrumentType { index: usize, kind: u8 },
+
+    /// One of the instrument names contains invalid bytes
+    #[error("The name of instrument {index} is invalid")]
+    InstrumentName {
+        index: usize,
+        source: FromBytesError,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::song::{
+        instrument::{KitInstrument, Output, PulseInstrument, PulseWidth},
+        wave::DEFAULT_WAVE,
+    };
+    use ux::u4;
+
+    #[test]
+    fn empty() {
+        let memory = SongMemory::new();
+        let song = Song::from_memory(&memory).unwrap();
+
+        assert_eq!(song.tempo, 128);
+        assert!(song.arrangement.iter().flatten().all(Option::is_none));
+        assert!(song.chains.iter().all(Option::is_none));
+        assert!(song.phrases.iter().all(Option::is_none));
+        assert!(song.instruments.iter().all(Option::is_none));
+        assert!(song.tables.iter().all(Option::is_none));
+        assert!(song.instrument_names.iter().all(|name| name.is_empty()));
+
+        // The empty song stores no grooves; LSDJ fills in groove 0 when the song is loaded
+        assert!(song.grooves.iter().all(|groove| groove.ticks().is_empty()));
+
+        for wave in &song.waves {
+            for (values, byte) in wave.chunks_exact(2).zip(DEFAULT_WAVE) {
+                assert_eq!(u8::from(values[0].value()), byte >> 4);
+                assert_eq!(u8::from(values[1].value()), byte & 0x0F);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = SongMemory::new();
+        let mut song = Song::from_memory(&source).unwrap();
+
+        let mut memory = SongMemory::new();
+        song.to_memory(&mut memory).unwrap();
+        assert_eq!(memory.as_slice(), source.as_slice());
+
+        song.tempo = 140;
+        song.instrument_names[1] = "BASS".try_into().unwrap();
+        song.waves[2][1] = WaveValue::new(u4::new(0x3));
+        song.to_memory(&mut memory).unwrap();
+
+        assert_eq!(memory.tempo(), Ok(140));
+        assert_eq!(&memory.as_slice()[0x1E7F..0x1E84], b"BASS\0");
+        assert_eq!(memory.as_slice()[0x6020], 0x83);
+        assert_eq!(Song::from_memory(&memory).unwrap(), song);
+    }
+
+    #[test]
+    fn notes() {
+        let mut song = Song::from_memory(&SongMemory::new()).unwrap();
+
+        let mut phrase = Phrase::default();
+        phrase.steps[0].note = Some(0x20);
+        phrase.steps[0].instrument = Some(1);
+        phrase.steps[4].note = Some(0x24);
+        song.phrases[5] = Some(phrase);
+
+        let mut chain = Chain::default();
+        chain.steps[1] = ChainStep {
+            phrase: Some(5),
+            transpose: 2,
+        };
+        song.chains[3] = Some(chain);
+
+        // The third row is never reached, because the second one is empty
+        song.arrangement[0][Channel::Wave as usize] = Some(3);
+        song.arrangement[2][Channel::Wave as usize] = Some(3);
+
+        let mut memory = SongMemory::new();
+        song.to_memory(&mut memory).unwrap();
+        let song = Song::from_memory(&memory).unwrap();
+
+        assert_eq!(song.notes(Channel::Pulse1).count(), 0);
+        assert_eq!(
+            song.notes(Channel::Wave).collect::<Vec<_>>(),
+            [
+                NoteEvent {
+                    row: 0,
+                    chain: 3,
+                    chain_step: 1,
+                    phrase: 5,
+                    phrase_step: 0,
+                    note: 0x20,
+                    transpose: 2,
+                    instrument: Some(1),
+                },
+                NoteEvent {
+                    row: 0,
+                    chain: 3,
+                    chain_step: 1,
+                    phrase: 5,
+                    phrase_step: 4,
+                    note: 0x24,
+                    transpose: 2,
+                    instrument: None,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn unallocated_references() {
+        let mut memory = SongMemory::new();
+        let bytes = memory.as_mut_slice();
+
+        // Point the first row at chain 1, which isn't allocated
+        bytes[CHAIN_ASSIGNMENTS_RANGE.start] = 1;
+        let song = Song::from_memory(&memory).unwrap();
+        assert_eq!(song.arrangement[0][0], None);
+
+        // Writing back leaves the dangling reference alone, until the chain is allocated
+        let mut song = song;
+        song.to_memory(&mut memory).unwrap();
+        assert_eq!(memory.as_slice()[CHAIN_ASSIGNMENTS_RANGE.start], 1);
+
+        song.chains[1] = Some(Chain::default());
+        assert_eq!(song.chain(1), Some(&Chain::default()));
+        assert_eq!(song.chain(0xFE), None);
+        assert_eq!(song.phrase(0xFE), None);
+        song.to_memory(&mut memory).unwrap();
+        assert_eq!(memory.as_slice()[CHAIN_ASSIGNMENTS_RANGE.start], EMPTY);
+        assert_eq!(memory.as_slice()[CHAIN_ALLOCATION_RANGE.start], 0b10);
+    }
+
+    #[test]
+    fn instruments() {
+        let mut memory = SongMemory::new();
+        let bytes = memory.as_mut_slice();
+
+        let pulse = [
+            0x00, 0xA8, 0x00, 0x45, 0xFF, 0x00, 0x23, 0x9B, 0x00, 0x00, 0xD0, 0x00, 0x00, 0x00,
+            0xF3, 0x00,
+        ];
+        let kit = [
+            0x02, 0x60, 0x85, 0x00, 0x00, 0x08, 0x00, 0x02, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+
+        bytes[INSTRUMENT_ALLOCATION_RANGE.start] = 1;
+        bytes[INSTRUMENT_ALLOCATION_RANGE.start + 2] = 1;
+        bytes[INSTRUMENT_PARAMETERS_RANGE][..0x10].copy_from_slice(&pulse);
+        bytes[INSTRUMENT_PARAMETERS_RANGE][0x20..0x30].copy_from_slice(&kit);
+
+        let mut song = Song::from_memory(&memory).unwrap();
+        assert_eq!(song.instruments[1], None);
+        assert_eq!(
+            song.instruments[0],
+            Some(Instrument::Pulse(PulseInstrument {
+                envelope: 0xA8,
+                phase_transpose: 0,
+                length: Some(5),
+                sweep: 0xFF,
+                pulse_width: PulseWidth::Half,
+                finetune: u4::new(6),
+                output: Output::Both,
+                table: Some(3),
+                automate: false,
+            }))
+        );
+        assert_eq!(
+            song.instruments[2],
+            Some(Instrument::Kit(KitInstrument {
+                volume: 0x60,
+                kit1: 5,
+                kit2: 0x11,
+                output: Output::Left,
+                table: None,
+                automate: true,
+            }))
+        );
+
+        assert_eq!(song.kits(), [5, 0x11]);
+
+        let source = memory.as_slice().to_vec();
+        song.to_memory(&mut memory).unwrap();
+        assert_eq!(memory.as_slice(), source);
+
+        if let Some(Instrument::Kit(kit)) = &mut song.instruments[2] {
+            kit.kit1 = 7;
+        }
+        song.instruments[0] = None;
+        song.to_memory(&mut memory).unwrap();
+
+        // The keep attack bit next to the kit is left alone
+        assert_eq!(
+            memory.as_slice()[INSTRUMENT_PARAMETERS_RANGE.start + 0x22],
+            0x87
+        );
+        assert_eq!(memory.as_slice()[INSTRUMENT_ALLOCATION_RANGE.start], 0);
+
+        memory.as_mut_slice()[INSTRUMENT_PARAMETERS_RANGE.start + 0x20] = 7;
+        assert_eq!(
+            Song::from_memory(&memory),
+            Err(FromMemoryError::InstrumentType { index: 2, kind: 7 })
+        );
+    }
+
+    #[test]
+    fn grooves_tables_and_words() {
+        let mut song = Song::from_memory(&SongMemory::new()).unwrap();
+
+        assert_eq!(Groove::default().ticks(), [6, 6]);
+        song.grooves[0] = Groove::default();
+        song.grooves[1].steps = [5; STEP_COUNT];
+        assert_eq!(song.grooves[1].ticks().len(), STEP_COUNT);
+
+        let mut table = Table::default();
+        table.steps[3] = TableStep {
+            envelope: 0xA8,
+            transpose: 0x0C,
+            command1: 1,
+            command1_value: 2,
+            command2: 3,
+            command2_value: 4,
+        };
+        song.tables[4] = Some(table);
+
+        song.words[2].sounds[0] = Sound {
+            allophone: 0x13,
+            length: 2,
+        };
+        song.word_names[2] = *b"C#2 ";
+
+        let mut memory = SongMemory::new();
+        song.to_memory(&mut memory).unwrap();
+
+        let bytes = memory.as_slice();
+        assert_eq!(
+            &bytes[GROOVES_RANGE.start..GROOVES_RANGE.start + 3],
+            [6, 6, 0]
+        );
+        assert_eq!(bytes[TABLE_ALLOCATION_RANGE.start + 4], 1);
+        assert_eq!(bytes[TABLE_COMMAND2_VALUES_RANGE.start + 0x43], 4);
+        assert_eq!(&bytes[WORDS_RANGE.start + 0x40..][..2], [0x13, 2]);
+        assert_eq!(&bytes[WORD_NAMES_RANGE.start + 8..][..4], b"C#2 ");
+
+        assert_eq!(Song::from_memory(&memory).unwrap(), song);
+    }
+
+    #[test]
+    fn wrong_format_version() {
+        let mut memory = SongMemory::new();
+        memory.as_mut_slice()[0x7FFF] = 21;
+
+        assert_eq!(
+            Song::from_memory(&memory),
+            Err(FromMemoryError::FormatVersion(UnsupportedFormatVersion(21)))
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Phrases, the sequences of notes played by a chain
+
+use super::STEP_COUNT;
+
+/// A phrase of 16 steps, each possibly playing a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Phrase {
+    /// The steps of the phrase, in playback order
+    pub steps: [PhraseStep; STEP_COUNT],
+}
+
+/// A single step in a [`Phrase`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PhraseStep {
+    /// The note played at this step, if any
+    ///
+    /// For the noise channel this is the raw noise shape instead of a pitch.
+    pub note: Option<u8>,
+
+    /// The instrument the note is played with, if any
+    pub instrument: Option<u8>,
+
+    /// The command at this step, where 0 means there is no command
+    pub command: u8,
+
+    /// The value passed to the command
+    pub command_value: u8,
+}
@@ -0,0 +1,32 @@
+//! Tables, the envelopes, transpositions and commands run alongside notes
+
+use super::STEP_COUNT;
+
+/// A table of 16 steps, run alongside a note or instrument
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Table {
+    /// The steps of the table, in playback order
+    pub steps: [TableStep; STEP_COUNT],
+}
+
+/// A single step in a [`Table`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TableStep {
+    /// The raw volume envelope
+    pub envelope: u8,
+
+    /// The number of semitones the note is transposed by
+    pub transpose: u8,
+
+    /// The first command, where 0 means there is no command
+    pub command1: u8,
+
+    /// The value passed to the first command
+    pub command1_value: u8,
+
+    /// The second command, where 0 means there is no command
+    pub command2: u8,
+
+    /// The value passed to the second command
+    pub command2_value: u8,
+}
@@ -0,0 +1,23 @@
+//! Words for the speech synth
+
+/// A word spoken by the speech synth, as a sequence of allophones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Word {
+    /// The sounds making up the word, in order
+    pub sounds: [Sound; Word::SOUND_COUNT],
+}
+
+impl Word {
+    /// The number of sounds in a word
+    pub const SOUND_COUNT: usize = 0x10;
+}
+
+/// A single allophone in a [`Word`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Sound {
+    /// The allophone that is spoken
+    pub allophone: u8,
+
+    /// How long the allophone lasts
+    pub length: u8,
+}
@@ -1,4 +1,57 @@
+//! Wave frames, as played by the wave channel
+
+use ux::u4;
+
 /// The default string of bytes for a new wave table
 pub const DEFAULT_WAVE: [u8; 16] = [
     0x8E, 0xCD, 0xCC, 0xBB, 0xAA, 0xA9, 0x99, 0x88, 0x87, 0x76, 0x66, 0x55, 0x54, 0x43, 0x32, 0x31,
 ];
+
+/// The number of samples in a single [`Wave`] frame
+pub const WAVE_LEN: usize = 32;
+
+/// A wave frame, consisting of 32 4-bit samples
+pub type Wave = [WaveValue; WAVE_LEN];
+
+/// A single 4-bit sample in a [`Wave`] frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct WaveValue(u4);
+
+impl WaveValue {
+    /// Construct a new sample from its 4-bit value
+    pub fn new(value: u4) -> Self {
+        Self(value)
+    }
+
+    /// The 4-bit value of the sample
+    pub fn value(self) -> u4 {
+        self.0
+    }
+
+    /// Unpack the two samples stored in a byte, high nibble first
+    pub fn unpack(byte: u8) -> [Self; 2] {
+        [Self(u4::new(byte >> 4)), Self(u4::new(byte & 0x0F))]
+    }
+
+    /// Pack two samples into a byte, the first in the high nibble (see [`WaveValue::unpack()`])
+    pub fn pack(values: [Self; 2]) -> u8 {
+        u8::from(values[0].0) << 4 | u8::from(values[1].0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack() {
+        for byte in DEFAULT_WAVE {
+            assert_eq!(WaveValue::pack(WaveValue::unpack(byte)), byte);
+        }
+
+        assert_eq!(
+            WaveValue::unpack(0x8E),
+            [WaveValue::new(u4::new(0x8)), WaveValue::new(u4::new(0xE))]
+        );
+    }
+}
@@ -0,0 +1,551 @@
+//! Building an SRAM out of song files on disk
+
+use super::{FromReaderError, InsertError, SRam};
+use crate::{
+    fs::{File, FileToLsdSngError, Index},
+    lsdprj::LsdPrj,
+    lsdsng::{self, LsdSng, ReadOptions},
+    name::Name,
+};
+use std::{
+    fs,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    vec,
+};
+use thiserror::Error;
+
+/// Where a song read by [`read_files()`] or [`read_reader()`] came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    /// The path of the file, or the label a reader was given
+    pub path: PathBuf,
+
+    /// The file slot the song was stored in, if the file is a `.sav`
+    pub slot: Option<u8>,
+}
+
+/// The kinds of file songs can be read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A single song (`.lsdsng`)
+    LsdSng,
+
+    /// A single song as exported by LSDPatcher (`.lsdprj`)
+    LsdPrj,
+
+    /// A full SRAM, with all the songs in its filesystem (`.sav`)
+    Sav,
+}
+
+impl Format {
+    /// Recognize the format from the extension of a path
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("lsdsng") => Some(Self::LsdSng),
+            Some("lsdprj") => Some(Self::LsdPrj),
+            Some("sav") => Some(Self::Sav),
+            _ => None,
+        }
+    }
+}
+
+/// Reported by [`build_from_files()`] and [`SRam::insert_songs()`] for every song they come across
+#[derive(Debug)]
+pub enum Progress {
+    /// The song was inserted into a file slot
+    Inserted {
+        source: Source,
+        name: Name<8>,
+        index: Index,
+    },
+
+    /// The song is already in the SRAM (see [`BuildOptions::dedupe`]), and was skipped
+    Skipped {
+        source: Source,
+        name: Name<8>,
+
+        /// The slot of the song it duplicates
+        index: Index,
+
+        /// The name of the song it duplicates, if that can be read
+        existing: Option<Name<8>>,
+    },
+
+    /// The song couldn't be read or inserted, and was skipped
+    Failed { source: Source, error: BuildError },
+}
+
+/// Control over where [`SRam::insert_songs()`] puts the songs
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// The slot to place each song in, in order. Songs without a slot go into the first free one
+    pub slots: Vec<Index>,
+
+    /// Replace songs in slots that are already occupied, instead of failing
+    pub force: bool,
+
+    /// Skip songs that are already in the SRAM
+    pub dedupe: Option<Dedupe>,
+}
+
+/// How [`SRam::insert_songs()`] recognizes songs that are already in the SRAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dedupe {
+    /// Songs with identical contents, regardless of name or version
+    #[cfg(feature = "hash")]
+    Content,
+
+    /// Songs with the same name, regardless of contents
+    Name,
+}
+
+impl Dedupe {
+    /// Find the slot of the song in `sram` that `lsdsng` duplicates, if there is one
+    ///
+    /// Songs in the SRAM that can't be read simply don't count as duplicates.
+    fn find(self, sram: &SRam, lsdsng: &LsdSng) -> Result<Option<Index>, InsertError> {
+        match self {
+            #[cfg(feature = "hash")]
+            Self::Content => {
+                let hash = lsdsng.content_hash()?;
+                Ok(sram
+                    .filesystem
+                    .occupied_files()
+                    .find(|(_, file)| file.content_hash().is_ok_and(|other| other == hash))
+                    .map(|(index, _)| index))
+            }
+            Self::Name => {
+                let name = lsdsng.name()?;
+                Ok(sram
+                    .filesystem
+                    .occupied_files()
+                    .find(|(_, file)| file.name().is_ok_and(|other| other == name))
+                    .map(|(index, _)| index))
+            }
+        }
+    }
+}
+
+/// Read the songs from `.lsdsng`, `.lsdprj` and `.sav` files
+///
+/// Every song found is returned with where it came from, so a `.sav` can produce many songs.
+/// Files that can't be read produce an error instead, without stopping the iteration.
+pub fn read_files<I>(paths: I) -> impl Iterator<Item = (Source, Result<LsdSng, ReadFileError>)>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    paths.into_iter().flat_map(|path| read_file(path.as_ref()))
+}
+
+/// Read the songs from a single file (see [`read_files()`])
+fn read_file(path: &Path) -> Vec<(Source, Result<LsdSng, ReadFileError>)> {
+    let error = |error| {
+        let source = Source {
+            path: path.to_owned(),
+            slot: None,
+        };
+        vec![(source, Err(error))]
+    };
+
+    let Some(format) = Format::from_path(path) else {
+        return error(ReadFileError::UnsupportedExtension);
+    };
+
+    match fs::File::open(path) {
+        Ok(file) => read_reader(path, format, BufReader::new(file)),
+        Err(open) => error(ReadFileError::Open(open)),
+    }
+}
+
+/// Read the songs from an arbitrary I/O reader, in the given format
+///
+/// This works like [`read_files()`], for songs that don't come from disk. `path` is only used as
+/// the [`Source`] of the songs, so it can be any label for where the reader came from.
+pub fn read_reader<P, R>(
+    path: P,
+    format: Format,
+    reader: R,
+) -> Vec<(Source, Result<LsdSng, ReadFileError>)>
+where
+    P: AsRef<Path>,
+    R: Read,
+{
+    let source = |slot| Source {
+        path: path.as_ref().to_owned(),
+        slot,
+    };
+
+    match format {
+        Format::LsdSng => {
+            let options = ReadOptions {
+                allow_partial_block: true,
+            };
+            let result = LsdSng::from_reader_with_options(reader, options);
+            vec![(source(None), result.map_err(ReadFileError::Song))]
+        }
+        Format::LsdPrj => {
+            let result = LsdPrj::from_reader(reader).map(LsdSng::from);
+            vec![(source(None), result.map_err(ReadFileError::Song))]
+        }
+        // Boxed, to keep the large SRAM off the stack while its songs are collected
+        Format::Sav => match SRam::from_reader(reader).map(Box::new) {
+            Ok(sram) => sram
+                .filesystem
+                .occupied_files()
+                .map(|(index, file)| {
+                    let result = file.lsdsng().map_err(ReadFileError::File);
+                    (source(Some(index.into())), result)
+                })
+                .collect(),
+            Err(error) => vec![(source(None), Err(ReadFileError::SRam(error)))],
+        },
+    }
+}
+
+/// Build a new [`SRam`] out of song files on disk
+///
+/// See [`SRam::insert_files()`], which this calls on an empty SRAM.
+pub fn build_from_files<I, F>(paths: I, on_progress: F) -> SRam
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+    F: FnMut(Progress),
+{
+    let mut sram = SRam::new();
+    sram.insert_files(paths, on_progress);
+    sram
+}
+
+impl SRam {
+    /// Insert the songs from `.lsdsng`, `.lsdprj` and `.sav` files into the first free slots
+    ///
+    /// See [`SRam::insert_songs()`], which this calls with the songs from [`read_files()`] and
+    /// the default [`BuildOptions`].
+    pub fn insert_files<I, F>(&mut self, paths: I, on_progress: F)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+        F: FnMut(Progress),
+    {
+        self.insert_songs(read_files(paths), &BuildOptions::default(), on_progress);
+    }
+
+    /// Insert songs read by [`read_files()`] or [`read_reader()`]
+    ///
+    /// Instead of stopping at the first song that couldn't be read or doesn't fit anymore, every
+    /// song is reported through `on_progress` and the rest are still tried. `options` decides
+    /// which slots the songs go into, and which are skipped as duplicates.
+    pub fn insert_songs<I, F>(&mut self, songs: I, options: &BuildOptions, mut on_progress: F)
+    where
+        I: IntoIterator<Item = (Source, Result<LsdSng, ReadFileError>)>,
+        F: FnMut(Progress),
+    {
+        let mut slots = options.slots.clone().into_iter();
+
+        for (source, result) in songs {
+            let progress = match result {
+                Ok(lsdsng) => self.insert_song(source, &lsdsng, &mut slots, options),
+                Err(error) => Progress::Failed {
+                    source,
+                    error: error.into(),
+                },
+            };
+
+            on_progress(progress);
+        }
+    }
+
+    /// Insert a single song for [`SRam::insert_songs()`]
+    fn insert_song(
+        &mut self,
+        source: Source,
+        lsdsng: &LsdSng,
+        slots: &mut vec::IntoIter<Index>,
+        options: &BuildOptions,
+    ) -> Progress {
+        let name = match lsdsng.name() {
+            Ok(name) => name,
+            Err(error) => {
+                let error = InsertError::from(error).into();
+                return Progress::Failed { source, error };
+            }
+        };
+
+        if let Some(dedupe) = options.dedupe {
+            match dedupe.find(self, lsdsng) {
+                Ok(Some(index)) => {
+                    let existing = self
+                        .filesystem
+                        .file(index)
+                        .and_then(|file| file.name().ok());
+
+                    return Progress::Skipped {
+                        source,
+                        name,
+                        index,
+                        existing,
+                    };
+                }
+                Ok(None) => (),
+                Err(error) => {
+                    let error = error.into();
+                    return Progress::Failed { source, error };
+                }
+            }
+        }
+
+        let inserted = match slots.next() {
+            Some(index) if !options.force && self.filesystem.is_file_in_use(index) => {
+                Err(BuildError::SlotOccupied(index))
+            }
+            Some(index) => self
+                .insert_lsdsng_at(index, lsdsng)
+                .map(|_| index)
+                .map_err(BuildError::from),
+            None => self.insert_lsdsng(lsdsng).map_err(BuildError::from),
+        };
+
+        match inserted {
+            Ok(index) => Progress::Inserted {
+                source,
+                name,
+                index,
+            },
+            Err(error) => Progress::Failed { source, error },
+        }
+    }
+}
+
+/// Errors that might be returned from [`read_files()`] and [`read_reader()`]
+#[derive(Debug, Error)]
+pub enum ReadFileError {
+    /// Opening the file failed
+    #[error("Opening the file failed")]
+    Open(#[source] io::Error),
+
+    /// Reading a `.lsdsng` or `.lsdprj` failed
+    #[error("Reading the song failed")]
+    Song(#[source] lsdsng::FromReaderError),
+
+    /// Reading a `.sav` failed
+    #[error("Reading the SRAM failed")]
+    SRam(#[source] FromReaderError),
+
+    /// Reading a song from the filesystem of a `.sav` failed
+    #[error("Reading the song from the SRAM failed")]
+    File(#[source] FileToLsdSngError),
+
+    /// The file isn't a `.lsdsng`, `.lsdprj` or `.sav`
+    #[error("The file isn't a .lsdsng, .lsdprj or .sav")]
+    UnsupportedExtension,
+}
+
+/// Errors reported by [`build_from_files()`] and [`SRam::insert_songs()`] for a single song
+#[derive(Debug, Error)]
+pub enum BuildError {
+    /// Reading the song failed
+    #[error("Reading the song failed")]
+    Read(#[from] ReadFileError),
+
+    /// The slot requested for the song is occupied, and [`BuildOptions::force`] isn't set
+    #[error("Slot {0} is already occupied")]
+    SlotOccupied(Index),
+
+    /// Inserting the song failed
+    #[error("Inserting the song failed")]
+    Insert(#[from] InsertError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fs::Filesystem, song::SongMemory};
+    use std::io::Cursor;
+
+    fn fixture(filename: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test")
+            .join(filename)
+    }
+
+    fn song(name: &str, song: &SongMemory) -> (Source, Result<LsdSng, ReadFileError>) {
+        let source = Source {
+            path: PathBuf::from(name),
+            slot: None,
+        };
+        let lsdsng = LsdSng::from_song(name.try_into().unwrap(), 0, song).unwrap();
+        (source, Ok(lsdsng))
+    }
+
+    fn insert_songs<I>(sram: &mut SRam, songs: I, options: &BuildOptions) -> Vec<Progress>
+    where
+        I: IntoIterator<Item = (Source, Result<LsdSng, ReadFileError>)>,
+    {
+        let mut progress = Vec::new();
+        sram.insert_songs(songs, options, |report| progress.push(report));
+        progress
+    }
+
+    #[test]
+    fn read_files() {
+        let paths = [
+            fixture("92L_empty.lsdsng"),
+            fixture("92L_empty.sav"),
+            fixture("92L_empty.lsdprj"),
+            fixture("missing.lsdsng"),
+            fixture("notes.txt"),
+        ];
+
+        let read: Vec<_> = super::read_files(&paths).collect();
+        assert_eq!(read.len(), 5);
+
+        let sources: Vec<_> = read.iter().map(|(source, _)| source.slot).collect();
+        assert_eq!(sources, [None, Some(0), None, None, None]);
+
+        for (_, result) in &read[..3] {
+            assert_eq!(result.as_ref().unwrap().name().unwrap().as_str(), "EMPTY");
+        }
+        assert!(matches!(read[3].1, Err(ReadFileError::Open(_))));
+        assert!(matches!(
+            read[4].1,
+            Err(ReadFileError::UnsupportedExtension)
+        ));
+    }
+
+    #[test]
+    fn read_reader() {
+        let bytes = include_bytes!("../../test/92L_empty.lsdsng");
+
+        let read = super::read_reader("memory", Format::LsdSng, Cursor::new(bytes));
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].0.path, Path::new("memory"));
+        assert_eq!(
+            read[0].1.as_ref().unwrap().name().unwrap().as_str(),
+            "EMPTY"
+        );
+
+        let read = super::read_reader("memory", Format::Sav, Cursor::new(bytes));
+        assert!(matches!(read[0].1, Err(ReadFileError::SRam(_))));
+    }
+
+    #[test]
+    fn build_from_files() {
+        let mut paths = vec![fixture("missing.lsdsng")];
+        paths.extend((0..=Filesystem::FILES_CAPACITY).map(|_| fixture("92L_empty.lsdsng")));
+
+        let mut progress = Vec::new();
+        let sram = super::build_from_files(&paths, |report| progress.push(report));
+
+        assert_eq!(progress.len(), paths.len());
+        assert!(matches!(
+            &progress[0],
+            Progress::Failed {
+                error: BuildError::Read(_),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &progress[1],
+            Progress::Inserted { index, .. } if *index == Index::new(0)
+        ));
+        assert!(matches!(
+            progress.last().unwrap(),
+            Progress::Failed {
+                error: BuildError::Insert(InsertError::FilesystemFull),
+                ..
+            }
+        ));
+
+        assert_eq!(
+            sram.filesystem.occupied_files().len(),
+            Filesystem::FILES_CAPACITY
+        );
+    }
+
+    #[test]
+    fn explicit_slots() {
+        let empty = SongMemory::new();
+        let mut sram = SRam::new();
+
+        let options = BuildOptions {
+            slots: vec![Index::new(5), Index::new(5)],
+            ..Default::default()
+        };
+        let songs = [song("A", &empty), song("B", &empty), song("C", &empty)];
+        let progress = insert_songs(&mut sram, songs, &options);
+
+        assert!(matches!(
+            &progress[0],
+            Progress::Inserted { index, .. } if *index == Index::new(5)
+        ));
+        assert!(matches!(
+            &progress[1],
+            Progress::Failed { error: BuildError::SlotOccupied(index), .. } if *index == Index::new(5)
+        ));
+        assert!(matches!(
+            &progress[2],
+            Progress::Inserted { index, .. } if *index == Index::new(0)
+        ));
+
+        // Forcing replaces the song in the occupied slot
+        let options = BuildOptions {
+            slots: vec![Index::new(5)],
+            force: true,
+            ..Default::default()
+        };
+        let progress = insert_songs(&mut sram, [song("D", &empty)], &options);
+
+        assert!(matches!(
+            &progress[0],
+            Progress::Inserted { index, .. } if *index == Index::new(5)
+        ));
+        let file = sram.filesystem.file(Index::new(5)).unwrap();
+        assert_eq!(file.name().unwrap().as_str(), "D");
+    }
+
+    #[test]
+    fn dedupe() {
+        let mut sram = SRam::new();
+        let mut song_memory = SongMemory::new();
+        insert_songs(
+            &mut sram,
+            [song("EXISTING", &song_memory)],
+            &Default::default(),
+        );
+
+        let name = BuildOptions {
+            dedupe: Some(Dedupe::Name),
+            ..Default::default()
+        };
+
+        #[cfg(feature = "hash")]
+        {
+            // Same contents under a different name, with the file changed flag set
+            song_memory.as_mut_slice()[0x3FC1] = 1;
+
+            let content = BuildOptions {
+                dedupe: Some(Dedupe::Content),
+                ..Default::default()
+            };
+            let progress = insert_songs(&mut sram, [song("OTHER", &song_memory)], &content);
+            assert!(matches!(
+                &progress[0],
+                Progress::Skipped { index, existing: Some(existing), .. }
+                    if *index == Index::new(0) && existing.as_str() == "EXISTING"
+            ));
+
+            let progress = insert_songs(&mut sram, [song("OTHER", &song_memory)], &name);
+            assert!(matches!(&progress[0], Progress::Inserted { .. }));
+        }
+
+        // Same name, different contents
+        song_memory.set_tempo(200).unwrap();
+        let progress = insert_songs(&mut sram, [song("EXISTING", &song_memory)], &name);
+        assert!(matches!(
+            &progress[0],
+            Progress::Skipped { index, .. } if *index == Index::new(0)
+        ));
+    }
+}
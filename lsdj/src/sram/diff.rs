@@ -0,0 +1,290 @@
+//! Comparing two versions of an SRAM
+
+use super::SRam;
+use crate::{fs::File, name::Name, song::SongMemory};
+
+/// A change to a single file slot between two [`SRam`]'s
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum SlotChange {
+    /// The slot was empty, and now contains a song
+    Added { name: Name<8> },
+
+    /// The slot contained a song, and is now empty
+    Removed { name: Name<8> },
+
+    /// The song has a new name, but its contents are the same
+    Renamed { old: Name<8>, new: Name<8> },
+
+    /// The song has a new name and different contents, so it's probably a different song
+    Replaced { old: Name<8>, new: Name<8> },
+
+    /// The song has the same name, but different contents
+    ContentChanged { name: Name<8> },
+
+    /// The version of the song changed
+    VersionBumped { name: Name<8>, old: u8, new: u8 },
+
+    /// The song in either [`SRam`] couldn't be read or decompressed, so it couldn't be compared
+    ///
+    /// A song that was added or removed is only unreadable if its name can't be read.
+    Unreadable,
+}
+
+/// A [`SlotChange`] at a specific file slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub struct SlotDiff {
+    /// The index of the file slot
+    pub slot: u8,
+
+    /// What changed
+    pub change: SlotChange,
+}
+
+/// The differences between two [`SRam`]'s, as returned by [`diff()`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub struct SRamDiff {
+    /// The changes per file slot, in slot order
+    ///
+    /// A slot can show up more than once, for example when a song was both renamed and
+    /// had its version bumped.
+    pub slots: Vec<SlotDiff>,
+
+    /// Does the song in working memory differ?
+    pub working_memory_changed: bool,
+}
+
+impl SRamDiff {
+    /// Are the SRAM's identical?
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty() && !self.working_memory_changed
+    }
+}
+
+/// Compare two [`SRam`]'s and report what changed per file slot
+///
+/// Songs are compared by their decompressed contents, so a song that was compressed into
+/// different blocks is still considered the same. Slots that fail to read or decompress in
+/// either SRAM are reported as [`SlotChange::Unreadable`] instead of failing the comparison,
+/// unless the slot is empty on the other side: then only the name has to be read.
+pub fn diff(old: &SRam, new: &SRam) -> SRamDiff {
+    let mut slots = Vec::new();
+
+    for (slot, (old, new)) in old
+        .filesystem
+        .files()
+        .zip(new.filesystem.files())
+        .enumerate()
+    {
+        let slot = slot as u8;
+        let mut push = |change| slots.push(SlotDiff { slot, change });
+
+        // A song that was added or removed only needs a readable name, not readable contents
+        let (old, new) = match (old, new) {
+            (None, None) => continue,
+            (Some(old), None) => {
+                push(match old.name() {
+                    Ok(name) => SlotChange::Removed { name },
+                    Err(_) => SlotChange::Unreadable,
+                });
+                continue;
+            }
+            (None, Some(new)) => {
+                push(match new.name() {
+                    Ok(name) => SlotChange::Added { name },
+                    Err(_) => SlotChange::Unreadable,
+                });
+                continue;
+            }
+            (Some(old), Some(new)) => match (read(old), read(new)) {
+                (Some(old), Some(new)) => (old, new),
+                _ => {
+                    push(SlotChange::Unreadable);
+                    continue;
+                }
+            },
+        };
+
+        let (old_name, old_version, old_song) = old;
+        let (new_name, new_version, new_song) = new;
+        let same_song = old_song.as_slice() == new_song.as_slice();
+
+        match (old_name == new_name, same_song) {
+            (true, true) => (),
+            (true, false) => push(SlotChange::ContentChanged {
+                name: new_name.clone(),
+            }),
+            (false, true) => push(SlotChange::Renamed {
+                old: old_name,
+                new: new_name.clone(),
+            }),
+            (false, false) => push(SlotChange::Replaced {
+                old: old_name,
+                new: new_name.clone(),
+            }),
+        }
+
+        if old_version != new_version {
+            push(SlotChange::VersionBumped {
+                name: new_name,
+                old: old_version,
+                new: new_version,
+            });
+        }
+    }
+
+    SRamDiff {
+        slots,
+        working_memory_changed: old.working_memory_song.as_slice()
+            != new.working_memory_song.as_slice(),
+    }
+}
+
+/// Read the name, version and decompressed song of a file, if possible
+fn read(file: impl File) -> Option<(Name<8>, u8, SongMemory)> {
+    Some((file.name().ok()?, file.version(), file.decompress().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{Filesystem, Index};
+    use std::str::FromStr;
+
+    fn insert(sram: &mut SRam, slot: u8, name: &str, version: u8, byte: u8) {
+        let mut song = SongMemory::new();
+        song.as_mut_slice()[0] = byte;
+
+        sram.filesystem
+            .insert_file(
+                Index::new(slot),
+                &Name::from_str(name).unwrap(),
+                version,
+                &song,
+            )
+            .unwrap();
+    }
+
+    fn name(name: &str) -> Name<8> {
+        Name::from_str(name).unwrap()
+    }
+
+    #[test]
+    fn identical() {
+        let mut sram = SRam::new();
+        insert(&mut sram, 0, "SONG", 0, 0);
+
+        assert!(diff(&sram, &sram).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let mut old = SRam::new();
+        insert(&mut old, 0, "REMOVED", 0, 0);
+        insert(&mut old, 1, "OLDNAME", 0, 1);
+        insert(&mut old, 2, "CHANGED", 0, 2);
+        insert(&mut old, 3, "BUMPED", 0, 3);
+        insert(&mut old, 4, "OLD", 0, 4);
+
+        let mut new = SRam::new();
+        insert(&mut new, 1, "NEWNAME", 0, 1);
+        insert(&mut new, 2, "CHANGED", 0, 0x22);
+        insert(&mut new, 3, "BUMPED", 1, 3);
+        insert(&mut new, 4, "NEW", 0, 0x44);
+        insert(&mut new, 5, "ADDED", 0, 5);
+        new.working_memory_song.as_mut_slice()[0] = 0xFF;
+
+        let diff = diff(&old, &new);
+        assert!(diff.working_memory_changed);
+        assert_eq!(
+            diff.slots,
+            [
+                SlotDiff {
+                    slot: 0,
+                    change: SlotChange::Removed {
+                        name: name("REMOVED")
+                    }
+                },
+                SlotDiff {
+                    slot: 1,
+                    change: SlotChange::Renamed {
+                        old: name("OLDNAME"),
+                        new: name("NEWNAME")
+                    }
+                },
+                SlotDiff {
+                    slot: 2,
+                    change: SlotChange::ContentChanged {
+                        name: name("CHANGED")
+                    }
+                },
+                SlotDiff {
+                    slot: 3,
+                    change: SlotChange::VersionBumped {
+                        name: name("BUMPED"),
+                        old: 0,
+                        new: 1
+                    }
+                },
+                SlotDiff {
+                    slot: 4,
+                    change: SlotChange::Replaced {
+                        old: name("OLD"),
+                        new: name("NEW")
+                    }
+                },
+                SlotDiff {
+                    slot: 5,
+                    change: SlotChange::Added {
+                        name: name("ADDED")
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unreadable() {
+        let mut old = SRam::new();
+        insert(&mut old, 0, "SONG", 0, 0);
+
+        // End the first block of the file with an early end-of-file command
+        let mut bytes = old.to_vec();
+        let block = SongMemory::LEN + Filesystem::BLOCK_LEN;
+        bytes[block..block + 2].copy_from_slice(&[0xE0, 0xFF]);
+        let new = SRam::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            diff(&old, &new).slots,
+            [SlotDiff {
+                slot: 0,
+                change: SlotChange::Unreadable
+            }]
+        );
+    }
+
+    #[test]
+    fn unreadable_removed() {
+        let mut old = SRam::new();
+        insert(&mut old, 0, "SONG", 0, 0);
+
+        let mut bytes = old.to_vec();
+        let block = SongMemory::LEN + Filesystem::BLOCK_LEN;
+        bytes[block..block + 2].copy_from_slice(&[0xE0, 0xFF]);
+        let old = SRam::from_bytes(&bytes).unwrap();
+
+        let removed = [SlotDiff {
+            slot: 0,
+            change: SlotChange::Removed { name: name("SONG") },
+        }];
+        assert_eq!(diff(&old, &SRam::new()).slots, removed);
+
+        let added = [SlotDiff {
+            slot: 0,
+            change: SlotChange::Added { name: name("SONG") },
+        }];
+        assert_eq!(diff(&SRam::new(), &old).slots, added);
+    }
+}
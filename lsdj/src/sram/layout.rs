@@ -0,0 +1,188 @@
+//! The memory layout of SRAM
+//!
+//! All offsets and ranges in this module are absolute, counting from the start of the
+//! [`SRam`](super::SRam) (or `.sav` file), and can be used to interpret its raw bytes.
+//!
+//! ```
+//! # use lsdj::{fs::Index, sram::layout::{self, LayoutRegion}};
+//! assert_eq!(layout::file_name_range(Index::new(1)), 0x8008..0x8010);
+//! assert_eq!(layout::region(0x8400), Some(LayoutRegion::Block { index: 2 }));
+//! ```
+
+use crate::{
+    fs::{self, Filesystem, Index},
+    song::SongMemory,
+};
+use std::ops::Range;
+
+/// The uncompressed song currently being worked on
+pub const WORKING_MEMORY_RANGE: Range<usize> = 0..SongMemory::LEN;
+
+/// Where the [`Filesystem`] starts, with its metadata in block 0
+pub const FILESYSTEM_START: usize = SongMemory::LEN;
+
+/// The names of all files, 8 bytes per file slot
+pub const FILE_NAMES_RANGE: Range<usize> = absolute(fs::FILE_NAMES_RANGE);
+
+/// The versions of all files, one byte per file slot
+pub const FILE_VERSIONS_RANGE: Range<usize> = absolute(fs::FILE_VERSIONS_RANGE);
+
+/// The bytes LSDJ checks to see whether the filesystem was initialized
+pub const CHECK_RANGE: Range<usize> = absolute(fs::CHECK_RANGE);
+
+/// The byte containing the index of the file loaded into working memory (0xFF for none)
+pub const ACTIVE_FILE_OFFSET: usize = FILESYSTEM_START + fs::ACTIVE_FILE_INDEX;
+
+/// The block allocation table, storing which file each block (from block 1 on) belongs to
+pub const ALLOC_TABLE_RANGE: Range<usize> = absolute(fs::ALLOC_TABLE_RANGE);
+
+/// The byte range of a file slot's name
+pub fn file_name_range(index: Index) -> Range<usize> {
+    let start = FILE_NAMES_RANGE.start + u8::from(index) as usize * 8;
+    start..start + 8
+}
+
+/// The offset of a file slot's version byte
+pub fn file_version_offset(index: Index) -> usize {
+    FILE_VERSIONS_RANGE.start + u8::from(index) as usize
+}
+
+/// The byte range of a block in the filesystem
+///
+/// Block 0 contains the filesystem metadata, compressed songs are stored from block 1 on.
+pub fn block_range(block: u8) -> Range<usize> {
+    let start = FILESYSTEM_START + block as usize * Filesystem::BLOCK_LEN;
+    start..start + Filesystem::BLOCK_LEN
+}
+
+/// The semantic meaning of a byte in SRAM, as returned by [`region()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutRegion {
+    /// Part of the uncompressed working memory song
+    WorkingMemory,
+
+    /// Part of the name of a file slot
+    FileName { index: Index },
+
+    /// The version of a file slot
+    FileVersion { index: Index },
+
+    /// Unused bytes in block 0
+    Reserved,
+
+    /// The filesystem initialization check bytes
+    Check,
+
+    /// The active file index
+    ActiveFile,
+
+    /// The block allocation table
+    AllocTable,
+
+    /// A compression block holding song data (never block 0)
+    Block { index: u8 },
+}
+
+/// Map an absolute SRAM offset to the region it's part of
+///
+/// Returns `None` for offsets past the end of the SRAM.
+pub fn region(offset: usize) -> Option<LayoutRegion> {
+    let region = if WORKING_MEMORY_RANGE.contains(&offset) {
+        LayoutRegion::WorkingMemory
+    } else if FILE_NAMES_RANGE.contains(&offset) {
+        LayoutRegion::FileName {
+            index: Index::new(((offset - FILE_NAMES_RANGE.start) / 8) as u8),
+        }
+    } else if FILE_VERSIONS_RANGE.contains(&offset) {
+        LayoutRegion::FileVersion {
+            index: Index::new((offset - FILE_VERSIONS_RANGE.start) as u8),
+        }
+    } else if CHECK_RANGE.contains(&offset) {
+        LayoutRegion::Check
+    } else if offset == ACTIVE_FILE_OFFSET {
+        LayoutRegion::ActiveFile
+    } else if ALLOC_TABLE_RANGE.contains(&offset) {
+        LayoutRegion::AllocTable
+    } else if block_range(0).contains(&offset) {
+        LayoutRegion::Reserved
+    } else if offset < FILESYSTEM_START + Filesystem::LEN {
+        LayoutRegion::Block {
+            index: ((offset - FILESYSTEM_START) / Filesystem::BLOCK_LEN) as u8,
+        }
+    } else {
+        return None;
+    };
+
+    Some(region)
+}
+
+/// Offset a range within the filesystem to an absolute SRAM range
+const fn absolute(range: Range<usize>) -> Range<usize> {
+    FILESYSTEM_START + range.start..FILESYSTEM_START + range.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sram::SRam;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(WORKING_MEMORY_RANGE, 0x0000..0x8000);
+        assert_eq!(file_name_range(Index::new(0)), 0x8000..0x8008);
+        assert_eq!(file_name_range(Index::new(31)), 0x80F8..0x8100);
+        assert_eq!(file_version_offset(Index::new(31)), 0x811F);
+        assert_eq!(ACTIVE_FILE_OFFSET, 0x8140);
+        assert_eq!(block_range(1), 0x8200..0x8400);
+        assert_eq!(block_range(0xBF).end, SRam::LEN);
+    }
+
+    #[test]
+    fn boundaries() {
+        assert_eq!(region(0x7FFF), Some(LayoutRegion::WorkingMemory));
+        assert_eq!(
+            region(0x8000),
+            Some(LayoutRegion::FileName {
+                index: Index::new(0)
+            })
+        );
+        assert_eq!(
+            region(0x8008),
+            Some(LayoutRegion::FileName {
+                index: Index::new(1)
+            })
+        );
+        assert_eq!(
+            region(0x80FF),
+            Some(LayoutRegion::FileName {
+                index: Index::new(31)
+            })
+        );
+        assert_eq!(
+            region(0x8100),
+            Some(LayoutRegion::FileVersion {
+                index: Index::new(0)
+            })
+        );
+        assert_eq!(
+            region(0x811F),
+            Some(LayoutRegion::FileVersion {
+                index: Index::new(31)
+            })
+        );
+        assert_eq!(region(0x8120), Some(LayoutRegion::Reserved));
+        assert_eq!(region(0x813D), Some(LayoutRegion::Reserved));
+        assert_eq!(region(0x813E), Some(LayoutRegion::Check));
+        assert_eq!(region(0x813F), Some(LayoutRegion::Check));
+        assert_eq!(region(0x8140), Some(LayoutRegion::ActiveFile));
+        assert_eq!(region(0x8141), Some(LayoutRegion::AllocTable));
+        assert_eq!(region(0x81FF), Some(LayoutRegion::AllocTable));
+        assert_eq!(region(0x8200), Some(LayoutRegion::Block { index: 1 }));
+        assert_eq!(region(0x83FF), Some(LayoutRegion::Block { index: 1 }));
+        assert_eq!(
+            region(SRam::LEN - 1),
+            Some(LayoutRegion::Block { index: 0xBF })
+        );
+        assert_eq!(region(SRam::LEN), None);
+    }
+}
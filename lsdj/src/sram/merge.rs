@@ -0,0 +1,495 @@
+//! Combining the songs of multiple SRAMs into one
+
+use super::SRam;
+use crate::{
+    fs::{File, Filesystem, Index, InsertFileError},
+    name::{self, Name},
+    serde::CompressBlockError,
+    song::{self, SongMemory},
+};
+use thiserror::Error;
+
+/// How [`merge()`] deals with songs that share a name or don't fit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergePolicy {
+    /// What to do with songs that have the same name, but different contents
+    pub conflict: Conflict,
+
+    /// What to do when the songs don't all fit in a single filesystem
+    pub overflow: Overflow,
+}
+
+/// What [`merge()`] does with songs that have the same name, but different contents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Conflict {
+    /// Keep the song with the highest version, or the first one when the versions are equal
+    #[default]
+    KeepHighestVersion,
+
+    /// Keep both songs, giving the later one a numbered suffix (e.g. `HONEY2`)
+    KeepBoth,
+
+    /// Fail the merge
+    Error,
+}
+
+/// What [`merge()`] does when there are too many songs, or too few blocks to store them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail the merge
+    #[default]
+    Fail,
+
+    /// Leave out the songs that take up the most blocks, until the rest fits
+    SkipLargest,
+}
+
+/// What happened to a single song during [`merge()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum MergeOutcome {
+    /// The song was stored in a file slot
+    Inserted { slot: u8 },
+
+    /// The song was stored in a file slot under a new name, because its name was taken
+    Renamed { slot: u8, name: Name<8> },
+
+    /// A song with the same name and contents was kept instead
+    Duplicate,
+
+    /// A song with the same name and a higher version was kept instead
+    Superseded,
+
+    /// The song was left out, because not everything fit
+    Skipped,
+}
+
+/// A song from one of the [`SRam`]'s passed to [`merge()`], and what happened to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub struct MergedSong {
+    /// The position of the source SRAM in the slice passed to [`merge()`]
+    pub source: usize,
+
+    /// The file slot the song was stored in, in the source SRAM
+    pub slot: u8,
+
+    /// The name of the song, in the source SRAM
+    pub name: Name<8>,
+
+    /// The version of the song
+    pub version: u8,
+
+    /// What happened to the song
+    pub outcome: MergeOutcome,
+}
+
+/// The result of [`merge()`]
+pub struct Merged {
+    /// The SRAM containing the merged songs, with an empty working memory song
+    pub sram: SRam,
+
+    /// Every song from every source SRAM, in the order they were read
+    pub songs: Vec<MergedSong>,
+}
+
+/// Combine the songs of multiple [`SRam`]'s into a new one
+///
+/// The songs are read from every SRAM in order, and stored in the new filesystem from slot 0
+/// onwards. Songs with the same name and contents are only stored once (keeping the highest
+/// version), while songs with the same name and different contents are handled according to
+/// the [`Conflict`] policy. The working memory songs are not merged.
+pub fn merge(srams: &[&SRam], policy: MergePolicy) -> Result<Merged, MergeError> {
+    let mut songs = Vec::new();
+    let mut kept: Vec<Candidate> = Vec::new();
+
+    for (source, sram) in srams.iter().enumerate() {
+        for (slot, file) in sram.filesystem.occupied_files() {
+            let name = file.name()?;
+            let song = file.decompress()?;
+
+            let mut candidate = Candidate {
+                song: songs.len(),
+                blocks: song.compressed_block_count()?,
+                original: name.clone(),
+                name: name.clone(),
+                version: file.version(),
+                memory: song,
+            };
+            songs.push(MergedSong {
+                source,
+                slot: slot.into(),
+                name,
+                version: candidate.version,
+                outcome: MergeOutcome::Skipped,
+            });
+
+            // Songs that were renamed still count as duplicates under their original name
+            let duplicate = kept.iter().position(|other| {
+                (other.original == candidate.original || other.name == candidate.name)
+                    && other.memory.as_slice() == candidate.memory.as_slice()
+            });
+            let conflict = kept.iter().position(|other| other.name == candidate.name);
+
+            let position = match (duplicate, conflict, policy.conflict) {
+                (Some(position), _, _) => position,
+                (None, None, _) => {
+                    kept.push(candidate);
+                    continue;
+                }
+                (None, Some(position), Conflict::KeepHighestVersion) => position,
+                (None, Some(_), Conflict::KeepBoth) => {
+                    candidate.name = suffixed(&candidate.original, &kept);
+                    kept.push(candidate);
+                    continue;
+                }
+                (None, Some(_), Conflict::Error) => {
+                    return Err(MergeError::Conflict {
+                        name: candidate.name,
+                    })
+                }
+            };
+
+            let dropped = if candidate.version > kept[position].version {
+                // The song takes over the spot (and name) of the one it replaces
+                candidate.name = kept[position].name.clone();
+                std::mem::replace(&mut kept[position], candidate).song
+            } else {
+                candidate.song
+            };
+
+            songs[dropped].outcome = if duplicate.is_some() {
+                MergeOutcome::Duplicate
+            } else {
+                MergeOutcome::Superseded
+            };
+        }
+    }
+
+    fit(&mut kept, policy.overflow)?;
+
+    let mut sram = SRam::new();
+    for (slot, candidate) in kept.iter().enumerate() {
+        let index = Index::new(slot as u8);
+        sram.filesystem.insert_file(
+            index,
+            &candidate.name,
+            candidate.version,
+            &candidate.memory,
+        )?;
+
+        let song = &mut songs[candidate.song];
+        song.outcome = if candidate.name == song.name {
+            MergeOutcome::Inserted { slot: slot as u8 }
+        } else {
+            MergeOutcome::Renamed {
+                slot: slot as u8,
+                name: candidate.name.clone(),
+            }
+        };
+    }
+
+    Ok(Merged { sram, songs })
+}
+
+/// A song that is (for now) going to be stored in the merged SRAM
+struct Candidate {
+    /// The index of the song in the report
+    song: usize,
+
+    /// The name the song was read with
+    original: Name<8>,
+
+    /// The name the song will be stored with
+    name: Name<8>,
+
+    version: u8,
+    memory: SongMemory,
+
+    /// The number of blocks the song compresses to
+    blocks: usize,
+}
+
+/// Find a name for a song that conflicts with another, by adding a numbered suffix
+fn suffixed(name: &Name<8>, kept: &[Candidate]) -> Name<8> {
+    (2..)
+        .map(|number: usize| {
+            let suffix = number.to_string();
+            let len = name.len().min(8 - suffix.len());
+            let bytes = [&name.bytes()[..len], suffix.as_bytes()].concat();
+
+            Name::from_bytes(&bytes).unwrap()
+        })
+        .find(|name| kept.iter().all(|other| other.name != *name))
+        .unwrap()
+}
+
+/// Make sure the kept songs fit in a single filesystem
+fn fit(kept: &mut Vec<Candidate>, overflow: Overflow) -> Result<(), MergeError> {
+    loop {
+        let blocks: usize = kept.iter().map(|candidate| candidate.blocks).sum();
+        let fits =
+            kept.len() <= Filesystem::FILES_CAPACITY && blocks <= Filesystem::FILE_BLOCKS_CAPACITY;
+
+        match overflow {
+            _ if fits => return Ok(()),
+            Overflow::Fail if kept.len() > Filesystem::FILES_CAPACITY => {
+                return Err(MergeError::TooManySongs { songs: kept.len() })
+            }
+            Overflow::Fail => {
+                return Err(MergeError::NotEnoughBlocks {
+                    needed: blocks,
+                    free: Filesystem::FILE_BLOCKS_CAPACITY,
+                })
+            }
+            Overflow::SkipLargest => {
+                // Songs left out keep their Skipped outcome in the report
+                let largest = (0..kept.len())
+                    .max_by_key(|index| kept[*index].blocks)
+                    .unwrap();
+                kept.remove(largest);
+            }
+        }
+    }
+}
+
+/// Errors that might be returned from [`merge()`]
+#[derive(Debug, Error)]
+pub enum MergeError {
+    /// Two songs share a name but differ, and the policy is [`Conflict::Error`]
+    #[error("There are different songs named {name}")]
+    Conflict { name: Name<8> },
+
+    /// There are more songs than file slots
+    #[error(
+        "There are {songs} songs, but only {} file slots",
+        Filesystem::FILES_CAPACITY
+    )]
+    TooManySongs { songs: usize },
+
+    /// The songs need more blocks than a filesystem has
+    #[error("The songs need {needed} blocks, but only {free} are available")]
+    NotEnoughBlocks { needed: usize, free: usize },
+
+    /// The name of a song is invalid
+    #[error("Reading the name failed")]
+    Name(#[from] name::FromBytesError),
+
+    /// Decompressing a song failed
+    #[error("Decompressing the song failed")]
+    Decompress(#[from] song::FromReaderError),
+
+    /// Compressing a song failed
+    #[error("Compressing the song failed")]
+    Compress(#[from] CompressBlockError),
+
+    /// Inserting a song into the filesystem failed
+    #[error("Inserting the song into the filesystem failed")]
+    InsertFile(#[from] InsertFileError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::song::test_util::noisy_song;
+
+    /// An empty song, made unique by a marker byte
+    fn song(marker: u8) -> SongMemory {
+        let mut song = SongMemory::new();
+        song.as_mut_slice()[0x100] = marker;
+        song
+    }
+
+    /// An SRAM with songs (name, version, song) from slot 0 onwards
+    ///
+    /// It's boxed, because a couple of SRAM's quickly overflow the stack of a test thread.
+    fn sram<'a, S>(songs: impl IntoIterator<Item = (S, u8, &'a SongMemory)>) -> Box<SRam>
+    where
+        S: AsRef<str>,
+    {
+        let mut sram = Box::new(SRam::new());
+        for (slot, (name, version, song)) in songs.into_iter().enumerate() {
+            sram.filesystem
+                .insert_file(
+                    Index::new(slot as u8),
+                    &name.as_ref().try_into().unwrap(),
+                    version,
+                    song,
+                )
+                .unwrap();
+        }
+        sram
+    }
+
+    fn outcomes(merged: &Merged) -> Vec<(usize, &str, MergeOutcome)> {
+        merged
+            .songs
+            .iter()
+            .map(|song| (song.source, song.name.as_str(), song.outcome.clone()))
+            .collect()
+    }
+
+    /// The name, version and marker byte of the song in a slot
+    fn stored(sram: &SRam, slot: u8) -> (String, u8, u8) {
+        let file = sram.filesystem.file(Index::new(slot)).unwrap();
+        (
+            file.name().unwrap().as_str().to_owned(),
+            file.version(),
+            file.decompress().unwrap().as_slice()[0x100],
+        )
+    }
+
+    #[test]
+    fn keep_highest_version() {
+        let a = sram([("HONEY", 1, &song(1)), ("FUNGAL", 0, &song(2))]);
+        let b = sram([("HONEY", 3, &song(3)), ("FUNGAL", 0, &song(2))]);
+
+        let merged = merge(&[a.as_ref(), b.as_ref()], MergePolicy::default()).unwrap();
+        assert_eq!(
+            outcomes(&merged),
+            [
+                (0, "HONEY", MergeOutcome::Superseded),
+                (0, "FUNGAL", MergeOutcome::Inserted { slot: 1 }),
+                (1, "HONEY", MergeOutcome::Inserted { slot: 0 }),
+                (1, "FUNGAL", MergeOutcome::Duplicate),
+            ]
+        );
+
+        assert_eq!(merged.sram.filesystem.occupied_files().len(), 2);
+        assert_eq!(stored(&merged.sram, 0), ("HONEY".to_owned(), 3, 3));
+        assert_eq!(stored(&merged.sram, 1), ("FUNGAL".to_owned(), 0, 2));
+        assert_eq!(merged.sram.filesystem.active_file(), None);
+    }
+
+    #[test]
+    fn keep_both() {
+        let (one, two, three) = (song(1), song(2), song(3));
+        let a = sram([("HONEY", 0, &one), ("LONGNAME", 0, &one)]);
+        let b = sram([
+            ("HONEY", 0, &two),
+            ("HONEY", 0, &three),
+            ("HONEY", 0, &two),
+            ("LONGNAME", 0, &two),
+        ]);
+
+        let policy = MergePolicy {
+            conflict: Conflict::KeepBoth,
+            ..Default::default()
+        };
+        let merged = merge(&[a.as_ref(), b.as_ref()], policy).unwrap();
+
+        let renamed = |slot, name: &str| MergeOutcome::Renamed {
+            slot,
+            name: name.try_into().unwrap(),
+        };
+        assert_eq!(
+            outcomes(&merged),
+            [
+                (0, "HONEY", MergeOutcome::Inserted { slot: 0 }),
+                (0, "LONGNAME", MergeOutcome::Inserted { slot: 1 }),
+                (1, "HONEY", renamed(2, "HONEY2")),
+                (1, "HONEY", renamed(3, "HONEY3")),
+                (1, "HONEY", MergeOutcome::Duplicate),
+                (1, "LONGNAME", renamed(4, "LONGNAM2")),
+            ]
+        );
+
+        assert_eq!(stored(&merged.sram, 2), ("HONEY2".to_owned(), 0, 2));
+        assert_eq!(stored(&merged.sram, 3), ("HONEY3".to_owned(), 0, 3));
+    }
+
+    #[test]
+    fn conflict_error() {
+        let a = sram([("HONEY", 0, &song(1))]);
+        let b = sram([("HONEY", 1, &song(1))]);
+        let c = sram([("HONEY", 0, &song(2))]);
+
+        let policy = MergePolicy {
+            conflict: Conflict::Error,
+            ..Default::default()
+        };
+
+        // Duplicates aren't conflicts
+        let merged = merge(&[a.as_ref(), b.as_ref()], policy).unwrap();
+        assert_eq!(stored(&merged.sram, 0), ("HONEY".to_owned(), 1, 1));
+        drop(merged);
+
+        assert!(matches!(
+            merge(&[a.as_ref(), c.as_ref()], policy),
+            Err(MergeError::Conflict { name }) if name.as_str() == "HONEY"
+        ));
+    }
+
+    #[test]
+    fn too_many_songs() {
+        let (one, two) = (song(1), song(2));
+        let a = sram((0..20).map(|index| (format!("SONG{index}"), 0, &one)));
+        let b = sram((0..20).map(|index| (format!("SONG{index}"), 0, &two)));
+
+        let policy = MergePolicy {
+            conflict: Conflict::KeepBoth,
+            overflow: Overflow::Fail,
+        };
+        assert!(matches!(
+            merge(&[a.as_ref(), b.as_ref()], policy),
+            Err(MergeError::TooManySongs { songs: 40 })
+        ));
+
+        let policy = MergePolicy {
+            overflow: Overflow::SkipLargest,
+            ..policy
+        };
+        let merged = merge(&[a.as_ref(), b.as_ref()], policy).unwrap();
+        let skipped = merged
+            .songs
+            .iter()
+            .filter(|song| song.outcome == MergeOutcome::Skipped)
+            .count();
+
+        assert_eq!(skipped, 40 - Filesystem::FILES_CAPACITY);
+        assert_eq!(
+            merged.sram.filesystem.occupied_files().len(),
+            Filesystem::FILES_CAPACITY
+        );
+    }
+
+    #[test]
+    fn not_enough_blocks() {
+        let (large, small) = (noisy_song(0x1E00, 1), noisy_song(0x0800, 1));
+        let a = sram((0..10).map(|index| (format!("LARGE{index}"), 0, &large)));
+        let b = sram((0..8).map(|index| (format!("SMALL{index}"), 0, &small)));
+
+        let large = large.compressed_block_count().unwrap();
+        let small = small.compressed_block_count().unwrap();
+        let needed = 10 * large + 8 * small;
+        assert!(large > small);
+        assert!(needed > Filesystem::FILE_BLOCKS_CAPACITY);
+
+        match merge(&[a.as_ref(), b.as_ref()], MergePolicy::default()) {
+            Err(MergeError::NotEnoughBlocks { needed: n, free }) => {
+                assert_eq!(n, needed);
+                assert_eq!(free, Filesystem::FILE_BLOCKS_CAPACITY);
+                assert_eq!(free, Filesystem::new().blocks_free_count());
+            }
+            result => panic!("Expected NotEnoughBlocks, got {:?}", result.err()),
+        }
+
+        let policy = MergePolicy {
+            overflow: Overflow::SkipLargest,
+            ..Default::default()
+        };
+        let merged = merge(&[a.as_ref(), b.as_ref()], policy).unwrap();
+
+        let skipped = (Filesystem::FILE_BLOCKS_CAPACITY..needed)
+            .step_by(large)
+            .count();
+        for song in &merged.songs {
+            let is_skipped = song.outcome == MergeOutcome::Skipped;
+            assert_eq!(
+                is_skipped,
+                song.source == 0 && song.slot >= 10 - skipped as u8
+            );
+        }
+        assert!(merged.sram.filesystem.blocks_used_count() <= Filesystem::FILE_BLOCKS_CAPACITY);
+    }
+}
@@ -5,9 +5,17 @@
 //! emulators use to store the SRAM tied to a ROM. You can also download/upload `.sav`
 //! files to flashcarts for playback on real hardware.
 
+pub mod build;
+pub mod diff;
+pub mod layout;
+pub mod merge;
+
 use crate::{
-    fs::{self, Filesystem},
-    song::{self, SongMemory},
+    fs::{self, File as _, Filesystem},
+    lsdsng::LsdSng,
+    name::{self, Name},
+    serde::CompressBlockError,
+    song::{self, SongMemory, Validation},
 };
 use std::{
     fs::{create_dir_all, File},
@@ -16,6 +24,12 @@ use std::{
 };
 use thiserror::Error;
 
+pub use build::{
+    build_from_files, read_files, read_reader, BuildOptions, Dedupe, Format, Progress, Source,
+};
+pub use diff::{diff, SRamDiff};
+pub use merge::{merge, MergeError, MergePolicy, Merged};
+
 /// A full representation of LittleSoundDJ SRAM
 ///
 /// Every LSDJ save file consists of the same amount of bytes, in which both the song you're
@@ -59,9 +73,15 @@ pub struct SRam {
 
     /// Compressed storage for songs not currently worked on
     pub filesystem: Filesystem,
+
+    /// The bytes following the SRAM in the source it was read from (see [`SRam::extra_banks()`])
+    extra_banks: Vec<u8>,
 }
 
 impl SRam {
+    /// The number of bytes taken up by SRAM
+    pub const LEN: usize = SongMemory::LEN + Filesystem::LEN;
+
     /// Construct a new SRAM, with a default song and empty filesystem
     ///
     /// This function also sets some necessary verification bytes which LSDJ uses to check
@@ -70,23 +90,157 @@ impl SRam {
         Self {
             working_memory_song: SongMemory::new(),
             filesystem: Filesystem::new(),
+            extra_banks: Vec::new(),
         }
     }
 
     /// Deserialize SRAM from an arbitrary I/O reader
-    pub fn from_reader<R>(mut reader: R) -> Result<Self, FromReaderError>
+    ///
+    /// See [`SRam::from_reader_with_size()`] for how readers with more or fewer bytes
+    /// than [`SRam::LEN`] are handled.
+    pub fn from_reader<R>(reader: R) -> Result<Self, FromReaderError>
+    where
+        R: Read,
+    {
+        let (sram, _) = Self::from_reader_with_size(reader)?;
+        Ok(sram)
+    }
+
+    /// Deserialize SRAM from an arbitrary I/O reader, reporting on the amount of bytes read
+    ///
+    /// Save files aren't always exactly [`SRam::LEN`] bytes long. Some emulators and flashcarts
+    /// pad them, or append extra data such as RTC state or a second bank. This function reads the
+    /// reader until its end, keeps anything after the SRAM itself as [`SRam::extra_banks()`] and
+    /// reports this through [`SRamSize::Padded`].
+    ///
+    /// Readers with too few bytes result in [`FromReaderError::TooShort`].
+    pub fn from_reader_with_size<R>(reader: R) -> Result<(Self, SRamSize), FromReaderError>
+    where
+        R: Read,
+    {
+        Self::from_reader_with_options(reader, ReadOptions::default())
+    }
+
+    /// Deserialize SRAM from an arbitrary I/O reader, with control over how strict to be
+    ///
+    /// This works like [`SRam::from_reader_with_size()`], except that trailing bytes can be
+    /// rejected with [`FromReaderError::TrailingData`], for example to verify that a file isn't
+    /// corrupted or a concatenation of multiple save files.
+    pub fn from_reader_with_options<R>(
+        mut reader: R,
+        options: ReadOptions,
+    ) -> Result<(Self, SRamSize), FromReaderError>
+    where
+        R: Read,
+    {
+        let mut bytes = Vec::with_capacity(Self::LEN);
+        reader.read_to_end(&mut bytes)?;
+
+        if bytes.len() < Self::LEN {
+            return Err(FromReaderError::TooShort {
+                expected: Self::LEN,
+                got: bytes.len(),
+            });
+        }
+
+        let working_memory_song =
+            SongMemory::from_reader_with_validation(&bytes[..SongMemory::LEN], options.validation)?;
+        let filesystem = Filesystem::from_reader(&bytes[SongMemory::LEN..Self::LEN])?;
+
+        let size = match bytes.len() - Self::LEN {
+            0 => SRamSize::Exact,
+            padding if options.allow_trailing => SRamSize::Padded(padding),
+            padding => return Err(FromReaderError::TrailingData(padding)),
+        };
+
+        Ok((
+            Self {
+                working_memory_song,
+                filesystem,
+                extra_banks: bytes.split_off(Self::LEN),
+            },
+            size,
+        ))
+    }
+
+    /// Deserialize SRAM from an arbitrary I/O reader, returning any bytes following it
+    ///
+    /// The bytes are returned instead of kept as [`SRam::extra_banks()`], so they can be passed
+    /// to [`SRam::to_writer_with_trailing()`] separately.
+    #[deprecated(note = "the bytes are kept on the SRam itself, see SRam::extra_banks()")]
+    pub fn from_reader_with_trailing<R>(mut reader: R) -> Result<(Self, Vec<u8>), FromReaderError>
     where
         R: Read,
     {
-        let working_memory_song = SongMemory::from_reader(&mut reader)?;
-        let filesystem = Filesystem::from_reader(&mut reader)?;
+        let mut bytes = Vec::with_capacity(Self::LEN);
+        reader.read_to_end(&mut bytes)?;
+
+        let trailing = bytes.split_off(bytes.len().min(Self::LEN));
+        let (sram, _) = Self::from_reader_with_options(bytes.as_slice(), ReadOptions::default())?;
+
+        Ok((sram, trailing))
+    }
+
+    /// Deserialize SRAM from a slice of bytes
+    ///
+    /// Unlike [`SRam::from_reader()`], this requires the slice to be exactly [`SRam::LEN`]
+    /// bytes long.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromBytesError> {
+        Self::from_bytes_with_validation(bytes, Validation::Strict)
+    }
+
+    /// Deserialize SRAM from a slice of bytes, choosing how strictly the working memory song
+    /// is verified
+    pub fn from_bytes_with_validation(
+        bytes: &[u8],
+        validation: Validation,
+    ) -> Result<Self, FromBytesError> {
+        if bytes.len() < Self::LEN {
+            return Err(FromBytesError::TooShort {
+                expected: Self::LEN,
+                got: bytes.len(),
+            });
+        } else if bytes.len() > Self::LEN {
+            return Err(FromBytesError::TooLong {
+                expected: Self::LEN,
+                got: bytes.len(),
+            });
+        }
+
+        let (working_memory_song, filesystem) = bytes.split_at(SongMemory::LEN);
 
         Ok(Self {
-            working_memory_song,
-            filesystem,
+            working_memory_song: SongMemory::from_bytes_with_validation(
+                working_memory_song,
+                validation,
+            )?,
+            filesystem: Filesystem::from_bytes(filesystem)?,
+            extra_banks: Vec::new(),
         })
     }
 
+    /// Deserialize every bank from a reader containing multiple concatenated SRAM images
+    ///
+    /// Some flashcart backup tools store multiple SRAM banks in a single file, one after the
+    /// other. This function reads the reader until its end and tries to deserialize every
+    /// [`SRam::LEN`] bytes as a separate bank, returning the result per bank. Any trailing bytes
+    /// not making up a full bank are reported as [`FromBytesError::TooShort`].
+    pub fn from_reader_multi<R>(
+        mut reader: R,
+        validation: Validation,
+    ) -> Result<Vec<Result<Self, FromBytesError>>, io::Error>
+    where
+        R: Read,
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        Ok(bytes
+            .chunks(Self::LEN)
+            .map(|bank| Self::from_bytes_with_validation(bank, validation))
+            .collect())
+    }
+
     /// Deserialize SRAM from a path on disk (.sav)
     pub fn from_path<P>(path: P) -> Result<Self, FromPathError>
     where
@@ -98,13 +252,62 @@ impl SRam {
         Ok(sram)
     }
 
-    /// Serialize SRAM to an arbitrary I/O writer
-    pub fn to_writer<W>(&self, mut writer: W) -> Result<(), io::Error>
+    /// Deserialize SRAM from a path on disk (.sav), returning any bytes following it
+    ///
+    /// See [`SRam::from_reader_with_trailing()`].
+    #[deprecated(note = "the bytes are kept on the SRam itself, see SRam::extra_banks()")]
+    #[allow(deprecated)]
+    pub fn from_path_with_trailing<P>(path: P) -> Result<(Self, Vec<u8>), FromPathError>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        Ok(Self::from_reader_with_trailing(file)?)
+    }
+
+    /// The bytes that followed the SRAM in the source it was read from
+    ///
+    /// This can be another SRAM bank, or emulator state such as the RTC. They aren't parsed, but
+    /// [`SRam::to_writer()`] writes them back after the SRAM, so a load and save round-trip keeps
+    /// the file intact.
+    pub fn extra_banks(&self) -> &[u8] {
+        &self.extra_banks
+    }
+
+    /// Replace the bytes written after the SRAM (see [`SRam::extra_banks()`])
+    pub fn set_extra_banks(&mut self, bytes: Vec<u8>) {
+        self.extra_banks = bytes;
+    }
+
+    /// Serialize SRAM to an arbitrary I/O writer, followed by its [`SRam::extra_banks()`]
+    pub fn to_writer<W>(&self, writer: W) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        self.to_writer_with_trailing(writer, &self.extra_banks)
+    }
+
+    /// Serialize SRAM to an arbitrary I/O writer, followed by extra bytes
+    ///
+    /// The bytes are written instead of [`SRam::extra_banks()`].
+    pub fn to_writer_with_trailing<W>(&self, mut writer: W, trailing: &[u8]) -> io::Result<()>
     where
         W: Write,
     {
         self.working_memory_song.to_writer(&mut writer)?;
-        self.filesystem.to_writer(writer)
+        self.filesystem.to_writer(&mut writer)?;
+        writer.write_all(trailing)
+    }
+
+    /// Copy the raw bytes that make up the SRAM into a [`Vec`], followed by its
+    /// [`SRam::extra_banks()`]
+    pub fn to_vec(&self) -> Vec<u8> {
+        [
+            self.working_memory_song.as_slice(),
+            self.filesystem.as_slice(),
+            &self.extra_banks,
+        ]
+        .concat()
     }
 
     /// Serialize SRAM to a path on disk (.sav)
@@ -116,6 +319,155 @@ impl SRam {
         create_dir_all(path.parent().unwrap())?;
         self.to_writer(File::create(path)?)
     }
+
+    /// Serialize SRAM to a path on disk (.sav), followed by extra bytes
+    ///
+    /// See [`SRam::to_writer_with_trailing()`].
+    pub fn to_path_with_trailing<P>(&self, path: P, trailing: &[u8]) -> Result<(), io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        create_dir_all(path.parent().unwrap())?;
+        self.to_writer_with_trailing(File::create(path)?, trailing)
+    }
+
+    /// Save the working memory song into the filesystem, like LSDJ does when saving a song
+    ///
+    /// The song is compressed into the file slot at `index` and that slot becomes the active
+    /// file. If the slot was already occupied, the old file is replaced and the version number
+    /// is incremented. Otherwise, the version starts at 0.
+    ///
+    /// Returns whether the song stored in the slot changed, which is false when the slot already
+    /// contained the working memory song. The version is incremented either way, like LSDJ does.
+    pub fn commit_working_memory(
+        &mut self,
+        index: fs::Index,
+        name: &Name<8>,
+    ) -> Result<bool, fs::InsertFileError> {
+        let (version, changed) = match self.filesystem.file(index) {
+            Some(file) => (
+                file.version().wrapping_add(1),
+                !self.is_working_memory_song(&file),
+            ),
+            None => (0, true),
+        };
+
+        self.filesystem
+            .insert_file(index, name, version, &self.working_memory_song)?;
+        self.filesystem.set_active_file(Some(index));
+
+        Ok(changed)
+    }
+
+    /// Does the working memory song differ from the active file?
+    ///
+    /// This is how you can tell whether there are unsaved changes. Returns `None` when there is
+    /// no active file to compare with. A file that can't be decompressed counts as different.
+    pub fn working_memory_changed(&self) -> Option<bool> {
+        let file = self.filesystem.file(self.filesystem.active_file()?)?;
+        Some(!self.is_working_memory_song(&file))
+    }
+
+    /// Compress the working memory song into an [`LsdSng`]
+    ///
+    /// The name and version are taken from the active file, if there is one. When there is no
+    /// active file (or it points to an empty slot), the name `WORKMEM` and version 0 are used.
+    pub fn working_memory_lsdsng(&self) -> Result<LsdSng, CompressBlockError> {
+        let (name, version) = self
+            .filesystem
+            .active_file()
+            .and_then(|index| self.filesystem.file(index))
+            .and_then(|file| Some((file.name().ok()?, file.version())))
+            .unwrap_or_else(|| (Name::from_bytes(b"WORKMEM").unwrap(), 0));
+
+        self.export_working_memory(name, version)
+    }
+
+    /// Compress the working memory song into an [`LsdSng`] with a given name and version
+    ///
+    /// Unlike [`SRam::commit_working_memory()`], this leaves the filesystem untouched.
+    pub fn export_working_memory(
+        &self,
+        name: Name<8>,
+        version: u8,
+    ) -> Result<LsdSng, CompressBlockError> {
+        LsdSng::from_song(name, version, &self.working_memory_song)
+    }
+
+    /// Insert an [`LsdSng`] into the first free file slot, returning which slot that is
+    ///
+    /// The song keeps its name and version.
+    pub fn insert_lsdsng(&mut self, lsdsng: &LsdSng) -> Result<fs::Index, InsertError> {
+        let index = self
+            .filesystem
+            .free_slots()
+            .next()
+            .ok_or(InsertError::FilesystemFull)?;
+
+        self.insert_lsdsng_at(index, lsdsng)?;
+
+        Ok(index)
+    }
+
+    /// Insert an [`LsdSng`] into a specific file slot, returning the file it replaced (if any)
+    ///
+    /// The song keeps its name and version. Nothing is changed when an error is returned.
+    pub fn insert_lsdsng_at(
+        &mut self,
+        index: fs::Index,
+        lsdsng: &LsdSng,
+    ) -> Result<Option<LsdSng>, InsertError> {
+        let name = lsdsng.name()?;
+        let song = lsdsng.decompress()?;
+
+        match self
+            .filesystem
+            .insert_file(index, &name, lsdsng.version(), &song)
+        {
+            Err(fs::InsertFileError::NotEnoughBlocks { needed, free }) => {
+                Err(InsertError::NotEnoughBlocks { needed, free })
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Export the file at `index` as an [`LsdSng`]
+    pub fn export_lsdsng(&self, index: fs::Index) -> Result<LsdSng, ExportError> {
+        Ok(self
+            .filesystem
+            .file(index)
+            .ok_or(ExportError::EmptySlot(index))?
+            .lsdsng()?)
+    }
+
+    /// Decompress a file from the filesystem into working memory, like LSDJ does when loading a song
+    ///
+    /// This replaces the current working memory song (without saving it) and makes the file
+    /// at `index` the active file. Returns whether the working memory song changed, which is
+    /// false when it already was the song stored in the file.
+    pub fn load_to_working_memory(
+        &mut self,
+        index: fs::Index,
+    ) -> Result<bool, LoadToWorkingMemoryError> {
+        let song = self
+            .filesystem
+            .file(index)
+            .ok_or(LoadToWorkingMemoryError::EmptySlot(index))?
+            .decompress()?;
+
+        let changed = song.as_slice() != self.working_memory_song.as_slice();
+        self.working_memory_song = song;
+        self.filesystem.set_active_file(Some(index));
+
+        Ok(changed)
+    }
+
+    /// Does a file contain the working memory song?
+    fn is_working_memory_song(&self, file: &impl fs::File) -> bool {
+        file.decompress()
+            .is_ok_and(|song| song.as_slice() == self.working_memory_song.as_slice())
+    }
 }
 
 impl Default for SRam {
@@ -124,9 +476,69 @@ impl Default for SRam {
     }
 }
 
+impl<'a> TryFrom<&'a [u8]> for SRam {
+    type Error = FromBytesError;
+
+    #[inline]
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(bytes)
+    }
+}
+
+/// Options for deserializing [`SRam`] with [`SRam::from_reader_with_options()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// Accept any bytes following the SRAM, keeping them as [`SRam::extra_banks()`]
+    pub allow_trailing: bool,
+
+    /// How strictly the working memory song is verified
+    pub validation: Validation,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            allow_trailing: true,
+            validation: Validation::Strict,
+        }
+    }
+}
+
+/// The size of the source SRAM was read from, compared to [`SRam::LEN`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SRamSize {
+    /// The source contained exactly the bytes needed
+    Exact,
+
+    /// The source contained more bytes than needed, which were kept as [`SRam::extra_banks()`]
+    Padded(usize),
+}
+
+impl SRamSize {
+    /// The total number of bytes that were read from the source
+    pub fn bytes_read(&self) -> usize {
+        match self {
+            Self::Exact => SRam::LEN,
+            Self::Padded(padding) => SRam::LEN + padding,
+        }
+    }
+}
+
 /// Errors that might be returned from [`SRam::from_reader()`]
 #[derive(Debug, Error)]
 pub enum FromReaderError {
+    /// Any failure that has to do with I/O
+    #[error("Something failed with I/O")]
+    Read(#[from] io::Error),
+
+    /// The source contained fewer bytes than SRAM takes up
+    #[error("Expected {expected} bytes of SRAM, but only got {got}")]
+    TooShort { expected: usize, got: usize },
+
+    /// The source contained more bytes than SRAM takes up, while that wasn't allowed
+    #[error("Found {0} bytes of unexpected data after the SRAM")]
+    TrailingData(usize),
+
     /// Deserializing the working memory song from I/O failed
     #[error("Reading the working memory song failed")]
     WorkingSong(#[from] song::FromReaderError),
@@ -136,6 +548,26 @@ pub enum FromReaderError {
     Filesystem(#[from] fs::FromReaderError),
 }
 
+/// Errors that might be returned from [`SRam::from_bytes()`]
+#[derive(Debug, Error)]
+pub enum FromBytesError {
+    /// The slice contains fewer bytes than SRAM takes up
+    #[error("Expected {expected} bytes of SRAM, but only got {got}")]
+    TooShort { expected: usize, got: usize },
+
+    /// The slice contains more bytes than SRAM takes up
+    #[error("Expected {expected} bytes of SRAM, but got {got}")]
+    TooLong { expected: usize, got: usize },
+
+    /// Deserializing the working memory song failed
+    #[error("Reading the working memory song failed")]
+    WorkingSong(#[from] song::FromBytesError),
+
+    /// Deserializing the file system failed
+    #[error("Reading the filesystem failed")]
+    Filesystem(#[from] fs::FromBytesError),
+}
+
 /// Errors that might be returned from [`SRam::from_path()`]
 #[derive(Debug, Error)]
 pub enum FromPathError {
@@ -147,3 +579,375 @@ pub enum FromPathError {
     #[error("Reading the SRAM from file failed")]
     Read(#[from] FromReaderError),
 }
+
+/// Errors that might be returned from [`SRam::load_to_working_memory()`]
+#[derive(Debug, Error)]
+pub enum LoadToWorkingMemoryError {
+    /// There is no file stored at the requested index
+    #[error("There is no file at index {0}")]
+    EmptySlot(fs::Index),
+
+    /// Decompressing the file failed
+    #[error("Decompressing the file failed")]
+    Decompress(#[from] song::FromReaderError),
+}
+
+/// Errors that might be returned from [`SRam::insert_lsdsng()`] and [`SRam::insert_lsdsng_at()`]
+#[derive(Debug, Error)]
+pub enum InsertError {
+    /// Every file slot is already in use
+    #[error("All {} file slots are in use", Filesystem::FILES_CAPACITY)]
+    FilesystemFull,
+
+    /// There is a free slot, but there aren't enough free blocks to store the song
+    #[error("The song needs {needed} blocks, but only {free} are free")]
+    NotEnoughBlocks { needed: usize, free: usize },
+
+    /// The name of the song is invalid
+    #[error("Reading the name failed")]
+    Name(#[from] name::FromBytesError),
+
+    /// Decompressing the song failed
+    #[error("Decompressing the song failed")]
+    Decompress(#[from] song::FromReaderError),
+
+    /// Compressing the song failed
+    #[error("Compressing the song failed")]
+    Compress(#[from] CompressBlockError),
+
+    /// Inserting the song into the filesystem failed
+    #[error("Inserting the song into the filesystem failed")]
+    InsertFile(#[from] fs::InsertFileError),
+}
+
+/// Errors that might be returned from [`SRam::export_lsdsng()`]
+#[derive(Debug, Error)]
+pub enum ExportError {
+    /// There is no file stored at the requested index
+    #[error("There is no file at index {0}")]
+    EmptySlot(fs::Index),
+
+    /// Converting the file failed
+    #[error("Converting the file to an LsdSng failed")]
+    File(#[from] fs::FileToLsdSngError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::song::test_util::noisy_song;
+    use std::io::Cursor;
+
+    const SOURCE: &[u8] = include_bytes!("../../test/92L_empty.sav");
+
+    #[test]
+    fn exact() {
+        let (sram, size) = SRam::from_reader_with_size(Cursor::new(SOURCE)).unwrap();
+        assert_eq!(size, SRamSize::Exact);
+        assert!(sram.filesystem.file(fs::Index::new(0)).is_some());
+    }
+
+    #[test]
+    fn padded() {
+        let mut source = SOURCE.to_vec();
+        source.resize(SRam::LEN * 2, 0xFF);
+
+        let (sram, size) = SRam::from_reader_with_size(Cursor::new(&source)).unwrap();
+        assert_eq!(size, SRamSize::Padded(SRam::LEN));
+        assert_eq!(size.bytes_read(), source.len());
+        assert_eq!(sram.extra_banks(), &source[SRam::LEN..]);
+
+        // The second bank survives a load and save round-trip
+        let mut dest = Vec::new();
+        sram.to_writer(&mut dest).unwrap();
+        assert_eq!(dest, source);
+        assert_eq!(sram.to_vec(), source);
+
+        let mut dest = Vec::new();
+        sram.to_writer_with_trailing(&mut dest, &[]).unwrap();
+        assert_eq!(dest, SOURCE);
+    }
+
+    #[test]
+    fn garbage_appended() {
+        let source = [SOURCE, b"RTC\x01\x02\x03 metadata"].concat();
+
+        let (_, size) = SRam::from_reader_with_size(Cursor::new(&source)).unwrap();
+        assert_eq!(size, SRamSize::Padded(15));
+    }
+
+    #[test]
+    fn strict() {
+        let options = ReadOptions {
+            allow_trailing: false,
+            ..Default::default()
+        };
+
+        let (_, size) = SRam::from_reader_with_options(Cursor::new(SOURCE), options).unwrap();
+        assert_eq!(size, SRamSize::Exact);
+
+        let source = [SOURCE, SOURCE].concat();
+        assert!(matches!(
+            SRam::from_reader_with_options(Cursor::new(source), options),
+            Err(FromReaderError::TrailingData(SRam::LEN))
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let source = &SOURCE[..0x10000];
+
+        assert!(matches!(
+            SRam::from_reader(Cursor::new(source)),
+            Err(FromReaderError::TooShort {
+                expected: SRam::LEN,
+                got: 0x10000
+            })
+        ));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn trailing_round_trip() {
+        let trailing: Vec<u8> = (0..48).collect();
+        let source = [SOURCE, &trailing].concat();
+
+        let (sram, read) = SRam::from_reader_with_trailing(Cursor::new(&source)).unwrap();
+        assert_eq!(read, trailing);
+
+        let mut dest = Vec::new();
+        sram.to_writer_with_trailing(&mut dest, &read).unwrap();
+        assert_eq!(dest, source);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn no_trailing() {
+        let (_, trailing) = SRam::from_reader_with_trailing(Cursor::new(SOURCE)).unwrap();
+        assert!(trailing.is_empty());
+    }
+
+    #[test]
+    fn commit_working_memory() {
+        let mut sram = SRam::from_reader(Cursor::new(SOURCE)).unwrap();
+        let name = "EMPTY".try_into().unwrap();
+
+        assert_eq!(sram.working_memory_changed(), Some(false));
+        assert!(!sram
+            .commit_working_memory(fs::Index::new(0), &name)
+            .unwrap());
+        assert_eq!(sram.filesystem.active_file(), Some(fs::Index::new(0)));
+        let file = sram.filesystem.file(fs::Index::new(0)).unwrap();
+        assert_eq!(file.name(), Ok(name.clone()));
+        assert_eq!(file.version(), 1);
+
+        sram.working_memory_song.set_tempo(200).unwrap();
+        assert_eq!(sram.working_memory_changed(), Some(true));
+        assert!(sram
+            .commit_working_memory(fs::Index::new(0), &name)
+            .unwrap());
+        assert_eq!(sram.working_memory_changed(), Some(false));
+
+        assert!(sram
+            .commit_working_memory(fs::Index::new(3), &name)
+            .unwrap());
+        assert_eq!(sram.filesystem.active_file(), Some(fs::Index::new(3)));
+        let file = sram.filesystem.file(fs::Index::new(3)).unwrap();
+        assert_eq!(file.version(), 0);
+        assert_eq!(
+            file.decompress().unwrap().as_slice(),
+            sram.working_memory_song.as_slice()
+        );
+    }
+
+    #[test]
+    fn load_to_working_memory() {
+        let mut sram = SRam::from_reader(Cursor::new(SOURCE)).unwrap();
+        let expected = sram
+            .filesystem
+            .file(fs::Index::new(0))
+            .unwrap()
+            .decompress()
+            .unwrap();
+
+        sram.working_memory_song.set_tempo(200).unwrap();
+        sram.filesystem.set_active_file(None);
+        assert_eq!(sram.working_memory_changed(), None);
+
+        assert!(matches!(
+            sram.load_to_working_memory(fs::Index::new(1)),
+            Err(LoadToWorkingMemoryError::EmptySlot(_))
+        ));
+
+        assert!(sram.load_to_working_memory(fs::Index::new(0)).unwrap());
+        assert_eq!(sram.filesystem.active_file(), Some(fs::Index::new(0)));
+        assert_eq!(sram.working_memory_song.as_slice(), expected.as_slice());
+        assert_eq!(sram.working_memory_changed(), Some(false));
+
+        assert!(!sram.load_to_working_memory(fs::Index::new(0)).unwrap());
+    }
+
+    #[test]
+    fn insert_and_export_lsdsng() {
+        let mut sram = SRam::from_reader(Cursor::new(SOURCE)).unwrap();
+        let name = "NEW".try_into().unwrap();
+        let lsdsng = LsdSng::from_song(name, 3, &SongMemory::new()).unwrap();
+
+        assert_eq!(sram.insert_lsdsng(&lsdsng).unwrap(), fs::Index::new(1));
+        assert_eq!(sram.insert_lsdsng(&lsdsng).unwrap(), fs::Index::new(2));
+
+        let exported = sram.export_lsdsng(fs::Index::new(2)).unwrap();
+        assert_eq!(exported.name(), lsdsng.name());
+        assert_eq!(exported.version(), 3);
+        assert_eq!(
+            exported.decompress().unwrap().as_slice(),
+            SongMemory::new().as_slice()
+        );
+
+        assert!(matches!(
+            sram.export_lsdsng(fs::Index::new(3)),
+            Err(ExportError::EmptySlot(_))
+        ));
+    }
+
+    #[test]
+    fn insert_lsdsng_full() {
+        let mut sram = SRam::new();
+        let name: Name<8> = "FULL".try_into().unwrap();
+        let lsdsng = LsdSng::from_song(name, 0, &SongMemory::new()).unwrap();
+
+        for slot in 0..Filesystem::FILES_CAPACITY as u8 {
+            assert_eq!(sram.insert_lsdsng(&lsdsng).unwrap(), fs::Index::new(slot));
+        }
+        assert_eq!(sram.filesystem.free_slots().count(), 0);
+        assert!(matches!(
+            sram.insert_lsdsng(&lsdsng),
+            Err(InsertError::FilesystemFull)
+        ));
+
+        // Replacing an existing file is still possible
+        assert!(sram
+            .insert_lsdsng_at(fs::Index::new(4), &lsdsng)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn insert_lsdsng_not_enough_blocks() {
+        let mut sram = SRam::new();
+        let name: Name<8> = "NOISE".try_into().unwrap();
+        for slot in 0..2 {
+            sram.filesystem
+                .insert_file(
+                    fs::Index::new(slot),
+                    &name,
+                    0,
+                    &noisy_song(SongMemory::LEN, 1),
+                )
+                .unwrap();
+        }
+
+        let lsdsng = LsdSng::from_song(name, 0, &noisy_song(0x1E00, 1)).unwrap();
+        while sram.filesystem.blocks_free_count() >= lsdsng.block_count() {
+            sram.insert_lsdsng(&lsdsng).unwrap();
+        }
+        let before = sram.to_vec();
+
+        match sram.insert_lsdsng(&lsdsng) {
+            Err(InsertError::NotEnoughBlocks { needed, free }) => {
+                assert_eq!(needed, lsdsng.block_count());
+                assert_eq!(free, sram.filesystem.blocks_free_count());
+            }
+            result => panic!("Expected NotEnoughBlocks, got {result:?}"),
+        }
+        assert_eq!(sram.to_vec(), before);
+    }
+
+    #[test]
+    fn from_bytes() {
+        let sram = SRam::try_from(SOURCE).unwrap();
+        assert_eq!(sram.to_vec(), SOURCE);
+
+        assert!(matches!(
+            SRam::try_from(&SOURCE[..0x10000]),
+            Err(FromBytesError::TooShort {
+                expected: SRam::LEN,
+                got: 0x10000
+            })
+        ));
+
+        let source = [SOURCE, &[0]].concat();
+        assert!(matches!(
+            SRam::try_from(source.as_slice()),
+            Err(FromBytesError::TooLong { .. })
+        ));
+
+        let mut source = SOURCE.to_vec();
+        source[SongMemory::LEN + 0x13E] = 0;
+        assert!(matches!(
+            SRam::try_from(source.as_slice()),
+            Err(FromBytesError::Filesystem(
+                fs::FromBytesError::InitializationCheckIncorrect
+            ))
+        ));
+    }
+
+    #[test]
+    fn working_memory_lsdsng() {
+        let mut sram = SRam::from_reader(Cursor::new(SOURCE)).unwrap();
+        sram.working_memory_song.set_tempo(200).unwrap();
+
+        let saved = sram.filesystem.file(fs::Index::new(0)).unwrap();
+        let working = sram.working_memory_lsdsng().unwrap();
+        assert_eq!(working.name(), saved.name());
+        assert_eq!(working.version(), saved.version());
+        assert_eq!(working.decompress().unwrap().tempo(), Ok(200));
+
+        sram.filesystem.set_active_file(Some(fs::Index::new(7)));
+        let working = sram.working_memory_lsdsng().unwrap();
+        assert_eq!(working.name().unwrap().as_str(), "WORKMEM");
+        assert_eq!(working.version(), 0);
+
+        let exported = sram
+            .export_working_memory("LATEST".try_into().unwrap(), 4)
+            .unwrap();
+        assert_eq!(exported.name().unwrap().as_str(), "LATEST");
+        assert_eq!(exported.version(), 4);
+        assert_eq!(exported.blocks(), working.blocks());
+    }
+
+    #[test]
+    fn lenient() {
+        let mut source = SOURCE.to_vec();
+        for offset in [0x1E78, 0x3E80, 0x7FF0] {
+            source[offset] = 0;
+        }
+
+        assert!(SRam::from_bytes(&source).is_err());
+
+        let options = ReadOptions {
+            validation: Validation::Lenient,
+            ..Default::default()
+        };
+        let (sram, _) = SRam::from_reader_with_options(Cursor::new(&source), options).unwrap();
+        assert!(!sram.working_memory_song.has_initialization_check());
+        assert!(SRam::from_bytes_with_validation(&source, Validation::Lenient).is_ok());
+    }
+
+    #[test]
+    fn multi() {
+        let source = [SOURCE, SOURCE, &SOURCE[..0x10]].concat();
+        let banks = SRam::from_reader_multi(Cursor::new(source), Validation::Strict).unwrap();
+
+        assert_eq!(banks.len(), 3);
+        assert!(banks[0].is_ok());
+        assert!(banks[1].is_ok());
+        assert!(matches!(
+            banks[2],
+            Err(FromBytesError::TooShort {
+                expected: SRam::LEN,
+                got: 0x10
+            })
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+//! The `assemble` subcommand
+
+use crate::{
+    explode::{Manifest, ManifestSong, MANIFEST_FILENAME},
+    utils::{check_for_overwrite, content_hash, sha256},
+};
+use anyhow::{Context, Error, Result};
+use clap::Args;
+use lsdj::{
+    fs::{File, Filesystem, Index, InsertFileError},
+    lsdsng::LsdSng,
+    song::SongMemory,
+    sram::SRam,
+};
+use std::{collections::HashSet, path::Path, path::PathBuf};
+
+/// Arguments for the `assemble` subcommand
+#[derive(Args)]
+#[clap(author, version, about = "Assemble a .sav file from a folder made by explode", long_about = None)]
+pub struct AssembleArgs {
+    /// The folder containing the manifest and songs
+    path: PathBuf,
+
+    /// The output path
+    #[clap(short, long)]
+    output: PathBuf,
+}
+
+/// Assemble a .sav file from a folder made by explode
+pub fn assemble(args: AssembleArgs) -> Result<()> {
+    let sram = assemble_from(&args.path)?;
+
+    if check_for_overwrite(&args.output)? {
+        sram.to_path(&args.output).context(format!(
+            "Could not write SRAM to {}",
+            args.output.to_string_lossy()
+        ))?;
+
+        println!("Wrote {}", args.output.to_string_lossy());
+    }
+
+    Ok(())
+}
+
+/// Build an [`SRam`] from a folder written by [`explode_to()`](crate::explode::explode_to)
+///
+/// Every song ends up in the slot listed in the manifest, with the same active file and working
+/// memory. The blocks might be laid out differently than in the original save, but the songs
+/// decompress to the same bytes, which is checked against the hashes in the manifest.
+///
+/// The manifest is validated as a whole before anything is assembled, so a missing file or
+/// two songs claiming the same slot is reported instead of resulting in a partial save.
+pub fn assemble_from(folder: &Path) -> Result<SRam> {
+    let manifest = Manifest::from_path(&folder.join(MANIFEST_FILENAME))?;
+    validate(&manifest, folder)?;
+
+    let mut sram = SRam::new();
+    sram.working_memory_song = read_working_memory(folder, &manifest.working_memory)?;
+
+    for file in &manifest.files {
+        let lsdsng = LsdSng::from_path(folder.join(&file.song.filename))
+            .context(format!("Could not load {}", file.song.filename))?;
+        let song = lsdsng
+            .decompress()
+            .context(format!("Could not decompress {}", file.song.filename))?;
+        check_hash(&file.song, &content_hash(&song))?;
+
+        match sram.filesystem.insert_file(
+            Index::new(file.slot),
+            &lsdsng.name()?,
+            lsdsng.version(),
+            &song,
+        ) {
+            Err(InsertFileError::NotEnoughBlocks { .. }) => {
+                return Err(Error::msg(format!(
+                    "Ran out of space in the SRAM memory while inserting {}",
+                    file.song.filename
+                )))
+            }
+            result => {
+                result.context(format!("Could not insert {}", file.song.filename))?;
+            }
+        }
+    }
+
+    sram.filesystem
+        .set_active_file(manifest.active_file.map(Index::new));
+
+    Ok(sram)
+}
+
+/// Check the slots and filenames in a manifest, before assembling anything
+fn validate(manifest: &Manifest, folder: &Path) -> Result<()> {
+    let mut slots = HashSet::new();
+    for file in &manifest.files {
+        if file.slot as usize >= Filesystem::FILES_CAPACITY {
+            return Err(Error::msg(format!(
+                "{} is placed in slot {}, but there are only {} slots",
+                file.song.filename,
+                file.slot,
+                Filesystem::FILES_CAPACITY
+            )));
+        }
+
+        if !slots.insert(file.slot) {
+            let others: Vec<_> = manifest
+                .files
+                .iter()
+                .filter(|other| other.slot == file.slot)
+                .map(|other| other.song.filename.as_str())
+                .collect();
+
+            return Err(Error::msg(format!(
+                "Slot {} is claimed by more than one song: {}",
+                file.slot,
+                others.join(", ")
+            )));
+        }
+    }
+
+    if let Some(active) = manifest.active_file {
+        if active as usize >= Filesystem::FILES_CAPACITY {
+            return Err(Error::msg(format!(
+                "The active file is slot {active}, but there are only {} slots",
+                Filesystem::FILES_CAPACITY
+            )));
+        }
+    }
+
+    let missing: Vec<_> = manifest
+        .songs()
+        .filter(|song| !folder.join(&song.filename).is_file())
+        .map(|song| song.filename.as_str())
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(Error::msg(format!(
+            "The manifest refers to files that don't exist in {}: {}",
+            folder.to_string_lossy(),
+            missing.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+fn read_working_memory(folder: &Path, song: &ManifestSong) -> Result<SongMemory> {
+    let bytes = std::fs::read(folder.join(&song.filename))
+        .context(format!("Could not read {}", song.filename))?;
+    check_hash(song, &sha256(&bytes))?;
+
+    SongMemory::from_bytes(&bytes).context(format!("Could not parse {}", song.filename))
+}
+
+fn check_hash(song: &ManifestSong, hash: &str) -> Result<()> {
+    if hash == song.sha256 {
+        Ok(())
+    } else {
+        Err(Error::msg(format!(
+            "{} doesn't match the hash in the manifest",
+            song.filename
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::explode::explode_to;
+    use std::env::temp_dir;
+
+    /// A fresh folder in the temporary directory, unique to a test
+    fn folder(test: &str) -> PathBuf {
+        let folder = temp_dir().join(format!("lsdj-tools-{test}-{}", std::process::id()));
+        if folder.exists() {
+            std::fs::remove_dir_all(&folder).unwrap();
+        }
+
+        folder
+    }
+
+    fn sram() -> SRam {
+        let mut sram = SRam::new();
+        for (slot, name) in [(0, "FIRST"), (5, "SECOND")] {
+            let mut song = SongMemory::new();
+            song.as_mut_slice()[0x100 + slot] = 0x42;
+
+            sram.filesystem
+                .insert_file(
+                    Index::new(slot as u8),
+                    &name.try_into().unwrap(),
+                    slot as u8,
+                    &song,
+                )
+                .unwrap();
+        }
+        sram.filesystem.set_active_file(Some(Index::new(5)));
+
+        sram
+    }
+
+    #[test]
+    fn round_trip() {
+        let folder = folder("round-trip");
+        let original = sram();
+        explode_to(&original, &folder).unwrap();
+
+        assert!(folder.join("00_FIRST.lsdsng").is_file());
+        assert!(folder.join("05_SECOND.lsdsng").is_file());
+
+        let assembled = assemble_from(&folder).unwrap();
+        assert_eq!(
+            assembled.filesystem.active_file(),
+            original.filesystem.active_file()
+        );
+        assert_eq!(
+            assembled.working_memory_song.as_slice(),
+            original.working_memory_song.as_slice()
+        );
+
+        for (a, b) in assembled
+            .filesystem
+            .files()
+            .zip(original.filesystem.files())
+        {
+            assert_eq!(a.is_some(), b.is_some());
+            if let (Some(a), Some(b)) = (a, b) {
+                assert_eq!(a.name().unwrap(), b.name().unwrap());
+                assert_eq!(a.version(), b.version());
+                assert_eq!(
+                    a.decompress().unwrap().as_slice(),
+                    b.decompress().unwrap().as_slice()
+                );
+            }
+        }
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn missing_file() {
+        let folder = folder("missing-file");
+        explode_to(&sram(), &folder).unwrap();
+        std::fs::remove_file(folder.join("05_SECOND.lsdsng")).unwrap();
+
+        let error = assemble_from(&folder).err().unwrap().to_string();
+        assert!(error.contains("05_SECOND.lsdsng"), "{error}");
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn working_memory_hash() {
+        let folder = folder("working-memory-hash");
+        let manifest = explode_to(&sram(), &folder).unwrap();
+
+        let toml = std::fs::read_to_string(folder.join(MANIFEST_FILENAME)).unwrap();
+        assert!(toml.contains("active_file = 5"), "{toml}");
+
+        // The file changed flag doesn't count for the content hash, but the raw file has to match
+        let path = folder.join(&manifest.working_memory.filename);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[0x3FC1] ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+
+        let error = assemble_from(&folder).err().unwrap().to_string();
+        assert!(error.contains("working_memory.raw"), "{error}");
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn slot_collision() {
+        let folder = folder("slot-collision");
+        let mut manifest = explode_to(&sram(), &folder).unwrap();
+        manifest.files[1].slot = 0;
+
+        manifest.to_path(&folder.join(MANIFEST_FILENAME)).unwrap();
+
+        let error = assemble_from(&folder).err().unwrap().to_string();
+        assert!(error.contains("Slot 0"), "{error}");
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn path_traversal() {
+        let parent = folder("path-traversal");
+        let folder = parent.join("exploded");
+        let mut manifest = explode_to(&sram(), &folder).unwrap();
+
+        // A file next to the exploded folder, which the manifest shouldn't be able to reach
+        let outside = parent.join("outside.lsdsng");
+        std::fs::copy(folder.join("00_FIRST.lsdsng"), &outside).unwrap();
+        manifest.files[0].song.filename = "../outside.lsdsng".to_string();
+
+        manifest.to_path(&folder.join(MANIFEST_FILENAME)).unwrap();
+
+        let error = assemble_from(&folder).err().unwrap().to_string();
+        assert!(error.contains("../outside.lsdsng"), "{error}");
+
+        assert!(explode_to(&sram(), &folder).is_err());
+        assert!(outside.is_file());
+
+        std::fs::remove_dir_all(&parent).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+//! The `diff` subcommand
+
+use anyhow::{Context, Result};
+use clap::Args;
+use lsdj::{
+    fs::File,
+    lsdsng::LsdSng,
+    song::{self, SongMemory},
+    sram::{self, diff::SlotChange, SRam, SRamDiff},
+};
+use std::path::{Path, PathBuf};
+
+use crate::utils::has_extension;
+
+/// Arguments for the `diff` subcommand
+#[derive(Args)]
+#[clap(author, version, about = "Compare two .lsdsng's or .sav's and show what differs", long_about = None)]
+pub struct DiffArgs {
+    /// The path to the first song or save file
+    a: PathBuf,
+
+    /// The path to the second song or save file
+    b: PathBuf,
+
+    /// Print the differences as JSON
+    #[clap(short, long)]
+    json: bool,
+}
+
+/// Whether [`diff()`] found any differences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The files are identical
+    Identical,
+
+    /// The files differ
+    Different,
+}
+
+impl Outcome {
+    /// The exit status for the outcome, 0 or 1 like diff(1)
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Identical => 0,
+            Outcome::Different => 1,
+        }
+    }
+}
+
+/// Compare two .lsdsng's or .sav's and show what differs
+///
+/// This doesn't exit the process itself. Like diff(1), the binary exits with
+/// [`Outcome::exit_code()`], and with status 2 if an error is returned.
+pub fn diff(args: &DiffArgs) -> Result<Outcome> {
+    let identical = if has_extension(&args.a, "sav") && has_extension(&args.b, "sav") {
+        diff_srams(args)?
+    } else {
+        diff_songs(args)?
+    };
+
+    Ok(if identical {
+        Outcome::Identical
+    } else {
+        Outcome::Different
+    })
+}
+
+fn diff_songs(args: &DiffArgs) -> Result<bool> {
+    let a = load(&args.a)?;
+    let b = load(&args.b)?;
+
+    let diff = song::diff(&a, &b).context("Could not compare the songs")?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else if diff.is_empty() {
+        println!("The songs are identical");
+    } else {
+        for region in &diff.regions {
+            println!(
+                "{:<12} {} bytes differ",
+                format!("{:?}", region.region),
+                region.bytes_changed
+            );
+        }
+    }
+
+    Ok(diff.is_empty())
+}
+
+fn diff_srams(args: &DiffArgs) -> Result<bool> {
+    let a =
+        SRam::from_path(&args.a).context(format!("Could not load {}", args.a.to_string_lossy()))?;
+    let b =
+        SRam::from_path(&args.b).context(format!("Could not load {}", args.b.to_string_lossy()))?;
+
+    let diff = sram::diff(&a, &b);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else if diff.is_empty() {
+        println!("The save files are identical");
+    } else {
+        print_sram_diff(&diff);
+    }
+
+    Ok(diff.is_empty())
+}
+
+fn print_sram_diff(diff: &SRamDiff) {
+    for slot in &diff.slots {
+        let change = match &slot.change {
+            SlotChange::Added { name } => format!("Added {name}"),
+            SlotChange::Removed { name } => format!("Removed {name}"),
+            SlotChange::Renamed { old, new } => format!("Renamed {old} to {new}"),
+            SlotChange::Replaced { old, new } => format!("Replaced {old} with {new}"),
+            SlotChange::ContentChanged { name } => format!("Changed {name}"),
+            SlotChange::VersionBumped { name, old, new } => {
+                format!("Bumped {name} from v{old:03} to v{new:03}")
+            }
+            SlotChange::Unreadable => "Unreadable".to_string(),
+        };
+
+        println!("{:02} {change}", slot.slot);
+    }
+
+    if diff.working_memory_changed {
+        println!("WM Changed");
+    }
+}
+
+fn load(path: &Path) -> Result<SongMemory> {
+    let lsdsng =
+        LsdSng::from_path(path).context(format!("Could not load {}", path.to_string_lossy()))?;
+
+    lsdsng
+        .decompress()
+        .context(format!("Could not decompress {}", path.to_string_lossy()))
+}
@@ -0,0 +1,228 @@
+//! The `explode` subcommand
+
+use crate::utils::{check_for_overwrite, content_hash, sha256};
+use anyhow::{Context, Error, Result};
+use clap::Args;
+use lsdj::{fs::File, sram::SRam};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{create_dir_all, remove_file},
+    path::{Path, PathBuf},
+};
+
+/// Arguments for the `explode` subcommand
+#[derive(Args)]
+#[clap(author, version, about = "Explode a .sav file into a folder of .lsdsng's and a manifest", long_about = None)]
+pub struct ExplodeArgs {
+    /// The path to the save file to explode
+    path: PathBuf,
+
+    /// The destination folder to place the songs and manifest
+    #[clap(short, long)]
+    output: PathBuf,
+}
+
+/// The filename of the manifest in an exploded folder
+pub const MANIFEST_FILENAME: &str = "manifest.toml";
+
+/// The filename of the working memory song in an exploded folder
+pub const WORKING_MEMORY_FILENAME: &str = "working_memory.raw";
+
+/// Describes the contents of an exploded save file, so it can be assembled again
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The file slot loaded into working memory, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_file: Option<u8>,
+
+    /// The uncompressed working memory song, hashed as the raw bytes of its file
+    pub working_memory: ManifestSong,
+
+    /// The songs in the filesystem, in slot order
+    pub files: Vec<ManifestFile>,
+}
+
+/// A song file referenced by a [`Manifest`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestSong {
+    /// The filename, relative to the manifest
+    pub filename: String,
+
+    /// The SHA-256 hash of the song, as a lowercase hexadecimal string
+    ///
+    /// The .lsdsng's are hashed by their decompressed song (see
+    /// [`lsdj::song::SongMemory::content_hash()`]), so their block layout may differ. The working
+    /// memory song is hashed as the exact bytes of its raw file.
+    pub sha256: String,
+}
+
+/// A file slot in a [`Manifest`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// The file slot the song is stored in
+    pub slot: u8,
+
+    /// The .lsdsng containing the song
+    #[serde(flatten)]
+    pub song: ManifestSong,
+}
+
+/// Explode a .sav file into a folder of .lsdsng's and a manifest
+pub fn explode(args: ExplodeArgs) -> Result<()> {
+    let sram = SRam::from_path(&args.path).context("Reading the SRAM from file failed")?;
+
+    if check_for_overwrite(&args.output.join(MANIFEST_FILENAME))? {
+        let manifest = explode_to(&sram, &args.output)?;
+
+        for file in &manifest.files {
+            println!("{:02} => {}", file.slot, file.song.filename);
+        }
+        println!("WM => {}", manifest.working_memory.filename);
+        println!("Wrote {}", args.output.to_string_lossy());
+    }
+
+    Ok(())
+}
+
+/// Write every file slot and the working memory of an [`SRam`] to a folder, with a manifest
+///
+/// Each file slot becomes an `NN_NAME.lsdsng`, the working memory song is written as raw bytes
+/// and everything is listed in `manifest.toml`. Files listed in a manifest already in the folder
+/// are removed first, so the folder keeps mirroring the save file when exploded again.
+pub fn explode_to(sram: &SRam, folder: &Path) -> Result<Manifest> {
+    create_dir_all(folder).context("Could not create output directory")?;
+    remove_previous(folder)?;
+
+    let mut files = Vec::new();
+    for (slot, file) in sram.filesystem.occupied_files() {
+        let slot = u8::from(slot);
+        let lsdsng = file
+            .lsdsng()
+            .context("Could not create an LsdSng from an SRAM file slot")?;
+        let song = lsdsng
+            .decompress()
+            .context(format!("Could not decompress the song in slot {slot}"))?;
+
+        let filename = format!("{slot:02}_{}.lsdsng", lsdsng.name()?.as_str());
+        lsdsng
+            .to_path(folder.join(&filename))
+            .context(format!("Could not write {filename}"))?;
+
+        files.push(ManifestFile {
+            slot,
+            song: ManifestSong {
+                filename,
+                sha256: content_hash(&song),
+            },
+        });
+    }
+
+    let bytes = sram.working_memory_song.as_slice();
+    let filename = WORKING_MEMORY_FILENAME.to_string();
+    std::fs::write(folder.join(&filename), bytes)
+        .context("Could not write the working memory song")?;
+
+    let manifest = Manifest {
+        active_file: sram.filesystem.active_file().map(u8::from),
+        working_memory: ManifestSong {
+            filename,
+            sha256: sha256(bytes),
+        },
+        files,
+    };
+
+    manifest.to_path(&folder.join(MANIFEST_FILENAME))?;
+
+    Ok(manifest)
+}
+
+/// Remove the songs listed in a previous manifest in the folder, if there is one
+fn remove_previous(folder: &Path) -> Result<()> {
+    let path = folder.join(MANIFEST_FILENAME);
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let manifest = Manifest::from_path(&path)?;
+    for song in manifest.songs() {
+        let path = folder.join(&song.filename);
+        if path.exists() {
+            remove_file(&path).context(format!("Could not remove {}", song.filename))?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Manifest {
+    /// Read a manifest from a TOML file
+    ///
+    /// Every filename has to be a plain filename inside the manifest's folder. Manifests tend to
+    /// be shared, and the files they list are read and removed, so paths like `../song.lsdsng`
+    /// or `/song.lsdsng` are rejected.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let toml = std::fs::read_to_string(path)
+            .context(format!("Could not open {}", path.to_string_lossy()))?;
+        let manifest: Self =
+            toml::from_str(&toml).context(format!("Could not parse {}", path.to_string_lossy()))?;
+
+        for song in manifest.songs() {
+            if !is_plain_filename(&song.filename) {
+                return Err(Error::msg(format!(
+                    "{} refers to {}, which isn't a filename inside its folder",
+                    path.to_string_lossy(),
+                    song.filename
+                )));
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Write the manifest to a TOML file
+    pub fn to_path(&self, path: &Path) -> Result<()> {
+        let toml = toml::to_string_pretty(self).context("Could not serialize the manifest")?;
+        std::fs::write(path, toml).context(format!("Could not write {}", path.to_string_lossy()))
+    }
+
+    /// The songs in the filesystem, followed by the working memory song
+    pub fn songs(&self) -> impl Iterator<Item = &ManifestSong> {
+        self.files
+            .iter()
+            .map(|file| &file.song)
+            .chain([&self.working_memory])
+    }
+}
+
+/// Is a filename a single path component, without separators or special components?
+fn is_plain_filename(filename: &str) -> bool {
+    let path = Path::new(filename);
+    !filename.contains(['/', '\\'])
+        && !path.is_absolute()
+        && path.file_name() == Some(filename.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_filenames() {
+        assert!(is_plain_filename("00_SONG.lsdsng"));
+        assert!(is_plain_filename(WORKING_MEMORY_FILENAME));
+
+        for filename in [
+            "",
+            ".",
+            "..",
+            "../song.lsdsng",
+            "../../x",
+            "songs/song.lsdsng",
+            "songs\\song.lsdsng",
+            "/song.lsdsng",
+            "/",
+        ] {
+            assert!(!is_plain_filename(filename), "{filename}");
+        }
+    }
+}
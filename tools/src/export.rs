@@ -1,13 +1,15 @@
 //! The `export` subcommand
 
-use crate::utils::check_for_overwrite;
-use anyhow::{Context, Result};
+use crate::utils::{check_for_overwrite, content_hash};
+use anyhow::{Context, Error, Result};
 use clap::Args;
 use lsdj::{
     fs::{File, Filesystem},
+    lsdsng::LsdSng,
     sram::SRam,
 };
-use std::{env::current_dir, fs::create_dir_all};
+use serde::Serialize;
+use std::{env::current_dir, fs::create_dir_all, path::Path};
 
 use std::path::PathBuf;
 
@@ -18,8 +20,12 @@ pub struct ExportArgs {
     /// The path to the save file to export from
     path: PathBuf,
 
-    /// Indices of the songs that should be exported. No indices means all songs.
-    index: Vec<usize>,
+    /// Indices, names or glob patterns (with * and ?) of the songs that should be exported. No songs means all songs.
+    song: Vec<String>,
+
+    /// Only select songs by index, even if a song is named like a number
+    #[clap(short, long)]
+    index_only: bool,
 
     /// The destination folder to place the songs
     #[clap(short, long)]
@@ -36,63 +42,290 @@ pub struct ExportArgs {
     /// Use decimal version numbers, instead of hexadecimal
     #[clap(short, long)]
     decimal: bool,
+
+    /// Also export the song in working memory (prefixed with WM_)
+    #[clap(short, long)]
+    working_memory: bool,
+
+    /// Recompress the songs, instead of copying the compressed blocks as-is
+    #[clap(short, long)]
+    normalize: bool,
+
+    /// Write a JSON manifest of the exported songs and errors to this path
+    #[clap(short, long, visible_alias = "json")]
+    manifest: Option<PathBuf>,
+
+    /// Don't print a line for every exported song
+    #[clap(short, long)]
+    quiet: bool,
+}
+
+/// The JSON manifest written by [`export()`]
+#[derive(Default, Serialize)]
+struct Manifest {
+    /// The songs that were exported
+    songs: Vec<ManifestEntry>,
+
+    /// The songs that couldn't be exported
+    errors: Vec<ManifestError>,
+}
+
+/// A single song in the export manifest
+#[derive(Serialize)]
+struct ManifestEntry {
+    /// The file slot the song was stored in, or none for the working memory song
+    slot: Option<usize>,
+    name: String,
+    version: u8,
+    format_version: u8,
+    blocks: usize,
+    filename: String,
+
+    /// The SHA-256 hash of the song contents, which ignores whether it was changed since saving
+    sha256: String,
+
+    /// Whether the song wasn't written because the user chose not to overwrite an existing file
+    skipped: bool,
+}
+
+/// A song in the export manifest that couldn't be exported
+#[derive(Serialize)]
+struct ManifestError {
+    /// The file slot the song was stored in, or none for the working memory song
+    slot: Option<usize>,
+
+    /// Why the song couldn't be exported
+    error: String,
 }
 
 /// Export .lsdsng's from .sav files
-pub fn export(mut args: ExportArgs) -> Result<()> {
+pub fn export(args: ExportArgs) -> Result<()> {
     let sram = SRam::from_path(&args.path).context("Reading the SRAM from file failed")?;
+    let selected = select(&sram, &args.song, args.index_only)?;
 
-    if args.index.is_empty() {
-        args.index = (0..Filesystem::FILES_CAPACITY).collect();
-    }
-
-    let folder = match args.output {
-        Some(folder) => folder,
+    let folder = match &args.output {
+        Some(folder) => folder.clone(),
         None => current_dir().context("Could not fetch current working directory")?,
     };
     create_dir_all(&folder).context("Could not create output directory")?;
 
-    for (index, file) in sram.filesystem.files().enumerate() {
-        if !args.index.contains(&index) {
+    let mut manifest = Manifest::default();
+    let mut push = |result: Result<ManifestEntry>, label: &str, slot: Option<usize>| match result {
+        Ok(entry) => manifest.songs.push(entry),
+        Err(error) => {
+            // Carry on with the other songs, a broken song shouldn't abort the whole export
+            eprintln!("{label}. ! {error:#}");
+            manifest.errors.push(ManifestError {
+                slot,
+                error: format!("{error:#}"),
+            });
+        }
+    };
+
+    for (index, file) in sram.filesystem.occupied_files() {
+        let index = u8::from(index) as usize;
+        if !selected.contains(&index) {
             continue;
         }
 
-        if let Some(file) = file {
-            let lsdsng = file
-                .lsdsng()
-                .context("Could not create an LsdSng from an SRAM file slot")?;
-
-            let mut filename = String::new();
-            if args.output_pos {
-                filename.push_str(&format!("{:02}_", index));
-            }
-
-            let name = lsdsng.name()?;
-            filename.push_str(name.as_str());
-            if args.output_version {
-                if args.decimal {
-                    filename.push_str(&format!("_v{:03}", lsdsng.version()));
-                } else {
-                    filename.push_str(&format!("_v{:02X}", lsdsng.version()));
+        let label = format!("{:02}", index);
+        let result = file
+            .lsdsng()
+            .context("Could not create an LsdSng from an SRAM file slot")
+            .and_then(|lsdsng| {
+                let mut filename = String::new();
+                if args.output_pos {
+                    filename.push_str(&format!("{:02}_", index));
                 }
-            }
-
-            let path = folder.join(filename).with_extension("lsdsng");
-
-            if check_for_overwrite(&path)? {
-                lsdsng
-                    .to_path(&path)
-                    .context("Could not write lsdsng to file")?;
-
-                println!(
-                    "{:02}. {:8} => {}",
-                    index,
-                    name.as_str(),
-                    path.file_name().unwrap().to_string_lossy()
-                );
-            }
-        }
+
+                write(&args, &folder, lsdsng, filename, &label, Some(index))
+            });
+
+        push(result, &label, Some(index));
+    }
+
+    if args.working_memory {
+        let result = sram
+            .working_memory_lsdsng()
+            .context("Could not create an LsdSng from the working memory song")
+            .and_then(|lsdsng| write(&args, &folder, lsdsng, "WM_".to_string(), "WM", None));
+
+        push(result, "WM", None);
+    }
+
+    if let Some(path) = &args.manifest {
+        let file = std::fs::File::create(path).context("Could not create the manifest file")?;
+        serde_json::to_writer_pretty(file, &manifest).context("Could not write the manifest")?;
     }
 
     Ok(())
 }
+
+/// Find the slots of the songs to export
+///
+/// Every selector is first matched (case-insensitively) against the song names, and only
+/// treated as an index when no name matches. No selectors means every slot.
+fn select(sram: &SRam, selectors: &[String], index_only: bool) -> Result<Vec<usize>> {
+    if selectors.is_empty() {
+        return Ok((0..Filesystem::FILES_CAPACITY).collect());
+    }
+
+    let names: Vec<(usize, String)> = sram
+        .filesystem
+        .occupied_files()
+        .filter_map(|(index, file)| Some((u8::from(index) as usize, file.name().ok()?.to_string())))
+        .collect();
+
+    let mut selected = Vec::new();
+    for selector in selectors {
+        let by_name: Vec<usize> = if index_only {
+            Vec::new()
+        } else {
+            let pattern = selector.to_ascii_uppercase();
+            names
+                .iter()
+                .filter(|(_, name)| glob(pattern.as_bytes(), name.as_bytes()))
+                .map(|(index, _)| *index)
+                .collect()
+        };
+
+        if !by_name.is_empty() {
+            selected.extend(by_name);
+        } else if let Ok(index) = selector.parse::<usize>() {
+            selected.push(index);
+        } else if index_only {
+            return Err(Error::msg(format!("{selector} is not a valid index")));
+        } else {
+            let available: Vec<&str> = names.iter().map(|(_, name)| name.as_str()).collect();
+            return Err(Error::msg(format!(
+                "No song matches {selector}, available songs are: {}",
+                available.join(", ")
+            )));
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Does a name match a pattern, where `*` matches any run of characters and `?` a single one?
+fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (Some((b'*', rest)), _) => {
+            glob(rest, name) || (!name.is_empty() && glob(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name))) => glob(rest, name),
+        (Some((expected, rest)), Some((actual, name))) => expected == actual && glob(rest, name),
+        (Some(_), None) => false,
+        (None, _) => name.is_empty(),
+    }
+}
+
+/// Write a single song to disk, but only if it decompresses
+fn write(
+    args: &ExportArgs,
+    folder: &Path,
+    mut lsdsng: LsdSng,
+    mut filename: String,
+    label: &str,
+    slot: Option<usize>,
+) -> Result<ManifestEntry> {
+    if args.normalize {
+        lsdsng.normalize().context("Could not normalize the song")?;
+    }
+
+    let song = lsdsng
+        .decompress()
+        .context("Could not decompress the song")?;
+
+    let name = lsdsng.name()?;
+    filename.push_str(name.as_str());
+    if args.output_version {
+        if args.decimal {
+            filename.push_str(&format!("_v{:03}", lsdsng.version()));
+        } else {
+            filename.push_str(&format!("_v{:02X}", lsdsng.version()));
+        }
+    }
+
+    let path = folder.join(filename).with_extension("lsdsng");
+    let filename = path.file_name().unwrap().to_string_lossy().to_string();
+
+    let skipped = !check_for_overwrite(&path)?;
+    if !skipped {
+        lsdsng
+            .to_path(&path)
+            .context("Could not write lsdsng to file")?;
+
+        if !args.quiet {
+            println!("{}. {:8} => {}", label, name, filename);
+        }
+    }
+
+    Ok(ManifestEntry {
+        slot,
+        name: name.to_string(),
+        version: lsdsng.version(),
+        format_version: song.format_version(),
+        blocks: lsdsng.block_count(),
+        filename,
+        sha256: content_hash(&song),
+        skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsdj::{fs::Index, song::SongMemory};
+
+    fn sram() -> SRam {
+        let mut sram = SRam::new();
+        for (slot, name) in [(0, "GHOSTS"), (3, "DNTSWEAT"), (4, "DNTSTOP"), (7, "12")] {
+            sram.filesystem
+                .insert_file(
+                    Index::new(slot),
+                    &name.try_into().unwrap(),
+                    0,
+                    &SongMemory::new(),
+                )
+                .unwrap();
+        }
+        sram
+    }
+
+    #[test]
+    fn glob() {
+        assert!(super::glob(b"HONEY", b"HONEY"));
+        assert!(!super::glob(b"HONEY", b"HONEYS"));
+        assert!(super::glob(b"HON*", b"HONEY"));
+        assert!(super::glob(b"*EY", b"HONEY"));
+        assert!(super::glob(b"H*N*Y", b"HONEY"));
+        assert!(super::glob(b"*", b""));
+        assert!(super::glob(b"H?NEY", b"HONEY"));
+        assert!(!super::glob(b"H?", b"H"));
+    }
+
+    #[test]
+    fn select() {
+        let sram = sram();
+        let select = |selectors: &[&str], index_only| {
+            let selectors: Vec<String> = selectors.iter().map(|s| s.to_string()).collect();
+            super::select(&sram, &selectors, index_only)
+        };
+
+        assert_eq!(
+            select(&[], false).unwrap().len(),
+            Filesystem::FILES_CAPACITY
+        );
+        assert_eq!(select(&["ghosts", "DNT*"], false).unwrap(), [0, 3, 4]);
+        assert_eq!(select(&["DNTS?OP", "5"], false).unwrap(), [4, 5]);
+
+        // Names are preferred over indices, unless asked otherwise
+        assert_eq!(select(&["12"], false).unwrap(), [7]);
+        assert_eq!(select(&["12"], true).unwrap(), [12]);
+        assert!(select(&["GHOSTS"], true).is_err());
+
+        let error = select(&["HONEY"], false).unwrap_err().to_string();
+        assert!(error.contains("GHOSTS, DNTSWEAT, DNTSTOP, 12"), "{error}");
+    }
+}
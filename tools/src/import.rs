@@ -1,15 +1,11 @@
 //! The `import` subcommand
 
-use crate::utils::{check_for_overwrite, has_extension, iter_files};
+use crate::utils::{check_for_overwrite, iter_files};
 use anyhow::{Context, Error, Result};
-use clap::Args;
+use clap::{Args, ValueEnum};
 use lsdj::{
-    fs::{File, Filesystem, Index},
-    lsdsng::LsdSng,
-    name::Name,
-    serde::CompressBlockError,
-    song::SongMemory,
-    sram::SRam,
+    fs::{Filesystem, Index},
+    sram::{build::BuildError, read_files, BuildOptions, InsertError, Progress, SRam, Source},
 };
 use std::path::PathBuf;
 
@@ -23,61 +19,69 @@ pub struct ImportArgs {
     /// The output path
     #[clap(short, long)]
     output: PathBuf,
-}
-
-/// Import .lsdsng's into a .sav file
-pub fn import(args: ImportArgs) -> Result<()> {
-    let mut index = 0u8;
-    let mut sram = SRam::new();
-
-    for entry in iter_files(&args.song, true, &["lsdsng", "sav"]) {
-        let path = entry.path();
 
-        if index == Filesystem::FILES_CAPACITY as u8 {
-            return Err(Error::msg(
-                "Reached the maximum file limit. Aborting import.",
-            ));
-        }
-
-        if has_extension(path, "lsdsng") {
-            let lsdsng = LsdSng::from_path(path).context("Could not load {path}")?;
-            let song = lsdsng
-                .decompress()
-                .context(format!("Could not decompress {}", path.to_string_lossy()))?;
-
-            insert(&mut sram, index, &lsdsng.name()?, lsdsng.version(), &song)?;
-
-            println!("{:02} => {}", index, path.to_string_lossy());
+    /// An existing save file to import into, instead of starting from an empty one
+    #[clap(short, long)]
+    base: Option<PathBuf>,
 
-            index += 1;
-        } else if has_extension(path, "sav") {
-            let sav = SRam::from_path(path)
-                .context(format!("Could not open {}", path.to_string_lossy()))?;
+    /// The slot to place each song in, in order. Songs without a slot go into the first free one
+    #[clap(short, long)]
+    slot: Vec<usize>,
 
-            for (source_index, file) in sav.filesystem.files().enumerate() {
-                if let Some(file) = file {
-                    let song = file.decompress().context(format!(
-                        "Could not decompress file {} from {}",
-                        source_index,
-                        path.to_string_lossy()
-                    ))?;
+    /// Replace songs in slots that are already occupied
+    #[clap(short, long)]
+    force: bool,
 
-                    let name = file.name()?;
+    /// Skip songs that are already in the save, comparing by contents (the default) or name
+    #[clap(short, long, value_enum, num_args = 0..=1, default_missing_value = "content", require_equals = true)]
+    dedupe: Option<Dedupe>,
+}
 
-                    insert(&mut sram, index, &name, file.version(), &song)?;
+/// How [`import()`] recognizes songs that are already in the save
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Dedupe {
+    /// Songs with identical contents, regardless of name or version
+    Content,
 
-                    println!(
-                        "{:02} => {} - {}",
-                        index,
-                        path.to_string_lossy(),
-                        name.as_str(),
-                    );
+    /// Songs with the same name, regardless of contents
+    Name,
+}
 
-                    index += 1;
-                }
-            }
+impl From<Dedupe> for lsdj::sram::Dedupe {
+    fn from(dedupe: Dedupe) -> Self {
+        match dedupe {
+            Dedupe::Content => Self::Content,
+            Dedupe::Name => Self::Name,
         }
     }
+}
+
+/// Import .lsdsng's into a .sav file
+pub fn import(args: ImportArgs) -> Result<()> {
+    let options = BuildOptions {
+        slots: slots(&args.slot)?,
+        force: args.force,
+        dedupe: args.dedupe.map(Into::into),
+    };
+
+    let mut sram = match &args.base {
+        Some(path) => {
+            SRam::from_path(path).context(format!("Could not open {}", path.to_string_lossy()))?
+        }
+        None => SRam::new(),
+    };
+
+    let paths: Vec<PathBuf> = iter_files(&args.song, true, &["lsdsng", "lsdprj", "sav"])
+        .map(|entry| entry.into_path())
+        .collect();
+
+    // A song that can't be read or inserted is reported, but doesn't stop the import
+    sram.insert_songs(read_files(&paths), &options, |progress| {
+        match describe(progress, args.dedupe) {
+            Ok(line) => println!("{line}"),
+            Err(line) => eprintln!("{line}"),
+        }
+    });
 
     if check_for_overwrite(&args.output)? {
         sram.to_path(&args.output).context(format!(
@@ -91,23 +95,143 @@ pub fn import(args: ImportArgs) -> Result<()> {
     Ok(())
 }
 
-fn insert(
-    sram: &mut SRam,
-    index: u8,
-    name: &Name<8>,
-    version: u8,
-    song: &SongMemory,
-) -> Result<()> {
-    match sram
-        .filesystem
-        .insert_file(Index::new(index), name, version, song)
-    {
-        Err(CompressBlockError::NoBlockLeft) => {
-            Err(Error::msg("Ran out of space in the SRAM memory"))
+/// Turn the slots requested by the user into file indices
+fn slots(slots: &[usize]) -> Result<Vec<Index>> {
+    slots
+        .iter()
+        .map(|&slot| {
+            if slot < Filesystem::FILES_CAPACITY {
+                Ok(Index::new(slot as u8))
+            } else {
+                Err(Error::msg(format!(
+                    "Slot {slot} is out of range, there are only {} slots",
+                    Filesystem::FILES_CAPACITY
+                )))
+            }
+        })
+        .collect()
+}
+
+/// Describe what happened to a song, as a line for stdout or (for failures) stderr
+fn describe(progress: Progress, dedupe: Option<Dedupe>) -> Result<String, String> {
+    match progress {
+        Progress::Inserted {
+            source,
+            name,
+            index,
+        } => Ok(format!(
+            "{index:02} => {}",
+            label(&source, Some(name.as_str()))
+        )),
+        Progress::Skipped {
+            source,
+            name,
+            index,
+            existing,
+        } => {
+            let reason = match dedupe {
+                Some(Dedupe::Name) => "same name as",
+                _ => "same contents as",
+            };
+            let existing = existing.as_ref().map_or("????????", |name| name.as_str());
+            Ok(format!(
+                "-- => {} (skipped, {reason} {index:02}. {existing})",
+                label(&source, Some(name.as_str()))
+            ))
+        }
+        Progress::Failed { source, error } => Err(format!(
+            "!! => {}: {}",
+            label(&source, None),
+            message(error)
+        )),
+    }
+}
+
+/// Label a song by the file it came from, and its name if it was one of many in a .sav
+fn label(source: &Source, name: Option<&str>) -> String {
+    let path = source.path.to_string_lossy();
+    match (source.slot, name) {
+        (Some(_), Some(name)) => format!("{path} - {name}"),
+        (Some(slot), None) => format!("{path} - {slot:02}"),
+        (None, _) => path.to_string(),
+    }
+}
+
+/// Explain why a song couldn't be imported
+fn message(error: BuildError) -> String {
+    match error {
+        BuildError::Insert(InsertError::FilesystemFull) => {
+            "Reached the maximum file limit".to_string()
+        }
+        BuildError::Insert(InsertError::NotEnoughBlocks { needed, free }) => {
+            format!("Ran out of space in the SRAM memory: song needs {needed} blocks, {free} free")
         }
-        result => {
-            result.context("Could not insert song")?;
-            Ok(())
+        BuildError::SlotOccupied(index) => {
+            format!("Slot {index} is already occupied (use --force to replace it)")
         }
+        error => format!("{:#}", Error::new(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(slot: Option<u8>) -> Source {
+        Source {
+            path: PathBuf::from("songs.sav"),
+            slot,
+        }
+    }
+
+    #[test]
+    fn slots() {
+        assert_eq!(
+            super::slots(&[5, 0]).unwrap(),
+            [Index::new(5), Index::new(0)]
+        );
+
+        let error = super::slots(&[0, 32]).unwrap_err();
+        assert!(error.to_string().contains("out of range"), "{error}");
+    }
+
+    #[test]
+    fn describe() {
+        let inserted = Progress::Inserted {
+            source: source(Some(3)),
+            name: "NEW".try_into().unwrap(),
+            index: Index::new(1),
+        };
+        assert_eq!(
+            super::describe(inserted, None),
+            Ok("01 => songs.sav - NEW".to_string())
+        );
+
+        let skipped = Progress::Skipped {
+            source: source(None),
+            name: "NEW".try_into().unwrap(),
+            index: Index::new(0),
+            existing: None,
+        };
+        assert_eq!(
+            super::describe(skipped, Some(Dedupe::Name)),
+            Ok("-- => songs.sav (skipped, same name as 00. ????????)".to_string())
+        );
+
+        let full = Progress::Failed {
+            source: source(Some(3)),
+            error: BuildError::Insert(InsertError::FilesystemFull),
+        };
+        assert_eq!(
+            super::describe(full, None),
+            Err("!! => songs.sav - 03: Reached the maximum file limit".to_string())
+        );
+
+        let occupied = Progress::Failed {
+            source: source(None),
+            error: BuildError::SlotOccupied(Index::new(5)),
+        };
+        let line = super::describe(occupied, None).unwrap_err();
+        assert!(line.contains("use --force"), "{line}");
     }
 }
@@ -2,11 +2,13 @@
 
 use crate::utils::iter_files;
 use anyhow::{Context, Result};
-use clap::Args;
+use clap::{Args, ValueEnum};
 use lsdj::{
-    fs::{File, Filesystem},
-    lsdsng::LsdSng,
-    sram::SRam,
+    fs::{self, File, FileSummary, Filesystem},
+    lsdprj::LsdPrj,
+    lsdsng::{self, LsdSng},
+    song::Validation,
+    sram::{FromBytesError, SRam},
 };
 use std::path::{Path, PathBuf};
 
@@ -20,44 +22,112 @@ pub struct InspectArgs {
     /// Search the folder recursively
     #[clap(short, long)]
     recursive: bool,
+
+    /// Check save files for filesystem inconsistencies
+    #[clap(short, long)]
+    check: bool,
+
+    /// Show the number of blocks each song takes up, and the share of the save's capacity
+    #[clap(short, long)]
+    blocks: bool,
+
+    /// The order in which the songs of a save file are listed
+    #[clap(short, long, value_enum, default_value_t = Sort::Index)]
+    sort: Sort,
+
+    /// Finish save files with the number of blocks used and free
+    #[clap(short, long)]
+    total: bool,
+}
+
+/// The order in which [`inspect()`] lists songs
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Sort {
+    /// By position in the save file
+    Index,
+
+    /// Alphabetically by name, with unnamed songs last
+    Name,
+
+    /// By the number of blocks used, largest first
+    Blocks,
+
+    /// By version, lowest first
+    Version,
 }
 
 /// Inspect LSDJ .sav and .lsdsng files, or even entire directories for their contents
 pub fn inspect(args: &InspectArgs) -> Result<()> {
-    let paths: Vec<_> = iter_files(&args.path, args.recursive, &["sav", "lsdsng"])
+    let paths: Vec<_> = iter_files(&args.path, args.recursive, &["sav", "lsdsng", "lsdprj"])
         .map(|entry| entry.path().to_owned())
         .collect();
 
     if let Some((last, rest)) = paths.split_last() {
         for path in rest {
-            print(path)?;
+            print(path, args)?;
             println!();
         }
 
-        print(last)?;
+        print(last, args)?;
     }
 
     Ok(())
 }
 
-fn print(path: &Path) -> Result<()> {
+fn print(path: &Path, args: &InspectArgs) -> Result<()> {
     println!("{}", path.to_string_lossy());
 
     match path.extension().and_then(|str| str.to_str()) {
         Some("sav") => {
-            let sram = SRam::from_path(path).context("Reading the SRAM from file failed")?;
+            let file = std::fs::File::open(path).context("Opening the SRAM file failed")?;
+            let mut banks = SRam::from_reader_multi(file, Validation::Lenient)
+                .context("Reading the SRAM from file failed")?;
 
-            print_mem(&sram);
+            // Trailing bytes that don't make up a full bank are padding (e.g. RTC data), not a bank
+            if banks.len() > 1 && matches!(banks.last(), Some(Err(FromBytesError::TooShort { .. })))
+            {
+                banks.pop();
+            }
 
-            for (index, file) in sram.filesystem.files().enumerate() {
-                if let Some(file) = file {
-                    print_file(index, &file)?;
+            // Most save files contain just one bank, so only label them when there are more
+            if banks.len() <= 1 {
+                let sram = banks.pop().unwrap_or_else(|| SRam::from_bytes(&[]));
+                print_sram(&sram.context("Reading the SRAM from file failed")?, args)?;
+            } else {
+                for (bank, sram) in banks.into_iter().enumerate() {
+                    println!("{}[bank={bank}]", path.to_string_lossy());
+                    match sram {
+                        Ok(sram) => print_sram(&sram, args)?,
+                        Err(error) => println!("  ! {error}"),
+                    }
                 }
             }
         }
         Some("lsdsng") => {
-            let lsdsng = LsdSng::from_path(path).context("Reading the LsdSng from file failed")?;
-            print_file(0, &lsdsng)?;
+            let options = lsdsng::ReadOptions {
+                allow_partial_block: true,
+            };
+            let lsdsng = LsdSng::from_path_with_options(path, options)
+                .context("Reading the LsdSng from file failed")?;
+            print_file(
+                &FileSummary::new(0, &lsdsng, lsdsng.block_count()),
+                &lsdsng,
+                args.blocks,
+            );
+
+            if lsdsng.has_partial_block() {
+                println!("      ? The file ends halfway through its last block");
+            }
+        }
+        Some("lsdprj") => {
+            let lsdsng = LsdPrj::from_path(path)
+                .map(LsdSng::from)
+                .context("Reading the LsdPrj from file failed")?;
+            print_file(
+                &FileSummary::new(0, &lsdsng, lsdsng.block_count()),
+                &lsdsng,
+                args.blocks,
+            );
         }
         _ => (),
     }
@@ -65,6 +135,58 @@ fn print(path: &Path) -> Result<()> {
     Ok(())
 }
 
+fn print_sram(sram: &SRam, args: &InspectArgs) -> Result<()> {
+    print_mem(sram);
+
+    let problems = if args.check {
+        sram.filesystem.check()
+    } else {
+        Vec::new()
+    };
+
+    // Files in an inconsistent filesystem might not decompress at all
+    if problems.is_empty() {
+        let mut files: Vec<_> = fs::summary(&sram.filesystem)
+            .into_iter()
+            .zip(sram.filesystem.occupied_files().map(|(_, file)| file))
+            .collect();
+        sort(&mut files, args.sort);
+
+        for (summary, file) in &files {
+            print_file(summary, file, args.blocks);
+        }
+    } else {
+        for problem in problems {
+            println!("  ! {problem}");
+        }
+    }
+
+    if args.total {
+        println!(
+            "Total {:03} used | {:03} free",
+            sram.filesystem.blocks_used_count(),
+            sram.filesystem.blocks_free_count()
+        );
+    }
+
+    Ok(())
+}
+
+fn sort<F>(files: &mut [(FileSummary, F)], sort: Sort) {
+    match sort {
+        Sort::Index => files.sort_by_key(|(summary, _)| summary.index),
+        Sort::Name => files.sort_by(|(a, _), (b, _)| {
+            let key = |summary: &FileSummary| {
+                let name = summary.name.as_ref().map(|name| name.as_str().to_owned());
+                (name.is_none(), name)
+            };
+            key(a).cmp(&key(b))
+        }),
+        Sort::Blocks => files.sort_by_key(|(summary, _)| std::cmp::Reverse(summary.block_count)),
+        Sort::Version => files.sort_by_key(|(summary, _)| summary.version),
+    }
+}
+
 fn print_mem(sram: &SRam) {
     const BAR_LEN: usize = 24;
     let blocks = sram.filesystem.blocks_used_count();
@@ -77,17 +199,74 @@ fn print_mem(sram: &SRam) {
         "=".repeat(bar),
         " ".repeat(BAR_LEN - bar)
     );
+
+    let orphaned = sram.filesystem.blocks_orphaned_count();
+    if orphaned > 0 {
+        println!("  ? {orphaned} orphaned blocks");
+    }
 }
 
-fn print_file(index: usize, file: &impl File) -> Result<()> {
-    let song = file.decompress().context("Could not decompress file")?;
+fn print_file(summary: &FileSummary, file: &impl File, blocks: bool) {
+    let song = file.decompress_with_validation(Validation::Lenient).ok();
 
-    println!(
-        "{index:>3} | {:<8} | v{:03} | f{:03}",
-        format!("{}", file.name().context("Could not parse the file name")?),
-        file.version(),
-        song.format_version()
-    );
+    let tempo = match song.as_ref().map(|song| song.tempo()) {
+        Some(Ok(tempo)) => format!("{tempo:03}"),
+        _ => "---".to_string(),
+    };
 
-    Ok(())
+    if blocks {
+        println!(
+            "{summary} | t{tempo} | b{:03} {:>3}%",
+            summary.block_count,
+            summary.block_count * 100 / Filesystem::BLOCKS_CAPACITY
+        );
+    } else {
+        println!("{summary} | t{tempo}");
+    }
+
+    if let Some(song) = song.filter(|song| !song.has_initialization_check()) {
+        println!(
+            "      ? The initialization check failed (format version {}), this might be a song from an older LSDJ version",
+            song.format()
+        );
+    }
+
+    if let Some(error) = &summary.error {
+        println!("      ! {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(index: u8, name: Option<&str>, version: u8, block_count: usize) -> FileSummary {
+        FileSummary {
+            index,
+            name: name.map(|name| name.try_into().unwrap()),
+            version,
+            format_version: None,
+            block_count,
+            error: None,
+        }
+    }
+
+    fn sorted(sort: Sort) -> Vec<u8> {
+        let mut files = vec![
+            (summary(0, Some("ZEBRA"), 3, 2), ()),
+            (summary(1, None, 1, 5), ()),
+            (summary(2, Some("APE"), 2, 1), ()),
+        ];
+        super::sort(&mut files, sort);
+
+        files.iter().map(|(summary, _)| summary.index).collect()
+    }
+
+    #[test]
+    fn sort() {
+        assert_eq!(sorted(Sort::Index), [0, 1, 2]);
+        assert_eq!(sorted(Sort::Name), [2, 0, 1]);
+        assert_eq!(sorted(Sort::Blocks), [1, 0, 2]);
+        assert_eq!(sorted(Sort::Version), [1, 2, 0]);
+    }
 }
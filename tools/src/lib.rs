@@ -16,9 +16,13 @@
 //!     <PATH>...    The path(s) to inspect
 //!
 //! OPTIONS:
-//!     -h, --help         Print help information
-//!     -r, --recursive    Search the folder recursively
-//!     -V, --version      Print version information
+//!     -b, --blocks         Show the number of blocks each song takes up, and the share of the save's capacity
+//!     -c, --check          Check save files for filesystem inconsistencies
+//!     -h, --help           Print help information
+//!     -r, --recursive      Search the folder recursively
+//!     -s, --sort <SORT>    The order in which the songs of a save file are listed [default: index] [possible values: index, name, blocks, version]
+//!     -t, --total          Finish save files with the number of blocks used and free
+//!     -V, --version        Print version information
 //! ```
 //!
 //! ### Example
@@ -26,19 +30,29 @@
 //! ```console
 //! 4ntler@mbp > lsdj-tools inspect bangers.sav
 //! Mem 144/192    [==================      ]
-//!   0 | YOKAI    | v027 | f005
-//!   1 | ASPHALT  | v019 | f005
-//!   2 | NEWSHOES | v014 | f005
-//!   3 | FUNGAL   | v019 | f005
-//!   4 | LOGCBN   | v015 | f005
-//!   5 | NOSTALGA | v031 | f005
-//!   6 | GJITSU   | v026 | f005
-//!   7 | PRISTINE | v016 | f005
-//!   8 | KALEIDO  | v024 | f005
-//!   9 | CACTUAR  | v046 | f005
-//!  10 | DODGBALL | v018 | f005
-//!  11 | DNTSWEAT | v025 | f005
-//!  12 | HONEY    | v031 | f005
+//!   0 | YOKAI    | v027 | f005 | t---
+//!   1 | ASPHALT  | v019 | f005 | t---
+//!   2 | NEWSHOES | v014 | f005 | t---
+//!   3 | FUNGAL   | v019 | f005 | t---
+//!   4 | LOGCBN   | v015 | f005 | t---
+//!   5 | NOSTALGA | v031 | f005 | t---
+//!   6 | GJITSU   | v026 | f005 | t---
+//!   7 | PRISTINE | v016 | f005 | t---
+//!   8 | KALEIDO  | v024 | f005 | t---
+//!   9 | CACTUAR  | v046 | f005 | t---
+//!  10 | DODGBALL | v018 | f005 | t---
+//!  11 | DNTSWEAT | v025 | f005 | t---
+//!  12 | HONEY    | v031 | f005 | t---
+//! ```
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools inspect -bt --sort blocks bangers.sav
+//! Mem 144/192    [==================      ]
+//!   9 | CACTUAR  | v046 | f005 | t--- | b014  7%
+//!  12 | HONEY    | v031 | f005 | t--- | b014  7%
+//!   3 | FUNGAL   | v019 | f005 | t--- | b011  5%
+//! ...
+//! Total 144 used | 048 free
 //! ```
 //!
 //! ## Export
@@ -47,19 +61,24 @@
 //!
 //! ```console
 //! USAGE:
-//!     lsdj-tools export [OPTIONS] <PATH> [INDEX]...
+//!     lsdj-tools export [OPTIONS] <PATH> [SONG]...
 //!
 //! ARGS:
-//!     <PATH>        The path to the save file to export from
-//!     <INDEX>...    Indices of the songs that should be exported. No indices means all songs
+//!     <PATH>       The path to the save file to export from
+//!     <SONG>...    Indices, names or glob patterns (with * and ?) of the songs that should be exported. No songs means all songs
 //!
 //! OPTIONS:
-//!     -d, --decimal            Use decimal version numbers, instead of hexadecimal
-//!     -h, --help               Print help information
-//!     -o, --output <OUTPUT>    The destination folder to place the songs
-//!     -p, --output-pos         Prepend the song position to the start of the filename
-//!     -v, --output-version     Append the song version to the end of the filename
-//!     -V, --version            Print version information
+//!     -d, --decimal                Use decimal version numbers, instead of hexadecimal
+//!     -h, --help                   Print help information
+//!     -i, --index-only             Only select songs by index, even if a song is named like a number
+//!     -m, --manifest <MANIFEST>    Write a JSON manifest of the exported songs and errors to this path [aliases: json]
+//!     -n, --normalize              Recompress the songs, instead of copying the compressed blocks as-is
+//!     -o, --output <OUTPUT>        The destination folder to place the songs
+//!     -p, --output-pos             Prepend the song position to the start of the filename
+//!     -q, --quiet                  Don't print a line for every exported song
+//!     -v, --output-version         Append the song version to the end of the filename
+//!     -V, --version                Print version information
+//!     -w, --working-memory         Also export the song in working memory (prefixed with WM_)
 //! ```
 //!
 //! ### Example
@@ -81,20 +100,30 @@
 //! 12. HONEY    => 12_HONEY_v1F.lsdsng
 //! ```
 //!
+//! ```console
+//! 4ntler@mbp > lsdj-tools export bangers.sav honey "DNT*"
+//! 11. DNTSWEAT => DNTSWEAT.lsdsng
+//! 12. HONEY    => HONEY.lsdsng
+//! ```
+//!
 //! ## Import
 //!
 //! Import .lsdsng's into a .sav file
 //!
 //! ```console
 //! USAGE:
-//!     lsdj-tools import --output <OUTPUT> [SONG]...
+//!     lsdj-tools import [OPTIONS] --output <OUTPUT> [SONG]...
 //!
 //! ARGS:
 //!     <SONG>...    Paths to the songs that should be imported into a save
 //!
 //! OPTIONS:
+//!     -b, --base <BASE>        An existing save file to import into, instead of starting from an empty one
+//!     -d, --dedupe[=<DEDUPE>]  Skip songs that are already in the save, comparing by contents (the default) or name [possible values: content, name]
+//!     -f, --force              Replace songs in slots that are already occupied
 //!     -h, --help               Print help information
 //!     -o, --output <OUTPUT>    The output path
+//!     -s, --slot <SLOT>        The slot to place each song in, in order. Songs without a slot go into the first free one
 //!     -V, --version            Print version information
 //! ```
 //!
@@ -106,8 +135,224 @@
 //! 01 => banger2.lsdsng
 //! Wrote test.sav
 //! ```
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools import ./exports -b bangers.sav --dedupe -o ./test.sav
+//! -- => exports/HONEY.lsdsng (skipped, same contents as 12. HONEY)
+//! 13 => exports/FRESH.lsdsng
+//! Wrote test.sav
+//! ```
+//!
+//! ## Diff
+//!
+//! Compare two .lsdsng's or .sav's and show what differs. Songs are compared per region of memory,
+//! save files per file slot. Exits with status 1 if there are any differences, so it can be used in
+//! scripts.
+//!
+//! ```console
+//! USAGE:
+//!     lsdj-tools diff [OPTIONS] <A> <B>
+//!
+//! ARGS:
+//!     <A>    The path to the first song or save file
+//!     <B>    The path to the second song or save file
+//!
+//! OPTIONS:
+//!     -h, --help       Print help information
+//!     -j, --json       Print the differences as JSON
+//!     -V, --version    Print version information
+//! ```
+//!
+//! ### Example
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools diff HONEY_v1E.lsdsng HONEY_v1F.lsdsng
+//! Chains       3 bytes differ
+//! Phrases      41 bytes differ
+//!
+//! 4ntler@mbp > lsdj-tools diff backup.sav cart.sav
+//! 03 Changed HONEY
+//! 03 Bumped HONEY from v030 to v031
+//! 07 Added BANGER
+//! WM Changed
+//! ```
+//!
+//! ## Remove
+//!
+//! Remove songs from a .sav file
+//!
+//! ```console
+//! USAGE:
+//!     lsdj-tools remove [OPTIONS] --output <OUTPUT> <PATH> <INDEX>...
+//!
+//! ARGS:
+//!     <PATH>        The path to the save file to remove songs from
+//!     <INDEX>...    Indices of the songs that should be removed
+//!
+//! OPTIONS:
+//!     -h, --help               Print help information
+//!     -i, --ignore-missing     Skip indices that don't contain a song, instead of aborting
+//!     -o, --output <OUTPUT>    The output path
+//!     -V, --version            Print version information
+//! ```
+//!
+//! ### Example
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools remove bangers.sav 3 12 -o ./cleaned.sav
+//! 03. FUNGAL   => freed 11 blocks
+//! 12. HONEY    => freed 14 blocks
+//! Wrote cleaned.sav
+//! ```
+//!
+//! ## Rename
+//!
+//! Rename songs or set their version in a .sav file, without recompressing them
+//!
+//! ```console
+//! USAGE:
+//!     lsdj-tools rename [OPTIONS] --output <OUTPUT> <PATH> <INDEX>
+//!
+//! ARGS:
+//!     <PATH>     The path to the save file containing the song
+//!     <INDEX>    Index of the song that should be renamed
+//!
+//! OPTIONS:
+//!     -h, --help                           Print help information
+//!     -n, --name <NAME>                    The new name of the song
+//!     -o, --output <OUTPUT>                The output path
+//!     -s, --set-version <SET_VERSION>      The new version of the song
+//!     -V, --version                        Print version information
+//! ```
+//!
+//! ### Example
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools rename bangers.sav 3 -n FUNGI -o ./renamed.sav
+//! 03. FUNGAL   => FUNGI v13
+//! Wrote renamed.sav
+//! ```
+//!
+//! ## Explode
+//!
+//! Explode a .sav file into a folder of .lsdsng's and a manifest
+//!
+//! ```console
+//! USAGE:
+//!     lsdj-tools explode --output <OUTPUT> <PATH>
+//!
+//! ARGS:
+//!     <PATH>    The path to the save file to explode
+//!
+//! OPTIONS:
+//!     -h, --help               Print help information
+//!     -o, --output <OUTPUT>    The destination folder to place the songs and manifest
+//!     -V, --version            Print version information
+//! ```
+//!
+//! ### Example
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools explode bangers.sav -o ./bangers
+//! 00 => 00_YOKAI.lsdsng
+//! 01 => 01_ASPHALT.lsdsng
+//! 02 => 02_NEWSHOES.lsdsng
+//! WM => working_memory.raw
+//! Wrote ./bangers
+//! ```
+//!
+//! ## Assemble
+//!
+//! Assemble a .sav file from a folder made by explode
+//!
+//! ```console
+//! USAGE:
+//!     lsdj-tools assemble --output <OUTPUT> <PATH>
+//!
+//! ARGS:
+//!     <PATH>    The folder containing the manifest and songs
+//!
+//! OPTIONS:
+//!     -h, --help               Print help information
+//!     -o, --output <OUTPUT>    The output path
+//!     -V, --version            Print version information
+//! ```
+//!
+//! ### Example
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools assemble ./bangers -o bangers.sav
+//! Wrote bangers.sav
+//! ```
+//!
+//! ## Split
+//!
+//! Split a .sav file into a .sav file per song
+//!
+//! ```console
+//! USAGE:
+//!     lsdj-tools split [OPTIONS] <PATH>
+//!
+//! ARGS:
+//!     <PATH>    The path to the save file to split
+//!
+//! OPTIONS:
+//!     -h, --help               Print help information
+//!     -o, --output <OUTPUT>    The destination folder to place the save files
+//!     -V, --version            Print version information
+//! ```
+//!
+//! Every song ends up in slot 0 of its own save file, and is loaded into working memory as well.
+//!
+//! ### Example
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools split bangers.sav -o ./shared
+//! 00. YOKAI    => 00_YOKAI.sav
+//! 01. ASPHALT  => 01_ASPHALT.sav
+//! 02. NEWSHOES => 02_NEWSHOES.sav
+//! ```
+//!
+//! ## Merge
+//!
+//! Merge the songs of multiple .sav files into one
+//!
+//! ```console
+//! USAGE:
+//!     lsdj-tools merge [OPTIONS] --output <OUTPUT> <PATH> <PATH>...
+//!
+//! ARGS:
+//!     <PATH> <PATH>...    The paths to the save files to merge
+//!
+//! OPTIONS:
+//!     -c, --conflict <CONFLICT>    What to do with songs that have the same name, but different contents [default: highest] [possible values: highest, both, error]
+//!     -h, --help                   Print help information
+//!     -o, --output <OUTPUT>        The output path
+//!     -s, --skip-largest           Leave out the largest songs when not everything fits, instead of aborting
+//!     -V, --version                Print version information
+//! ```
+//!
+//! Songs with the same name and contents are only stored once. The working memory songs aren't merged.
+//!
+//! ### Example
+//!
+//! ```console
+//! 4ntler@mbp > lsdj-tools merge bangers.sav jams.sav --conflict both -o ./merged.sav
+//! bangers.sav: 00. YOKAI    => 00
+//! bangers.sav: 01. HONEY    => 01
+//! jams.sav: 00. HONEY    => 02 as HONEY2
+//! jams.sav: 01. YOKAI    => left out, duplicate
+//! Wrote merged.sav
+//! ```
 
+pub mod assemble;
+pub mod diff;
+pub mod explode;
 pub mod export;
 pub mod import;
 pub mod inspect;
+pub mod merge;
+pub mod remove;
+pub mod rename;
+pub mod split;
 pub(crate) mod utils;
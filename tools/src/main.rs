@@ -1,9 +1,16 @@
 use anyhow::Result;
 use clap::Parser;
 
+use lsdj_tools::assemble::{assemble, AssembleArgs};
+use lsdj_tools::diff::{diff, DiffArgs, Outcome};
+use lsdj_tools::explode::{explode, ExplodeArgs};
 use lsdj_tools::export::{export, ExportArgs};
 use lsdj_tools::import::{import, ImportArgs};
 use lsdj_tools::inspect::{inspect, InspectArgs};
+use lsdj_tools::merge::{merge, MergeArgs};
+use lsdj_tools::remove::{remove, RemoveArgs};
+use lsdj_tools::rename::{rename, RenameArgs};
+use lsdj_tools::split::{split, SplitArgs};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -11,6 +18,13 @@ enum Cli {
     Inspect(InspectArgs),
     Export(ExportArgs),
     Import(ImportArgs),
+    Merge(MergeArgs),
+    Diff(DiffArgs),
+    Remove(RemoveArgs),
+    Rename(RenameArgs),
+    Explode(ExplodeArgs),
+    Assemble(AssembleArgs),
+    Split(SplitArgs),
 }
 
 fn main() -> Result<()> {
@@ -18,5 +32,20 @@ fn main() -> Result<()> {
         Cli::Inspect(args) => inspect(&args),
         Cli::Export(args) => export(args),
         Cli::Import(args) => import(args),
+        Cli::Merge(args) => merge(args),
+        Cli::Diff(args) => match diff(&args) {
+            Ok(Outcome::Identical) => Ok(()),
+            Ok(outcome) => std::process::exit(outcome.exit_code()),
+            Err(error) => {
+                // Report the error like returning it would, but with diff(1)'s status
+                eprintln!("Error: {error:?}");
+                std::process::exit(2);
+            }
+        },
+        Cli::Remove(args) => remove(args),
+        Cli::Rename(args) => rename(args),
+        Cli::Explode(args) => explode(args),
+        Cli::Assemble(args) => assemble(args),
+        Cli::Split(args) => split(args),
     }
 }
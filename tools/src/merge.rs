@@ -0,0 +1,102 @@
+//! The `merge` subcommand
+
+use crate::utils::check_for_overwrite;
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use lsdj::sram::{
+    merge::{self, MergeOutcome, Overflow},
+    MergePolicy, SRam,
+};
+use std::path::PathBuf;
+
+/// Arguments for the `merge` subcommand
+#[derive(Args)]
+#[clap(author, version, about = "Merge the songs of multiple .sav files into one", long_about = None)]
+pub struct MergeArgs {
+    /// The paths to the save files to merge
+    #[clap(required = true, num_args = 2..)]
+    path: Vec<PathBuf>,
+
+    /// What to do with songs that have the same name, but different contents
+    #[clap(short, long, value_enum, default_value_t = Conflict::Highest)]
+    conflict: Conflict,
+
+    /// Leave out the largest songs when not everything fits, instead of aborting
+    #[clap(short, long)]
+    skip_largest: bool,
+
+    /// The output path
+    #[clap(short, long)]
+    output: PathBuf,
+}
+
+/// What [`merge()`] does with songs that have the same name, but different contents
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Conflict {
+    /// Keep the song with the highest version
+    Highest,
+
+    /// Keep both songs, adding a number to the name of the later one
+    Both,
+
+    /// Abort the merge
+    Error,
+}
+
+/// Merge the songs of multiple .sav files into one
+pub fn merge(args: MergeArgs) -> Result<()> {
+    let srams = args
+        .path
+        .iter()
+        .map(|path| {
+            SRam::from_path(path)
+                .map(Box::new)
+                .context(format!("Reading {} failed", path.to_string_lossy()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let policy = MergePolicy {
+        conflict: match args.conflict {
+            Conflict::Highest => merge::Conflict::KeepHighestVersion,
+            Conflict::Both => merge::Conflict::KeepBoth,
+            Conflict::Error => merge::Conflict::Error,
+        },
+        overflow: if args.skip_largest {
+            Overflow::SkipLargest
+        } else {
+            Overflow::Fail
+        },
+    };
+
+    let srams: Vec<&SRam> = srams.iter().map(AsRef::as_ref).collect();
+    let merged = merge::merge(&srams, policy).context("Merging the save files failed")?;
+
+    for song in &merged.songs {
+        let outcome = match &song.outcome {
+            MergeOutcome::Inserted { slot } => format!("{slot:02}"),
+            MergeOutcome::Renamed { slot, name } => format!("{slot:02} as {name}"),
+            MergeOutcome::Duplicate => "left out, duplicate".to_string(),
+            MergeOutcome::Superseded => "left out, newer version kept".to_string(),
+            MergeOutcome::Skipped => "left out, doesn't fit".to_string(),
+        };
+
+        println!(
+            "{}: {:02}. {:8} => {}",
+            args.path[song.source].to_string_lossy(),
+            song.slot,
+            song.name,
+            outcome
+        );
+    }
+
+    if check_for_overwrite(&args.output)? {
+        merged.sram.to_path(&args.output).context(format!(
+            "Could not write SRAM to {}",
+            args.output.to_string_lossy()
+        ))?;
+
+        println!("Wrote {}", args.output.to_string_lossy());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! The `remove` subcommand
+
+use crate::utils::check_for_overwrite;
+use anyhow::{Context, Error, Result};
+use clap::Args;
+use lsdj::{
+    fs::{File, Filesystem, Index},
+    sram::SRam,
+};
+use std::path::PathBuf;
+
+/// Arguments for the `remove` subcommand
+#[derive(Args)]
+#[clap(author, version, about = "Remove songs from a .sav file", long_about = None)]
+pub struct RemoveArgs {
+    /// The path to the save file to remove songs from
+    path: PathBuf,
+
+    /// Indices of the songs that should be removed
+    #[clap(required = true)]
+    index: Vec<usize>,
+
+    /// The output path
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// Skip indices that don't contain a song, instead of aborting
+    #[clap(short, long)]
+    ignore_missing: bool,
+}
+
+/// Remove songs from a .sav file
+pub fn remove(args: RemoveArgs) -> Result<()> {
+    let mut sram = SRam::from_path(&args.path).context("Reading the SRAM from file failed")?;
+
+    for index in &args.index {
+        if *index >= Filesystem::FILES_CAPACITY {
+            return Err(Error::msg(format!(
+                "Index {index} is out of range, there are only {} slots",
+                Filesystem::FILES_CAPACITY
+            )));
+        }
+    }
+
+    for index in &args.index {
+        let file = Index::new(*index as u8);
+
+        let blocks = match sram.filesystem.file_block_count(file) {
+            Some(blocks) => blocks,
+            None if args.ignore_missing => continue,
+            None => {
+                return Err(Error::msg(format!(
+                    "There is no song at index {index} (use --ignore-missing to skip it)"
+                )))
+            }
+        };
+
+        if let Some(lsdsng) = sram.filesystem.remove_file(file) {
+            println!(
+                "{:02}. {:8} => freed {} blocks",
+                index,
+                lsdsng.name()?,
+                blocks
+            );
+        }
+    }
+
+    if check_for_overwrite(&args.output)? {
+        sram.to_path(&args.output).context(format!(
+            "Could not write SRAM to {}",
+            args.output.to_string_lossy()
+        ))?;
+
+        println!("Wrote {}", args.output.to_string_lossy());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! The `rename` subcommand
+
+use crate::utils::check_for_overwrite;
+use anyhow::{Context, Error, Result};
+use clap::Args;
+use lsdj::{
+    fs::{File, Filesystem, Index},
+    name::Name,
+    sram::SRam,
+};
+use std::path::PathBuf;
+
+/// Arguments for the `rename` subcommand
+#[derive(Args)]
+#[clap(author, version, about = "Rename songs or set their version in a .sav file, without recompressing them", long_about = None)]
+pub struct RenameArgs {
+    /// The path to the save file containing the song
+    path: PathBuf,
+
+    /// Index of the song that should be renamed
+    index: usize,
+
+    /// The new name of the song
+    #[clap(short, long, required_unless_present = "set_version")]
+    name: Option<String>,
+
+    /// The new version of the song
+    #[clap(short, long)]
+    set_version: Option<u8>,
+
+    /// The output path
+    #[clap(short, long)]
+    output: PathBuf,
+}
+
+/// Rename songs or set their version in a .sav file, without recompressing them
+pub fn rename(args: RenameArgs) -> Result<()> {
+    let mut sram = SRam::from_path(&args.path).context("Reading the SRAM from file failed")?;
+
+    if args.index >= Filesystem::FILES_CAPACITY {
+        return Err(Error::msg(format!(
+            "Index {} is out of range, there are only {} slots",
+            args.index,
+            Filesystem::FILES_CAPACITY
+        )));
+    }
+
+    let name = args
+        .name
+        .as_deref()
+        .map(Name::<8>::try_from)
+        .transpose()
+        .context("The new name is not a valid song name")?;
+
+    let mut file = sram
+        .filesystem
+        .file_mut(Index::new(args.index as u8))
+        .ok_or_else(|| Error::msg(format!("There is no song at index {}", args.index)))?;
+    let old = file.name()?;
+
+    if let Some(name) = &name {
+        file.set_name(name);
+    }
+    if let Some(version) = args.set_version {
+        file.set_version(version);
+    }
+
+    println!(
+        "{:02}. {:8} => {} v{:02X}",
+        args.index,
+        old,
+        file.name()?,
+        file.version()
+    );
+
+    if check_for_overwrite(&args.output)? {
+        sram.to_path(&args.output).context(format!(
+            "Could not write SRAM to {}",
+            args.output.to_string_lossy()
+        ))?;
+
+        println!("Wrote {}", args.output.to_string_lossy());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! The `split` subcommand
+
+use crate::utils::check_for_overwrite;
+use anyhow::{Context, Result};
+use clap::Args;
+use lsdj::{
+    fs::{File, Index},
+    lsdsng::LsdSng,
+    sram::SRam,
+};
+use std::{env::current_dir, fs::create_dir_all, path::PathBuf};
+
+/// Arguments for the `split` subcommand
+#[derive(Args)]
+#[clap(author, version, about = "Split a .sav file into a .sav file per song", long_about = None)]
+pub struct SplitArgs {
+    /// The path to the save file to split
+    path: PathBuf,
+
+    /// The destination folder to place the save files
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Split a .sav file into a .sav file per song
+pub fn split(args: SplitArgs) -> Result<()> {
+    let sram = SRam::from_path(&args.path).context("Reading the SRAM from file failed")?;
+
+    let folder = match &args.output {
+        Some(folder) => folder.clone(),
+        None => current_dir().context("Could not fetch current working directory")?,
+    };
+    create_dir_all(&folder).context("Could not create output directory")?;
+
+    for (slot, file) in sram.filesystem.occupied_files() {
+        let slot = u8::from(slot);
+        let lsdsng = file
+            .lsdsng()
+            .context("Could not create an LsdSng from an SRAM file slot")?;
+        let name = lsdsng.name()?;
+
+        let filename = format!("{slot:02}_{}.sav", name.as_str());
+        let path = folder.join(&filename);
+        if check_for_overwrite(&path)? {
+            split_song(&lsdsng)
+                .context(format!("Could not split off the song in slot {slot}"))?
+                .to_path(&path)
+                .context(format!("Could not write {filename}"))?;
+
+            println!("{slot:02}. {name:8} => {filename}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Create a fresh [`SRam`] containing just a single song
+///
+/// The song is stored in slot 0 and loaded into working memory, so LSDJ opens it straight away.
+pub fn split_song(lsdsng: &LsdSng) -> Result<SRam> {
+    let index = Index::new(0);
+
+    let mut sram = SRam::new();
+    sram.insert_lsdsng_at(index, lsdsng)
+        .context("Could not insert the song")?;
+    sram.load_to_working_memory(index)
+        .context("Could not load the song into working memory")?;
+
+    Ok(sram)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsdj::song::SongMemory;
+
+    #[test]
+    fn split_song() {
+        let mut song = SongMemory::new();
+        song.as_mut_slice()[0x100] = 0x42;
+        let lsdsng = LsdSng::from_song("HONEY".try_into().unwrap(), 7, &song).unwrap();
+
+        let sram = super::split_song(&lsdsng).unwrap();
+        assert_eq!(sram.filesystem.active_file(), Some(Index::new(0)));
+        assert_eq!(sram.filesystem.occupied_files().len(), 1);
+        assert_eq!(sram.working_memory_song.as_slice(), song.as_slice());
+
+        let file = sram.filesystem.file(Index::new(0)).unwrap();
+        assert_eq!(file.name(), lsdsng.name());
+        assert_eq!(file.version(), 7);
+        assert_eq!(file.decompress().unwrap().as_slice(), song.as_slice());
+    }
+}
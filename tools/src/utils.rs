@@ -1,4 +1,6 @@
 use anyhow::{Context, Result};
+use lsdj::song::SongMemory;
+use sha2::{Digest, Sha256};
 use std::{io::stdin, path::Path};
 use walkdir::{DirEntry, WalkDir};
 
@@ -64,3 +66,18 @@ pub fn check_for_overwrite(path: &Path) -> Result<bool> {
         Ok(true)
     }
 }
+
+/// Hash a song with [`SongMemory::content_hash()`], as a lowercase hexadecimal string
+pub fn content_hash(song: &SongMemory) -> String {
+    hex(&song.content_hash())
+}
+
+/// Hash bytes exactly as they are with SHA-256, as a lowercase hexadecimal string
+pub fn sha256(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+/// Format bytes as a lowercase hexadecimal string
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}